@@ -1,16 +1,14 @@
-use libc_x::co_crate;
+use libc_x::{run, spawn};
 
 fn main() {
-    let _ = co_crate(
-        2048,
-        |param| {
-            println!("Hello, world!");
-            param
-        },
-        None,
-    );
-    //这里调用的实际上是被hook的实现，不是原始系统函数
-    unsafe {
-        libc::sleep(1);
-    }
+    let handle = spawn(|| {
+        //这里调用的实际上是被hook的实现，不是原始系统函数，只会挂起协程
+        unsafe {
+            libc::sleep(1);
+        }
+        println!("Hello, world!");
+    })
+    .unwrap();
+    run();
+    handle.join().unwrap();
 }
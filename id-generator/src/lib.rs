@@ -1,33 +1,294 @@
 use once_cell::sync::Lazy;
+use std::cell::Cell;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
 use std::sync::RwLock;
 
-pub struct IdGenerator {}
+/*
+无锁的id分配：
+IdGenerator是单调递增的计数器，可以声明为static，每个key一个；
+IdPool用位图管理有限的id空间，释放的id可以被重新分配；
+SchedulerId和CoroutineId是带类型的id，CoroutineId的高位是所属调度器的id，
+CoroutineId在所有平台上都是64位的，32位平台上也不会很快回绕；
+调度器id由可回收的槽位和槽位被回收的次数(generation)组成，
+销毁的调度器的id要等generation回绕之后才会再次出现
+ */
 
-static mut ID_MAP: Lazy<RwLock<HashMap<&str, AtomicUsize>>> =
-    Lazy::new(|| RwLock::new(HashMap::new()));
+/// 单调递增的id，从1开始，0保留表示没有id
+#[derive(Debug)]
+pub struct IdGenerator {
+    next: AtomicUsize,
+}
+
+static ID_MAP: Lazy<RwLock<HashMap<&str, IdGenerator>>> = Lazy::new(|| RwLock::new(HashMap::new()));
 
 impl IdGenerator {
+    pub const fn new() -> Self {
+        IdGenerator {
+            next: AtomicUsize::new(1),
+        }
+    }
+
+    pub fn next(&self) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// 分配count个连续的id，返回第一个
+    pub fn next_batch(&self, count: usize) -> usize {
+        self.next.fetch_add(count, Ordering::Relaxed)
+    }
+
+    /// 按key分配id，只有第一次使用key时才加写锁，
+    /// 频繁分配的id应该使用static的IdGenerator
     pub fn next_id(key: &'static str) -> usize {
-        unsafe {
-            match ID_MAP.write() {
-                Ok(mut map) => match map.get_mut(key) {
-                    Some(id) => id.fetch_add(1, Ordering::SeqCst),
-                    None => {
-                        map.insert(key, AtomicUsize::new(1));
-                        map.get_mut(key).unwrap().fetch_add(1, Ordering::SeqCst)
+        if let Some(generator) = ID_MAP.read().unwrap_or_else(|e| e.into_inner()).get(key) {
+            return generator.next();
+        }
+        ID_MAP
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(key)
+            .or_default()
+            .next()
+    }
+}
+
+impl Default for IdGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const BITS: usize = u64::BITS as usize;
+
+/// 可回收的id，范围是[1, capacity]，分配和释放都是无锁的
+pub struct IdPool {
+    //第i位为1表示id i+1已经被分配，最后一个字中超出capacity的位初始化为1
+    bits: Box<[AtomicU64]>,
+    capacity: usize,
+    //上次分配或释放的字，下次从这里开始找
+    hint: AtomicUsize,
+}
+
+impl IdPool {
+    pub fn new(capacity: usize) -> Self {
+        let words = capacity.div_ceil(BITS);
+        let bits = (0..words)
+            .map(|word| {
+                let valid = capacity - word * BITS;
+                match valid >= BITS {
+                    true => AtomicU64::new(0),
+                    false => AtomicU64::new(u64::MAX << valid),
+                }
+            })
+            .collect();
+        IdPool {
+            bits,
+            capacity,
+            hint: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// 分配一个id，id用完时返回None
+    pub fn acquire(&self) -> Option<usize> {
+        let words = self.bits.len();
+        let start = self.hint.load(Ordering::Relaxed);
+        for i in 0..words {
+            let word = (start + i) % words;
+            let bits = &self.bits[word];
+            let mut current = bits.load(Ordering::Relaxed);
+            while current != u64::MAX {
+                let bit = (!current).trailing_zeros() as usize;
+                match bits.compare_exchange_weak(
+                    current,
+                    current | (1 << bit),
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        self.hint.store(word, Ordering::Relaxed);
+                        return Some(word * BITS + bit + 1);
                     }
-                },
-                Err(_) => IdGenerator::next_id(key),
+                    Err(bits) => current = bits,
+                }
             }
         }
+        None
+    }
+
+    /// 释放id，之后可以被重新分配，释放没有分配的id会panic
+    pub fn release(&self, id: usize) {
+        assert!(id > 0 && id <= self.capacity, "id {} out of range !", id);
+        let (word, bit) = ((id - 1) / BITS, (id - 1) % BITS);
+        let previous = self.bits[word].fetch_and(!(1 << bit), Ordering::Release);
+        assert_ne!(0, previous & (1 << bit), "id {} not acquired !", id);
+        self.hint.store(word, Ordering::Relaxed);
+    }
+}
+
+impl Debug for IdPool {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IdPool")
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+//调度器槽位占的位数
+const SLOT_BITS: u32 = 16;
+
+const SLOT_MASK: usize = (1 << SLOT_BITS) - 1;
+
+//generation占的位数
+const GENERATION_BITS: u32 = 8;
+
+const GENERATION_MASK: usize = (1 << GENERATION_BITS) - 1;
+
+//CoroutineId中调度器id占的位数，调度器id在32位平台上也放得下
+const SCHEDULER_BITS: u32 = SLOT_BITS + GENERATION_BITS;
+
+const SEQUENCE_BITS: u32 = u64::BITS - SCHEDULER_BITS;
+
+const SEQUENCE_MASK: u64 = (1 << SEQUENCE_BITS) - 1;
+
+//调度器槽位是可回收的，同时存在的调度器不能超过这个数量
+static SCHEDULER_IDS: Lazy<IdPool> = Lazy::new(|| IdPool::new(SLOT_MASK));
+
+//每个槽位被回收的次数，只用低GENERATION_BITS位
+static GENERATIONS: Lazy<Box<[AtomicU8]>> =
+    Lazy::new(|| (0..=SLOT_MASK).map(|_| AtomicU8::new(0)).collect());
+
+/// 调度器id，调度器销毁时通过[`SchedulerId::release`]归还；
+/// 槽位被新的调度器复用时generation不同，所以id也不同，但generation会回绕，
+/// 从CoroutineId解出来的调度器id只能用来识别，不保证调度器还活着
+#[repr(transparent)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Default)]
+pub struct SchedulerId(usize);
+
+impl SchedulerId {
+    /// 不属于任何调度器
+    pub const NONE: SchedulerId = SchedulerId(0);
+
+    /// 分配新的调度器id，同时存在的调度器太多时panic
+    pub fn next() -> Self {
+        let slot = SCHEDULER_IDS.acquire().expect("too many schedulers !");
+        let generation = GENERATIONS[slot].load(Ordering::Acquire) as usize & GENERATION_MASK;
+        SchedulerId((generation << SLOT_BITS) | slot)
+    }
+
+    pub fn release(self) {
+        if self != SchedulerId::NONE {
+            //先增加generation再归还槽位，下一个拿到槽位的调度器一定看到新的generation
+            GENERATIONS[self.slot()].fetch_add(1, Ordering::Release);
+            SCHEDULER_IDS.release(self.slot());
+        }
+    }
+
+    /// 可回收的槽位，同时存在的调度器的槽位不同
+    pub fn slot(self) -> usize {
+        self.0 & SLOT_MASK
+    }
+
+    /// 槽位被回收的次数，会回绕
+    pub fn generation(self) -> usize {
+        self.0 >> SLOT_BITS
+    }
+
+    pub fn as_usize(self) -> usize {
+        self.0
+    }
+}
+
+impl Debug for SchedulerId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SchedulerId({}.{})", self.slot(), self.generation())
+    }
+}
+
+impl Display for SchedulerId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.slot(), self.generation())
+    }
+}
+
+//每个线程一次从全局计数器取一批序号，减少竞争
+const SEQUENCE_BATCH: u64 = 1024;
+
+//和IdGenerator一样从1开始，但在32位平台上也是64位的
+static SEQUENCES: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    //[下一个序号, 这一批的结尾)
+    static SEQUENCE: Cell<(u64, u64)> = const { Cell::new((0, 0)) };
+}
+
+fn next_sequence() -> u64 {
+    SEQUENCE.with(|cell| {
+        let (mut next, mut end) = cell.get();
+        if next == end {
+            next = SEQUENCES.fetch_add(SEQUENCE_BATCH, Ordering::Relaxed);
+            end = next + SEQUENCE_BATCH;
+        }
+        cell.set((next + 1, end));
+        next
+    })
+}
+
+/// 协程id，高位是创建时所属的调度器id，低位是全局唯一的序号，
+/// 有栈协程和无栈任务共用
+#[repr(transparent)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct CoroutineId(u64);
+
+impl CoroutineId {
+    pub fn next(scheduler: SchedulerId) -> Self {
+        let sequence = next_sequence() & SEQUENCE_MASK;
+        CoroutineId(((scheduler.0 as u64) << SEQUENCE_BITS) | sequence)
+    }
+
+    pub fn scheduler(self) -> SchedulerId {
+        SchedulerId((self.0 >> SEQUENCE_BITS) as usize)
+    }
+
+    pub fn sequence(self) -> u64 {
+        self.0 & SEQUENCE_MASK
+    }
+
+    /// 通过FFI传递的原始值，可以通过[`CoroutineId::from_u64`]还原
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    pub fn from_u64(id: u64) -> Self {
+        CoroutineId(id)
+    }
+}
+
+impl Debug for CoroutineId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CoroutineId({}-{})", self.scheduler(), self.sequence())
+    }
+}
+
+impl Display for CoroutineId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.scheduler(), self.sequence())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::IdGenerator;
+    use crate::{
+        CoroutineId, IdGenerator, IdPool, SchedulerId, GENERATION_MASK, SEQUENCE_BITS,
+        SEQUENCE_MASK, SLOT_BITS, SLOT_MASK,
+    };
+    use std::collections::HashSet;
+    use std::sync::Arc;
 
     #[test]
     fn test() {
@@ -35,5 +296,113 @@ mod tests {
         assert_eq!(1, IdGenerator::next_id(key));
         assert_eq!(2, IdGenerator::next_id(key));
         assert_eq!(3, IdGenerator::next_id(key));
+
+        static IDS: IdGenerator = IdGenerator::new();
+        assert_eq!(1, IDS.next());
+        assert_eq!(2, IDS.next_batch(10));
+        assert_eq!(12, IDS.next());
+    }
+
+    #[test]
+    fn test_pool() {
+        let pool = IdPool::new(70);
+        let ids: Vec<usize> = (0..70).map(|_| pool.acquire().unwrap()).collect();
+        assert!((1..=70).eq(ids.iter().copied()));
+        assert_eq!(None, pool.acquire());
+        pool.release(65);
+        pool.release(3);
+        let mut recycled = vec![pool.acquire().unwrap(), pool.acquire().unwrap()];
+        recycled.sort();
+        assert_eq!(vec![3, 65], recycled);
+        assert_eq!(None, pool.acquire());
+    }
+
+    #[test]
+    #[should_panic(expected = "id 5 not acquired !")]
+    fn test_pool_release_twice() {
+        let pool = IdPool::new(8);
+        for _ in 0..5 {
+            pool.acquire();
+        }
+        pool.release(5);
+        pool.release(5);
+    }
+
+    #[test]
+    fn test_pool_threads() {
+        let pool = Arc::new(IdPool::new(256));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let pool = pool.clone();
+                std::thread::spawn(move || {
+                    let mut ids = Vec::new();
+                    for _ in 0..1000 {
+                        let id = pool.acquire().unwrap();
+                        ids.push(id);
+                        if ids.len() == 32 {
+                            ids.drain(..).for_each(|id| pool.release(id));
+                        }
+                    }
+                    ids
+                })
+            })
+            .collect();
+        let held: Vec<usize> = threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect();
+        //同时持有的id不会重复
+        assert_eq!(held.len(), held.iter().collect::<HashSet<_>>().len());
+    }
+
+    #[test]
+    fn test_coroutine_id() {
+        let scheduler = SchedulerId::next();
+        let first = CoroutineId::next(scheduler);
+        let second = CoroutineId::next(scheduler);
+        assert_ne!(first, second);
+        assert_eq!(scheduler, first.scheduler());
+        assert_eq!(first.sequence() + 1, second.sequence());
+        assert_eq!(first, CoroutineId::from_u64(first.as_u64()));
+        assert_eq!(
+            format!("{}-{}", scheduler, first.sequence()),
+            first.to_string()
+        );
+        let other = std::thread::spawn(move || CoroutineId::next(SchedulerId::NONE))
+            .join()
+            .unwrap();
+        assert_eq!(SchedulerId::NONE, other.scheduler());
+        assert_ne!(first.sequence(), other.sequence());
+        scheduler.release();
+    }
+
+    #[test]
+    fn test_scheduler_generation() {
+        let first = SchedulerId::next();
+        first.release();
+        let second = SchedulerId::next();
+        //复用的槽位generation不同，销毁的调度器的id不会马上被复用
+        assert_ne!(first, second);
+        if first.slot() == second.slot() {
+            assert_ne!(first.generation(), second.generation());
+        }
+        let id = CoroutineId::next(second);
+        assert_eq!(second, id.scheduler());
+        assert_eq!(second.generation(), id.scheduler().generation());
+        second.release();
+    }
+
+    #[test]
+    fn test_coroutine_id_bits() {
+        //和平台的指针宽度无关
+        assert_eq!(16, SLOT_BITS);
+        assert_eq!(40, SEQUENCE_BITS);
+        let scheduler = SchedulerId((GENERATION_MASK << SLOT_BITS) | SLOT_MASK);
+        let id = CoroutineId::next(scheduler);
+        assert_eq!(scheduler, id.scheduler());
+        assert_eq!(
+            SEQUENCE_MASK,
+            CoroutineId::from_u64(id.as_u64() | SEQUENCE_MASK).sequence()
+        );
     }
 }
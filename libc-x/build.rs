@@ -1,4 +1,38 @@
+use std::env;
+#[cfg(feature = "bindgen")]
+use std::path::{Path, PathBuf};
+
 fn main() {
+    //安装的libhook.so所在目录，见README；没有设置时使用同一个workspace里一起构建的libhook.so
+    println!("cargo:rerun-if-env-changed=OPEN_COROUTINE_LIB_DIR");
+    let lib_dir = env::var("OPEN_COROUTINE_LIB_DIR")
+        .or_else(|_| env::var("DEP_HOOK_LIB_DIR"))
+        .expect("libhook must be a dependency !");
+    println!("cargo:rustc-link-search=native={}", lib_dir);
     //link hook dylib
     println!("cargo:rustc-link-lib=dylib=hook");
+    //从libhook构建时生成在它的OUT_DIR下的头文件重新生成绑定
+    #[cfg(feature = "bindgen")]
+    generate(
+        &PathBuf::from(env::var("DEP_HOOK_INCLUDE").expect("libhook must be a dependency !"))
+            .join("open_coroutine.h"),
+    );
+}
+
+/// 从libhook生成的头文件生成OUT_DIR/bindings.rs，开启bindgen feature时代替src/bindings.rs
+#[cfg(feature = "bindgen")]
+fn generate(header: &Path) {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    println!("cargo:rerun-if-changed={}", header.display());
+    bindgen::Builder::default()
+        .header(header.to_str().unwrap())
+        .allowlist_function("co_.*|set_hook_enabled|is_hook_enabled|in_coroutine")
+        .allowlist_type("co_.*")
+        .allowlist_var("OPEN_COROUTINE_.*")
+        //不同平台的定义不同，使用libc里的
+        .blocklist_type("pthread_key_t")
+        .generate()
+        .expect("generate bindings failed !")
+        .write_to_file(out_dir.join("bindings.rs"))
+        .expect("write bindings failed !");
 }
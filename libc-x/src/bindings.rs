@@ -0,0 +1,207 @@
+/* automatically generated by rust-bindgen 0.69.5 */
+
+pub const OPEN_COROUTINE_VERSION_MAJOR: u32 = 0;
+pub const OPEN_COROUTINE_VERSION_MINOR: u32 = 1;
+pub const OPEN_COROUTINE_VERSION_PATCH: u32 = 0;
+pub const OPEN_COROUTINE_VERSION: &[u8; 6] = b"0.1.0\0";
+#[doc = " 多生产者多消费者channel，传递void*"]
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct co_channel_t {
+    _unused: [u8; 0],
+}
+#[doc = " 协程句柄，通过co_join或者co_detach释放"]
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct co_handle_t {
+    _unused: [u8; 0],
+}
+#[doc = " 协程互斥锁，不区分持有者，可以在别的协程里解锁"]
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct co_mutex_t {
+    _unused: [u8; 0],
+}
+#[doc = " 调度器"]
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct co_scheduler_t {
+    _unused: [u8; 0],
+}
+pub type co_function_t = ::std::option::Option<
+    unsafe extern "C" fn(arg1: *mut ::std::os::raw::c_void) -> *mut ::std::os::raw::c_void,
+>;
+extern "C" {
+    #[doc = " 开启或关闭当前线程的hook，返回之前的状态"]
+    pub fn set_hook_enabled(enabled: bool) -> bool;
+}
+extern "C" {
+    pub fn is_hook_enabled() -> bool;
+}
+extern "C" {
+    #[doc = " 当前是否在协程中执行"]
+    pub fn in_coroutine() -> bool;
+}
+extern "C" {
+    #[doc = " 同pthread_key_create，destructor在协程退出时调用，不在协程中设置的值在线程退出时调用"]
+    pub fn co_key_create(
+        key: *mut pthread_key_t,
+        destructor: ::std::option::Option<unsafe extern "C" fn(arg1: *mut ::std::os::raw::c_void)>,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn co_key_delete(key: pthread_key_t) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    #[doc = " 同pthread_getspecific，在协程中时获取当前协程的值"]
+    pub fn co_getspecific(key: pthread_key_t) -> *mut ::std::os::raw::c_void;
+}
+extern "C" {
+    #[doc = " 同pthread_setspecific，在协程中时设置当前协程的值"]
+    pub fn co_setspecific(
+        key: pthread_key_t,
+        value: *const ::std::os::raw::c_void,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    #[doc = " 动态库的版本，同OPEN_COROUTINE_VERSION"]
+    pub fn co_version() -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    #[doc = " 创建独立的调度器，通过co_scheduler_destroy释放"]
+    pub fn co_scheduler_create() -> *mut co_scheduler_t;
+}
+extern "C" {
+    #[doc = " 当前线程的调度器，不需要释放"]
+    pub fn co_scheduler_current() -> *mut co_scheduler_t;
+}
+extern "C" {
+    #[doc = " 执行调度器上的协程直到全部完成，返回完成的协程数量，scheduler为NULL时使用当前线程的调度器"]
+    pub fn co_scheduler_run(scheduler: *mut co_scheduler_t) -> usize;
+}
+extern "C" {
+    #[doc = " 最多执行timeout毫秒，返回完成的协程数量"]
+    pub fn co_scheduler_run_timeout(scheduler: *mut co_scheduler_t, timeout: u64) -> usize;
+}
+extern "C" {
+    #[doc = " 释放co_scheduler_create创建的调度器，还有未完成的协程时返回EBUSY"]
+    pub fn co_scheduler_destroy(scheduler: *mut co_scheduler_t) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    #[doc = " 在scheduler上创建协程执行function(arg)，stack_size为0时使用默认大小，\n scheduler为NULL时使用当前线程的调度器，栈大小超出限制或者分配失败时返回NULL并设置errno"]
+    pub fn co_spawn(
+        scheduler: *mut co_scheduler_t,
+        function: co_function_t,
+        arg: *mut ::std::os::raw::c_void,
+        stack_size: usize,
+    ) -> *mut co_handle_t;
+}
+extern "C" {
+    #[doc = " 等待协程完成并释放句柄，返回值写入result，result可以为NULL"]
+    pub fn co_join(
+        handle: *mut co_handle_t,
+        result: *mut *mut ::std::os::raw::c_void,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    #[doc = " 释放句柄，不等待协程完成"]
+    pub fn co_detach(handle: *mut co_handle_t);
+}
+extern "C" {
+    #[doc = " 协程id"]
+    pub fn co_handle_id(handle: *const co_handle_t) -> usize;
+}
+extern "C" {
+    #[doc = " 请求取消协程，协程通过co_cancelled检查，返回是否由本次调用取消"]
+    pub fn co_cancel(handle: *const co_handle_t) -> bool;
+}
+extern "C" {
+    #[doc = " 当前协程是否已经被取消"]
+    pub fn co_cancelled() -> bool;
+}
+extern "C" {
+    #[doc = " 当前协程的id，不在协程中时返回0"]
+    pub fn co_current_id() -> usize;
+}
+extern "C" {
+    #[doc = " 让出当前协程，不在协程中时让出线程的时间片，\n co_yield是C++20的关键字，所以叫co_yield_now"]
+    pub fn co_yield_now();
+}
+extern "C" {
+    #[doc = " 挂起当前协程millis毫秒，不在协程中时挂起线程，被信号打断时返回EINTR"]
+    pub fn co_sleep(millis: u64) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    #[doc = " 创建容量为capacity的channel，capacity为0时send要等到有接收者取走数据"]
+    pub fn co_channel_bounded(capacity: usize) -> *mut co_channel_t;
+}
+extern "C" {
+    #[doc = " 创建没有容量限制的channel"]
+    pub fn co_channel_unbounded() -> *mut co_channel_t;
+}
+extern "C" {
+    #[doc = " 发送value，channel满时挂起当前协程，channel关闭时返回EPIPE"]
+    pub fn co_channel_send(
+        channel: *const co_channel_t,
+        value: *mut ::std::os::raw::c_void,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    #[doc = " 不挂起，channel满时返回EAGAIN"]
+    pub fn co_channel_try_send(
+        channel: *const co_channel_t,
+        value: *mut ::std::os::raw::c_void,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    #[doc = " 接收数据写入value，channel空时挂起当前协程，channel关闭并且没有数据时返回EPIPE"]
+    pub fn co_channel_recv(
+        channel: *const co_channel_t,
+        value: *mut *mut ::std::os::raw::c_void,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    #[doc = " 最多等待timeout毫秒，超时返回ETIMEDOUT"]
+    pub fn co_channel_recv_timeout(
+        channel: *const co_channel_t,
+        value: *mut *mut ::std::os::raw::c_void,
+        timeout: u64,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    #[doc = " 不挂起，channel空时返回EAGAIN"]
+    pub fn co_channel_try_recv(
+        channel: *const co_channel_t,
+        value: *mut *mut ::std::os::raw::c_void,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    #[doc = " 关闭channel，唤醒所有等待者，已经发送的数据仍然可以接收"]
+    pub fn co_channel_close(channel: *const co_channel_t);
+}
+extern "C" {
+    #[doc = " channel中的数据数量"]
+    pub fn co_channel_len(channel: *const co_channel_t) -> usize;
+}
+extern "C" {
+    #[doc = " 释放channel，调用者需要保证没有协程还在使用它"]
+    pub fn co_channel_destroy(channel: *mut co_channel_t);
+}
+extern "C" {
+    pub fn co_mutex_create() -> *mut co_mutex_t;
+}
+extern "C" {
+    #[doc = " 加锁，锁被占用时挂起当前协程"]
+    pub fn co_mutex_lock(mutex: *const co_mutex_t) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    #[doc = " 锁被占用时返回EBUSY"]
+    pub fn co_mutex_trylock(mutex: *const co_mutex_t) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    #[doc = " 解锁，没有加锁时返回EPERM"]
+    pub fn co_mutex_unlock(mutex: *const co_mutex_t) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn co_mutex_destroy(mutex: *mut co_mutex_t) -> ::std::os::raw::c_int;
+}
@@ -0,0 +1,87 @@
+use std::io::{Error, Result};
+use std::os::raw::c_void;
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+/*
+调用的都是libhook里被hook的系统函数，在协程中阻塞时只挂起协程
+ */
+
+fn cvt(result: isize) -> Result<usize> {
+    if result < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(result as usize)
+}
+
+pub fn read(fd: RawFd, buf: &mut [u8]) -> Result<usize> {
+    cvt(unsafe { libc::read(fd, buf.as_mut_ptr() as *mut c_void, buf.len()) })
+}
+
+pub fn write(fd: RawFd, buf: &[u8]) -> Result<usize> {
+    cvt(unsafe { libc::write(fd, buf.as_ptr() as *const c_void, buf.len()) })
+}
+
+pub fn recv(fd: RawFd, buf: &mut [u8], flags: libc::c_int) -> Result<usize> {
+    cvt(unsafe { libc::recv(fd, buf.as_mut_ptr() as *mut c_void, buf.len(), flags) })
+}
+
+pub fn send(fd: RawFd, buf: &[u8], flags: libc::c_int) -> Result<usize> {
+    cvt(unsafe { libc::send(fd, buf.as_ptr() as *const c_void, buf.len(), flags) })
+}
+
+/// 等待fd上的events事件，events同poll，返回revents，超时返回0，
+/// timeout为None时一直等待
+pub fn poll(fd: RawFd, events: libc::c_short, timeout: Option<Duration>) -> Result<libc::c_short> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+    let timeout = match timeout {
+        Some(timeout) => timeout
+            .as_nanos()
+            .div_ceil(1_000_000)
+            .min(libc::c_int::MAX as u128) as libc::c_int,
+        None => -1,
+    };
+    cvt(unsafe { libc::poll(&mut pollfd, 1, timeout) } as isize)?;
+    Ok(pollfd.revents)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::{poll, read, write};
+    use crate::{run, sleep, spawn};
+    use std::time::Duration;
+
+    #[test]
+    fn test_pipe() {
+        let mut fds = [0; 2];
+        assert_eq!(0, unsafe { libc::pipe(fds.as_mut_ptr()) });
+        let (reader, writer) = (fds[0], fds[1]);
+        assert_eq!(
+            0,
+            poll(reader, libc::POLLIN, Some(Duration::from_millis(1))).unwrap()
+        );
+        //读协程阻塞时只挂起协程，写协程仍然可以执行
+        let reading = spawn(move || {
+            let mut buf = [0u8; 5];
+            let n = read(reader, &mut buf).unwrap();
+            buf[..n].to_vec()
+        })
+        .unwrap();
+        let writing = spawn(move || {
+            sleep(Duration::from_millis(10)).unwrap();
+            write(writer, b"hello").unwrap()
+        })
+        .unwrap();
+        assert_eq!(2, run());
+        assert_eq!(5, writing.join().unwrap());
+        assert_eq!(b"hello".to_vec(), reading.join().unwrap());
+        unsafe {
+            libc::close(reader);
+            libc::close(writer);
+        }
+    }
+}
@@ -1,38 +1,259 @@
-use open_coroutine::coroutine::Coroutine;
+use std::io::{Error, Result};
 use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
 
-extern "C" {
-    fn coroutine_crate(coroutine: *mut c_void);
+pub mod sys;
+
+pub mod io;
+
+/*
+libhook的安全封装，通过sys里的C API调用，协程由libhook里的调度器执行，
+这样协程中调用的系统函数才会被hook
+ */
+
+/// 开启或关闭当前线程的hook，返回之前的状态，
+/// 关闭后被hook的函数直接调用原始系统函数
+pub fn enable_hook(enabled: bool) -> bool {
+    unsafe { sys::set_hook_enabled(enabled) }
+}
+
+/// 当前线程是否开启了hook
+pub fn hook_enabled() -> bool {
+    unsafe { sys::is_hook_enabled() }
+}
+
+/// 当前是否在协程中执行
+pub fn is_in_coroutine() -> bool {
+    unsafe { sys::in_coroutine() }
+}
+
+/// 在作用域内关闭当前线程的hook，离开作用域时恢复
+#[derive(Debug)]
+pub struct HookGuard {
+    enabled: bool,
+}
+
+impl HookGuard {
+    pub fn disable() -> Self {
+        HookGuard {
+            enabled: enable_hook(false),
+        }
+    }
+}
+
+impl Drop for HookGuard {
+    fn drop(&mut self) {
+        enable_hook(self.enabled);
+    }
+}
+
+type Slot<T> = Arc<Mutex<Option<std::thread::Result<T>>>>;
+
+struct Task<F, T> {
+    f: F,
+    result: Slot<T>,
+}
+
+//panic不能穿过C函数，在这里捕获后保存到结果里
+extern "C" fn trampoline<F, T>(arg: *mut c_void) -> *mut c_void
+where
+    F: FnOnce() -> T,
+{
+    let task = unsafe { Box::from_raw(arg as *mut Task<F, T>) };
+    let result = panic::catch_unwind(AssertUnwindSafe(task.f));
+    *task.result.lock().unwrap_or_else(PoisonError::into_inner) = Some(result);
+    ptr::null_mut()
+}
+
+/// 协程的句柄，drop时不会等待协程完成
+#[derive(Debug)]
+pub struct JoinHandle<T> {
+    handle: *mut sys::co_handle_t,
+    result: Slot<T>,
+}
+
+unsafe impl<T: Send> Send for JoinHandle<T> {}
+
+unsafe impl<T: Send> Sync for JoinHandle<T> {}
+
+impl<T> JoinHandle<T> {
+    /// 协程id
+    pub fn id(&self) -> usize {
+        unsafe { sys::co_handle_id(self.handle) }
+    }
+
+    /// 请求取消协程，协程通过[`cancelled`]检查，返回是否由本次调用取消
+    pub fn cancel(&self) -> bool {
+        unsafe { sys::co_cancel(self.handle) }
+    }
+
+    /// 等待协程完成，协程panic时返回panic的内容，同std::thread::JoinHandle；
+    /// 在协程中时挂起当前协程，否则挂起当前线程
+    pub fn join(mut self) -> std::thread::Result<T> {
+        let error = unsafe { sys::co_join(self.handle, ptr::null_mut()) };
+        assert_eq!(0, error, "join coroutine failed !");
+        //co_join已经释放了句柄
+        self.handle = ptr::null_mut();
+        let result = self
+            .result
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        result.expect("coroutine finished without result !")
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        unsafe { sys::co_detach(self.handle) };
+    }
+}
+
+/// 在当前线程的调度器上创建协程执行f，通过[`run`]或者被hook的阻塞调用驱动
+pub fn spawn<F, T>(f: F) -> Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + 'static,
+    T: 'static,
+{
+    spawn_with(0, f)
 }
 
-pub fn co_crate<F>(size: usize, proc: F, param: Option<*mut c_void>) -> Coroutine<F>
+//stack_size为0时使用libhook的默认大小
+fn spawn_with<F, T>(stack_size: usize, f: F) -> Result<JoinHandle<T>>
 where
-    F: FnOnce(Option<*mut c_void>) -> Option<*mut c_void> + Sized,
+    F: FnOnce() -> T + 'static,
+    T: 'static,
 {
-    let mut co = Coroutine::new(size, proc, param);
-    unsafe {
-        coroutine_crate(&mut co as *mut _ as *mut c_void);
+    let result = Slot::default();
+    let task = Box::into_raw(Box::new(Task {
+        f,
+        result: result.clone(),
+    }));
+    let handle = unsafe {
+        sys::co_spawn(
+            ptr::null_mut(),
+            Some(trampoline::<F, T>),
+            task as *mut c_void,
+            stack_size,
+        )
+    };
+    if handle.is_null() {
+        //协程没有创建，task不会再被trampoline取走
+        let error = Error::last_os_error();
+        drop(unsafe { Box::from_raw(task) });
+        return Err(error);
+    }
+    Ok(JoinHandle { handle, result })
+}
+
+/// 执行当前线程上的协程直到全部完成，返回完成的协程数量
+pub fn run() -> usize {
+    unsafe { sys::co_scheduler_run(ptr::null_mut()) }
+}
+
+/// 挂起当前协程，不在协程中时挂起线程，被信号打断时返回EINTR
+pub fn sleep(duration: Duration) -> Result<()> {
+    //向上取整，不会少睡
+    let millis = duration
+        .as_nanos()
+        .div_ceil(1_000_000)
+        .min(u64::MAX as u128) as u64;
+    match unsafe { sys::co_sleep(millis) } {
+        0 => Ok(()),
+        error => Err(Error::from_raw_os_error(error)),
     }
-    co
+}
+
+/// 让出当前协程，不在协程中时让出线程的时间片
+pub fn yield_now() {
+    unsafe { sys::co_yield_now() }
+}
+
+/// 当前协程的id，不在协程中时返回None
+pub fn current_id() -> Option<usize> {
+    match unsafe { sys::co_current_id() } {
+        0 => None,
+        id => Some(id),
+    }
+}
+
+/// 当前协程是否已经被[`JoinHandle::cancel`]取消
+pub fn cancelled() -> bool {
+    unsafe { sys::co_cancelled() }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::co_crate;
+    use crate::{
+        cancelled, current_id, hook_enabled, is_in_coroutine, run, sleep, spawn, spawn_with,
+        yield_now, HookGuard,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn test_spawn() {
+        assert_eq!(None, current_id());
+        let first = spawn(|| {
+            assert!(is_in_coroutine());
+            yield_now();
+            current_id().unwrap()
+        })
+        .unwrap();
+        let panicked = spawn(|| panic!("oops")).unwrap();
+        let id = first.id();
+        assert_eq!(2, run());
+        assert_eq!(id, first.join().unwrap());
+        assert!(panicked.join().is_err());
+    }
+
+    #[test]
+    fn test_spawn_failed() {
+        //栈大小超出限制，协程没有创建，闭包随task一起释放
+        let captured = std::rc::Rc::new(());
+        let moved = captured.clone();
+        let error = spawn_with(usize::MAX, move || drop(moved)).unwrap_err();
+        assert_eq!(Some(libc::EINVAL), error.raw_os_error());
+        assert_eq!(1, std::rc::Rc::strong_count(&captured));
+    }
 
     #[test]
     fn test_sleep() {
-        unsafe {
-            let x = 10;
-            let _co = co_crate(
-                2048,
-                |param| {
-                    println!("hello from coroutine {}", x);
-                    param
-                },
-                None,
-            );
-            libc::sleep(1);
+        let waiting = spawn(|| {
+            let mut slept = 0;
+            while !cancelled() {
+                sleep(Duration::from_millis(1)).unwrap();
+                slept += 1;
+            }
+            slept
+        })
+        .unwrap();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(Duration::from_millis(20));
+                assert!(waiting.cancel());
+            });
+            assert_eq!(1, run());
+        });
+        assert!(!waiting.cancel());
+        assert!(waiting.join().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_hook_guard() {
+        assert!(hook_enabled());
+        assert!(!is_in_coroutine());
+        {
+            let _guard = HookGuard::disable();
+            assert!(!hook_enabled());
+            {
+                let _guard = HookGuard::disable();
+                assert!(!hook_enabled());
+            }
+            assert!(!hook_enabled());
         }
+        assert!(hook_enabled());
     }
 }
@@ -0,0 +1,13 @@
+//! libhook的C API，由bindgen从libhook构建时生成的open_coroutine.h生成，
+//! 开启bindgen feature时在构建时重新生成
+#![allow(non_camel_case_types, non_upper_case_globals, clippy::all)]
+
+//不同平台的定义不同，绑定里不生成
+pub use libc::pthread_key_t;
+
+#[cfg(feature = "bindgen")]
+include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+//没有libclang时使用提交的绑定，头文件变了之后需要开启bindgen feature重新生成
+#[cfg(not(feature = "bindgen"))]
+include!("bindings.rs");
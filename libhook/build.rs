@@ -1,13 +1,95 @@
 extern crate cbindgen;
 
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+//除了co_开头的函数，头文件里还要声明的函数
+const EXPORTED: [&str; 3] = ["set_hook_enabled", "is_hook_enabled", "in_coroutine"];
+
+//被hook的系统函数和内部使用的函数，声明会和系统头文件冲突，不能放进头文件
+fn excluded(src: &Path) -> Vec<String> {
+    let mut excluded = vec![String::from("Main")];
+    for entry in fs::read_dir(src).unwrap() {
+        let path = entry.unwrap().path();
+        println!("cargo:rerun-if-changed={}", path.display());
+        let source = fs::read_to_string(&path).unwrap();
+        for line in source.lines() {
+            if let Some(rest) = line.trim().strip_prefix("pub extern \"C\" fn ") {
+                let name: String = rest
+                    .chars()
+                    .take_while(|c| c.is_alphanumeric() || *c == '_')
+                    .collect();
+                if !name.starts_with("co_") && !EXPORTED.contains(&name.as_str()) {
+                    excluded.push(name);
+                }
+            }
+        }
+    }
+    excluded
+}
 
 #[allow(warnings)]
 fn main() {
-    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let major = env::var("CARGO_PKG_VERSION_MAJOR").unwrap();
+    let minor = env::var("CARGO_PKG_VERSION_MINOR").unwrap();
+    let patch = env::var("CARGO_PKG_VERSION_PATCH").unwrap();
     let mut config: cbindgen::Config = Default::default();
     config.language = cbindgen::Language::C;
+    config.cpp_compat = true;
+    config.include_guard = Some(String::from("OPEN_COROUTINE_H"));
+    config.sys_includes = vec![String::from("pthread.h")];
+    config.after_includes = Some(format!(
+        "\n#define OPEN_COROUTINE_VERSION_MAJOR {major}\n\
+         #define OPEN_COROUTINE_VERSION_MINOR {minor}\n\
+         #define OPEN_COROUTINE_VERSION_PATCH {patch}\n\
+         #define OPEN_COROUTINE_VERSION \"{major}.{minor}.{patch}\""
+    ));
+    config.export.exclude = excluded(&crate_dir.join("src"));
+    for (name, rename) in [
+        ("CoFunction", "co_function_t"),
+        ("CoScheduler", "co_scheduler_t"),
+        ("CoHandle", "co_handle_t"),
+        ("CoChannel", "co_channel_t"),
+        ("CoMutex", "co_mutex_t"),
+    ] {
+        config
+            .export
+            .rename
+            .insert(String::from(name), String::from(rename));
+    }
+    //只生成在OUT_DIR下，安装到系统目录见README
+    let include_dir = PathBuf::from(env::var("OUT_DIR").unwrap()).join("include");
+    fs::create_dir_all(&include_dir).unwrap();
     cbindgen::generate_with_config(&crate_dir, config)
         .unwrap()
-        .write_to_file("target/hook.h");
+        .write_to_file(include_dir.join("open_coroutine.h"));
+    //依赖libhook的crate通过DEP_HOOK_INCLUDE找到头文件，集成测试也用它编译C程序
+    println!("cargo:include={}", include_dir.display());
+    println!("cargo:rustc-env=HOOK_INCLUDE_DIR={}", include_dir.display());
+    //稳定版cargo不会把cdylib的位置告诉依赖方，只有同一个workspace里一起构建的libc-x
+    //在没有设置OPEN_COROUTINE_LIB_DIR时用它找libhook.so，和依赖方生成在同一个deps目录下
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    if let Some(deps_dir) = out_dir.ancestors().nth(3) {
+        println!("cargo:lib_dir={}", deps_dir.join("deps").display());
+    }
+
+    //apple上可变参数函数的hook由C读取可变参数，rustc只导出rust定义的符号，需要显式导出
+    println!("cargo:rerun-if-changed=src/variadic.c");
+    if env::var("CARGO_CFG_TARGET_VENDOR").unwrap() == "apple" {
+        cc::Build::new()
+            .file(crate_dir.join("src").join("variadic.c"))
+            .warnings_into_errors(true)
+            //没有rust代码引用这些符号，整个链接进来才不会被丢掉
+            .link_lib_modifier("+whole-archive")
+            .compile("variadic");
+        for name in ["fcntl", "ioctl", "open"] {
+            println!("cargo:rustc-cdylib-link-arg=-Wl,-exported_symbol,_{name}");
+        }
+    }
+
+    //集成测试用同一个C编译器编译测试程序
+    let compiler = cc::Build::new().get_compiler();
+    println!("cargo:rustc-env=HOOK_CC={}", compiler.path().display());
 }
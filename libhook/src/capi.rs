@@ -0,0 +1,442 @@
+use open_coroutine::channel::{
+    self, Receiver, RecvTimeoutError, Sender, TryRecvError, TrySendError,
+};
+use open_coroutine::coroutine::{Coroutine, UserFunction};
+use open_coroutine::coroutine_local;
+use open_coroutine::future::JoinHandle;
+use open_coroutine::scheduler::Scheduler;
+use open_coroutine::sync::{CancelToken, CoSemaphore};
+use std::cell::RefCell;
+use std::os::raw::{c_char, c_void};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/*
+给C/C++使用的API，声明在构建时生成的open_coroutine.h，安装方法见README，
+句柄都是不透明指针，出错时返回errno风格的错误码或者NULL
+ */
+
+/// 协程执行的C函数
+pub type CoFunction = Option<extern "C" fn(*mut c_void) -> *mut c_void>;
+
+/// 调度器
+pub struct CoScheduler {
+    _private: [u8; 0],
+}
+
+/// 协程句柄，通过co_join或者co_detach释放
+pub struct CoHandle {
+    join: JoinHandle<usize>,
+    token: CancelToken,
+}
+
+/// 多生产者多消费者channel，传递void*
+pub struct CoChannel {
+    sender: Sender<usize>,
+    receiver: Receiver<usize>,
+}
+
+/// 协程互斥锁，不区分持有者，可以在别的协程里解锁
+pub struct CoMutex {
+    semaphore: CoSemaphore,
+    //是否已经加锁，解锁时通过compare_exchange保证同一次加锁只能被解锁一次
+    locked: AtomicBool,
+}
+
+//co_spawn未指定栈大小时使用
+const DEFAULT_STACK_SIZE: usize = 64 * 1024;
+
+coroutine_local! {
+    //当前协程的取消令牌
+    static CANCEL: RefCell<Option<CancelToken>> = RefCell::new(None);
+}
+
+fn scheduler<'a>(scheduler: *mut CoScheduler) -> &'a mut Scheduler {
+    if scheduler.is_null() {
+        return Scheduler::current();
+    }
+    unsafe { &mut *(scheduler as *mut Scheduler) }
+}
+
+fn millis(timeout: u64) -> Duration {
+    Duration::from_millis(timeout)
+}
+
+/// 动态库的版本，同OPEN_COROUTINE_VERSION
+#[no_mangle]
+pub extern "C" fn co_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+/// 创建独立的调度器，通过co_scheduler_destroy释放
+#[no_mangle]
+pub extern "C" fn co_scheduler_create() -> *mut CoScheduler {
+    Box::into_raw(Box::new(Scheduler::new())) as *mut CoScheduler
+}
+
+/// 当前线程的调度器，不需要释放
+#[no_mangle]
+pub extern "C" fn co_scheduler_current() -> *mut CoScheduler {
+    Scheduler::current() as *mut Scheduler as *mut CoScheduler
+}
+
+/// 执行调度器上的协程直到全部完成，返回完成的协程数量，scheduler为NULL时使用当前线程的调度器
+#[no_mangle]
+pub extern "C" fn co_scheduler_run(scheduler: *mut CoScheduler) -> libc::size_t {
+    self::scheduler(scheduler).schedule().len()
+}
+
+/// 最多执行timeout毫秒，返回完成的协程数量
+#[no_mangle]
+pub extern "C" fn co_scheduler_run_timeout(
+    scheduler: *mut CoScheduler,
+    timeout: u64,
+) -> libc::size_t {
+    self::scheduler(scheduler)
+        .try_timed_schedule(millis(timeout))
+        .len()
+}
+
+/// 释放co_scheduler_create创建的调度器，还有未完成的协程时返回EBUSY
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_scheduler_destroy(scheduler: *mut CoScheduler) -> libc::c_int {
+    if scheduler.is_null() || scheduler == co_scheduler_current() {
+        return libc::EINVAL;
+    }
+    let scheduler = scheduler as *mut Scheduler;
+    if !unsafe { (*scheduler).is_empty() } {
+        return libc::EBUSY;
+    }
+    drop(unsafe { Box::from_raw(scheduler) });
+    0
+}
+
+/// 在scheduler上创建协程执行function(arg)，stack_size为0时使用默认大小，
+/// scheduler为NULL时使用当前线程的调度器，function为NULL、栈大小超出限制或者分配失败时返回NULL并设置errno
+#[no_mangle]
+pub extern "C" fn co_spawn(
+    scheduler: *mut CoScheduler,
+    function: CoFunction,
+    arg: *mut c_void,
+    stack_size: libc::size_t,
+) -> *mut CoHandle {
+    let function = match function {
+        Some(function) => function,
+        None => {
+            crate::set_errno(std::io::Error::from_raw_os_error(libc::EINVAL));
+            return std::ptr::null_mut();
+        }
+    };
+    let stack_size = match stack_size {
+        0 => DEFAULT_STACK_SIZE,
+        size => size,
+    };
+    let token = CancelToken::new();
+    let cancel = token.clone();
+    let spawned = Coroutine::builder()
+        .stack_size(stack_size)
+        .scheduler(self::scheduler(scheduler))
+        .spawn(move || {
+            CANCEL.with(|token| *token.borrow_mut() = Some(cancel));
+            function(arg) as usize
+        });
+    match spawned {
+        Ok(join) => Box::into_raw(Box::new(CoHandle { join, token })),
+        Err(error) => {
+            //栈大小超出限制时是InvalidInput，没有对应的系统错误码
+            let errno = error.raw_os_error().unwrap_or(libc::EINVAL);
+            crate::set_errno(std::io::Error::from_raw_os_error(errno));
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// 等待协程完成并释放句柄，返回值写入result，result可以为NULL
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_join(handle: *mut CoHandle, result: *mut *mut c_void) -> libc::c_int {
+    if handle.is_null() {
+        return libc::EINVAL;
+    }
+    let handle = unsafe { Box::from_raw(handle) };
+    match handle.join.join() {
+        Ok(value) => {
+            if !result.is_null() {
+                unsafe { *result = value as *mut c_void };
+            }
+            0
+        }
+        //协程panic
+        Err(_) => libc::EIO,
+    }
+}
+
+/// 释放句柄，不等待协程完成
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_detach(handle: *mut CoHandle) {
+    if !handle.is_null() {
+        drop(unsafe { Box::from_raw(handle) });
+    }
+}
+
+/// 协程id
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_handle_id(handle: *const CoHandle) -> u64 {
+    if handle.is_null() {
+        return 0;
+    }
+    unsafe { (*handle).join.id() }.as_u64()
+}
+
+/// 请求取消协程，协程通过co_cancelled检查，返回是否由本次调用取消
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_cancel(handle: *const CoHandle) -> bool {
+    !handle.is_null() && unsafe { (*handle).token.cancel() }
+}
+
+/// 当前协程是否已经被取消
+#[no_mangle]
+pub extern "C" fn co_cancelled() -> bool {
+    CANCEL.with(|token| {
+        token
+            .borrow()
+            .as_ref()
+            .is_some_and(CancelToken::is_cancelled)
+    })
+}
+
+/// 当前协程的id，不在协程中时返回0
+#[no_mangle]
+pub extern "C" fn co_current_id() -> u64 {
+    Coroutine::<UserFunction>::current().map_or(0, |coroutine| coroutine.get_id().as_u64())
+}
+
+/// 让出当前协程，不在协程中时让出线程的时间片，
+/// co_yield是C++20的关键字，所以叫co_yield_now
+#[no_mangle]
+pub extern "C" fn co_yield_now() {
+    if !Scheduler::yield_now() {
+        std::thread::yield_now();
+    }
+}
+
+/// 挂起当前协程millis毫秒，不在协程中时挂起线程，被信号打断时返回EINTR
+#[no_mangle]
+pub extern "C" fn co_sleep(millis: u64) -> libc::c_int {
+    match Scheduler::sleep(self::millis(millis)) {
+        Ok(true) => 0,
+        Ok(false) => {
+            std::thread::sleep(self::millis(millis));
+            0
+        }
+        Err(error) => error.raw_os_error().unwrap_or(libc::EINTR),
+    }
+}
+
+/// 创建容量为capacity的channel，capacity为0时send要等到有接收者取走数据
+#[no_mangle]
+pub extern "C" fn co_channel_bounded(capacity: libc::size_t) -> *mut CoChannel {
+    let (sender, receiver) = channel::bounded(capacity);
+    Box::into_raw(Box::new(CoChannel { sender, receiver }))
+}
+
+/// 创建没有容量限制的channel
+#[no_mangle]
+pub extern "C" fn co_channel_unbounded() -> *mut CoChannel {
+    let (sender, receiver) = channel::unbounded();
+    Box::into_raw(Box::new(CoChannel { sender, receiver }))
+}
+
+/// 发送value，channel满时挂起当前协程，channel关闭时返回EPIPE
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_channel_send(channel: *const CoChannel, value: *mut c_void) -> libc::c_int {
+    if channel.is_null() {
+        return libc::EINVAL;
+    }
+    match unsafe { (*channel).sender.send(value as usize) } {
+        Ok(()) => 0,
+        Err(_) => libc::EPIPE,
+    }
+}
+
+/// 不挂起，channel满时返回EAGAIN
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_channel_try_send(
+    channel: *const CoChannel,
+    value: *mut c_void,
+) -> libc::c_int {
+    if channel.is_null() {
+        return libc::EINVAL;
+    }
+    match unsafe { (*channel).sender.try_send(value as usize) } {
+        Ok(()) => 0,
+        Err(TrySendError::Full(_)) => libc::EAGAIN,
+        Err(TrySendError::Disconnected(_)) => libc::EPIPE,
+    }
+}
+
+/// 接收数据写入value，channel空时挂起当前协程，channel关闭并且没有数据时返回EPIPE
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_channel_recv(
+    channel: *const CoChannel,
+    value: *mut *mut c_void,
+) -> libc::c_int {
+    if channel.is_null() || value.is_null() {
+        return libc::EINVAL;
+    }
+    match unsafe { (*channel).receiver.recv() } {
+        Ok(received) => {
+            unsafe { *value = received as *mut c_void };
+            0
+        }
+        Err(_) => libc::EPIPE,
+    }
+}
+
+/// 最多等待timeout毫秒，超时返回ETIMEDOUT
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_channel_recv_timeout(
+    channel: *const CoChannel,
+    value: *mut *mut c_void,
+    timeout: u64,
+) -> libc::c_int {
+    if channel.is_null() || value.is_null() {
+        return libc::EINVAL;
+    }
+    match unsafe { (*channel).receiver.recv_timeout(millis(timeout)) } {
+        Ok(received) => {
+            unsafe { *value = received as *mut c_void };
+            0
+        }
+        Err(RecvTimeoutError::Timeout) => libc::ETIMEDOUT,
+        Err(RecvTimeoutError::Disconnected) => libc::EPIPE,
+    }
+}
+
+/// 不挂起，channel空时返回EAGAIN
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_channel_try_recv(
+    channel: *const CoChannel,
+    value: *mut *mut c_void,
+) -> libc::c_int {
+    if channel.is_null() || value.is_null() {
+        return libc::EINVAL;
+    }
+    match unsafe { (*channel).receiver.try_recv() } {
+        Ok(received) => {
+            unsafe { *value = received as *mut c_void };
+            0
+        }
+        Err(TryRecvError::Empty) => libc::EAGAIN,
+        Err(TryRecvError::Disconnected) => libc::EPIPE,
+    }
+}
+
+/// 关闭channel，唤醒所有等待者，已经发送的数据仍然可以接收
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_channel_close(channel: *const CoChannel) {
+    if !channel.is_null() {
+        unsafe { (*channel).sender.close() };
+    }
+}
+
+/// channel中的数据数量
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_channel_len(channel: *const CoChannel) -> libc::size_t {
+    if channel.is_null() {
+        return 0;
+    }
+    unsafe { (*channel).receiver.len() }
+}
+
+/// 释放channel，调用者需要保证没有协程还在使用它
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_channel_destroy(channel: *mut CoChannel) {
+    if !channel.is_null() {
+        drop(unsafe { Box::from_raw(channel) });
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn co_mutex_create() -> *mut CoMutex {
+    Box::into_raw(Box::new(CoMutex {
+        semaphore: CoSemaphore::new(1),
+        locked: AtomicBool::new(false),
+    }))
+}
+
+/// 加锁，锁被占用时挂起当前协程
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_mutex_lock(mutex: *const CoMutex) -> libc::c_int {
+    if mutex.is_null() {
+        return libc::EINVAL;
+    }
+    unsafe {
+        (*mutex).semaphore.acquire().forget();
+        (*mutex).locked.store(true, Ordering::Release);
+    }
+    0
+}
+
+/// 锁被占用时返回EBUSY
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_mutex_trylock(mutex: *const CoMutex) -> libc::c_int {
+    if mutex.is_null() {
+        return libc::EINVAL;
+    }
+    match unsafe { (*mutex).semaphore.try_acquire() } {
+        Some(permit) => {
+            permit.forget();
+            unsafe { (*mutex).locked.store(true, Ordering::Release) };
+            0
+        }
+        None => libc::EBUSY,
+    }
+}
+
+/// 解锁，没有加锁时返回EPERM
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_mutex_unlock(mutex: *const CoMutex) -> libc::c_int {
+    if mutex.is_null() {
+        return libc::EINVAL;
+    }
+    let mutex = unsafe { &*mutex };
+    //先检查再释放的话，并发解锁会都看到已加锁，释放出多个许可
+    if mutex
+        .locked
+        .compare_exchange(true, false, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        return libc::EPERM;
+    }
+    mutex.semaphore.release(1);
+    0
+}
+
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn co_mutex_destroy(mutex: *mut CoMutex) -> libc::c_int {
+    if mutex.is_null() {
+        return libc::EINVAL;
+    }
+    if unsafe { (*mutex).semaphore.available_permits() } == 0 {
+        return libc::EBUSY;
+    }
+    drop(unsafe { Box::from_raw(mutex) });
+    0
+}
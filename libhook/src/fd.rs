@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::sync::{PoisonError, RwLock};
+use std::time::Duration;
+
+/// hook记录的fd状态，fd是进程级别的，所以放在全局
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct FdState {
+    //是否为socket，只有socket会被运行时强制设置为非阻塞
+    pub socket: bool,
+    //是否为普通文件或块设备，无法非阻塞，只能交给阻塞线程池
+    pub regular: bool,
+    //用户是否设置了非阻塞
+    pub user_nonblocking: bool,
+    //运行时是否已强制设置了非阻塞
+    pub runtime_nonblocking: bool,
+    //SO_RCVTIMEO
+    pub recv_timeout: Option<Duration>,
+    //SO_SNDTIMEO
+    pub send_timeout: Option<Duration>,
+    //记录时的st_dev和st_ino，用来发现fd号被复用后过期的状态
+    pub identity: (u64, u64),
+}
+
+static FDS: RwLock<BTreeMap<libc::c_int, FdState>> = RwLock::new(BTreeMap::new());
+
+fn stat(fd: libc::c_int) -> Option<libc::stat> {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+        return None;
+    }
+    Some(stat)
+}
+
+//st_dev和st_ino在不同平台上的类型不同
+#[allow(clippy::unnecessary_cast)]
+fn identity(stat: &libc::stat) -> (u64, u64) {
+    (stat.st_dev as u64, stat.st_ino as u64)
+}
+
+/// 已记录的fd状态
+pub fn get(fd: libc::c_int) -> Option<FdState> {
+    let state = FDS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&fd)
+        .copied()?;
+    //fd可能被没有经过hook的close关闭（比如glibc内部的fclose）后复用，记录的状态已经过期
+    if stat(fd).map(|stat| identity(&stat)) != Some(state.identity) {
+        let mut fds = FDS.write().unwrap_or_else(PoisonError::into_inner);
+        //别的线程可能已经重新记录了
+        if fds.get(&fd) == Some(&state) {
+            fds.remove(&fd);
+        }
+        return None;
+    }
+    Some(state)
+}
+
+/// fd状态，第一次访问时从内核读取
+pub fn load(fd: libc::c_int) -> Option<FdState> {
+    if let Some(state) = get(fd) {
+        return Some(state);
+    }
+    let flags = crate::original_fcntl(fd, libc::F_GETFL, 0);
+    if flags < 0 {
+        return None;
+    }
+    let stat = stat(fd)?;
+    let state = FdState {
+        socket: stat.st_mode & libc::S_IFMT == libc::S_IFSOCK,
+        regular: matches!(stat.st_mode & libc::S_IFMT, libc::S_IFREG | libc::S_IFBLK),
+        //SOCK_NONBLOCK、继承等方式设置的非阻塞也属于用户
+        user_nonblocking: flags & libc::O_NONBLOCK != 0,
+        identity: identity(&stat),
+        ..Default::default()
+    };
+    Some(
+        *FDS.write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(fd)
+            .or_insert(state),
+    )
+}
+
+pub fn update(fd: libc::c_int, f: impl FnOnce(&mut FdState)) {
+    if load(fd).is_some() {
+        if let Some(state) = FDS
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_mut(&fd)
+        {
+            f(state)
+        }
+    }
+}
+
+/// to和from共享同一个打开的文件，from没有记录时删除to的旧状态
+pub fn copy(from: libc::c_int, to: libc::c_int) {
+    let state = get(from);
+    let mut fds = FDS.write().unwrap_or_else(PoisonError::into_inner);
+    match state {
+        Some(state) => fds.insert(to, state),
+        None => fds.remove(&to),
+    };
+}
+
+pub fn remove(fd: libc::c_int) {
+    FDS.write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&fd);
+}
+
+/// 运行时接管阻塞的socket，强制设置为非阻塞，返回是否已是非阻塞
+pub fn force_nonblocking(fd: libc::c_int) -> bool {
+    let state = match load(fd) {
+        Some(state) => state,
+        None => return false,
+    };
+    if state.user_nonblocking || state.runtime_nonblocking {
+        return true;
+    }
+    if !state.socket {
+        return false;
+    }
+    let flags = crate::original_fcntl(fd, libc::F_GETFL, 0);
+    if flags < 0
+        || crate::original_fcntl(
+            fd,
+            libc::F_SETFL,
+            (flags | libc::O_NONBLOCK) as libc::c_long,
+        ) < 0
+    {
+        return false;
+    }
+    update(fd, |state| state.runtime_nonblocking = true);
+    true
+}
@@ -1,99 +1,290 @@
+//cbindgen依赖的syn无法解析c"..."字面量，只能手动补\0
+#![allow(clippy::manual_c_str_literals)]
+
+mod capi;
+
+mod fd;
+
+mod original;
+
+mod preload;
+
+mod signal;
+
+mod switch;
+
+mod sync;
+
 use open_coroutine::coroutine::Coroutine;
+use open_coroutine::local;
 use open_coroutine::scheduler::Scheduler;
+use std::io::ErrorKind;
 use std::os::raw::c_void;
 use std::ptr;
 use std::time::Duration;
 
-/**
+/*
 被hook的系统函数
 #[no_mangle]避免rust编译器修改方法名称
 epoll like
-fcntl由于最后一个参数的类型问题，不支持
 todo 待支持io_uring
  */
 
-static mut POLL: Option<
-    extern "C" fn(*mut libc::pollfd, libc::nfds_t, libc::c_int) -> libc::c_int,
-> = None;
+/*
+fcntl、ioctl、open是可变参数函数，稳定版rust只能声明不能定义可变参数函数，
+实际的hook按固定参数实现在open_coroutine_*里：
+apple arm64上可变参数通过栈传递，和固定参数的调用约定不同，导出的符号是variadic.c里的C函数，
+由C按调用约定读取可变参数后再调用open_coroutine_*；
+其他平台上整数或指针类型的第三个参数，不管是可变参数还是固定参数都通过同一个寄存器或栈位置传递，
+直接导出按固定参数定义的rust函数（rustc生成cdylib时只导出rust定义的符号，C函数导不出去）
+ */
+#[cfg(all(unix, not(target_vendor = "apple")))]
+#[no_mangle]
+pub extern "C" fn fcntl(fd: libc::c_int, cmd: libc::c_int, arg: libc::c_long) -> libc::c_int {
+    open_coroutine_fcntl(fd, cmd, arg)
+}
+
+#[cfg(all(unix, not(target_vendor = "apple")))]
+#[no_mangle]
+pub extern "C" fn ioctl(fd: libc::c_int, request: libc::c_ulong, arg: *mut c_void) -> libc::c_int {
+    open_coroutine_ioctl(fd, request, arg)
+}
+
+//mode只在创建文件时传入，没有传入时读到的值不会被使用
+#[cfg(all(unix, not(target_vendor = "apple")))]
+#[no_mangle]
+pub extern "C" fn open(
+    path: *const libc::c_char,
+    flags: libc::c_int,
+    mode: libc::c_int,
+) -> libc::c_int {
+    open_coroutine_open(path, flags, mode)
+}
+
+#[cfg(target_os = "linux")]
+#[no_mangle]
+pub extern "C" fn open64(
+    path: *const libc::c_char,
+    flags: libc::c_int,
+    mode: libc::c_int,
+) -> libc::c_int {
+    open_coroutine_open64(path, flags, mode)
+}
 
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[cfg(unix)]
 #[no_mangle]
-pub fn poll(fds: *mut libc::pollfd, nfds: libc::nfds_t, timeout: libc::c_int) -> libc::c_int {
-    //todo
+pub extern "C" fn poll(
+    fds: *mut libc::pollfd,
+    nfds: libc::nfds_t,
+    timeout: libc::c_int,
+) -> libc::c_int {
     //获取原始系统函数poll
-    let original = unsafe {
-        match POLL {
-            Some(original) => original,
-            None => {
-                let original =
-                    std::mem::transmute::<
-                        _,
-                        extern "C" fn(*mut libc::pollfd, libc::nfds_t, libc::c_int) -> libc::c_int,
-                    >(libc::dlsym(libc::RTLD_NEXT, "poll".as_ptr() as _));
-                POLL = Some(original);
-                original
-            }
+    let original = original::POLL.get();
+    //不需要等待或者不在协程中，相当于libc::poll(fds, nfds, timeout)
+    if timeout == 0 || !switch::hooked() {
+        return original(fds, nfds, timeout);
+    }
+    let timeout_time = millis_to_timeout_time(timeout);
+    let events: Vec<(libc::c_int, libc::c_short)> = (0..nfds as usize)
+        .map(|i| unsafe { *fds.add(i) })
+        .filter(|pollfd| pollfd.fd >= 0)
+        .map(|pollfd| (pollfd.fd, pollfd.events))
+        .collect();
+    loop {
+        let r = original(fds, nfds, 0);
+        if r != 0 {
+            return r;
         }
-    };
-    //相当于libc::poll(fds, nfds, timeout)
-    original(fds, nfds, timeout)
+        let left_time = match left_time(timeout_time) {
+            Some(Duration::ZERO) => return 0,
+            left_time => left_time,
+        };
+        //挂起当前协程，直到任意一个fd就绪或者超时
+        match Scheduler::wait_event(&events, left_time) {
+            Ok(true) => {}
+            Ok(false) => return original(fds, nfds, left_time.map_or(-1, duration_to_millis)),
+            Err(error) => return set_errno(error),
+        }
+    }
 }
 
-static mut SELECT: Option<
-    extern "C" fn(
-        libc::c_int,
-        *mut libc::fd_set,
-        *mut libc::fd_set,
-        *mut libc::fd_set,
-        *mut libc::timeval,
-    ) -> libc::c_int,
-> = None;
-
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[cfg(unix)]
 #[no_mangle]
-pub fn select(
+pub extern "C" fn select(
     nfds: libc::c_int,
-    readfs: *mut libc::fd_set,
+    readfds: *mut libc::fd_set,
     writefds: *mut libc::fd_set,
     errorfds: *mut libc::fd_set,
     timeout: *mut libc::timeval,
 ) -> libc::c_int {
-    //todo
     //获取原始系统函数select
-    let original = unsafe {
-        match SELECT {
-            Some(original) => original,
-            None => {
-                let original =
-                    std::mem::transmute::<
-                        _,
-                        extern "C" fn(
-                            libc::c_int,
-                            *mut libc::fd_set,
-                            *mut libc::fd_set,
-                            *mut libc::fd_set,
-                            *mut libc::timeval,
-                        ) -> libc::c_int,
-                    >(libc::dlsym(libc::RTLD_NEXT, "select".as_ptr() as _));
-                SELECT = Some(original);
-                original
+    let original = original::SELECT.get();
+    let timeout_time = if timeout.is_null() {
+        None
+    } else {
+        match timeval_to_duration(unsafe { &*timeout }) {
+            //不需要等待，或者timeout不合法
+            Some(Duration::ZERO) | None => {
+                return original(nfds, readfds, writefds, errorfds, timeout)
+            }
+            Some(timeout) => Some(timer::get_timeout_time(timeout)),
+        }
+    };
+    if !switch::hooked() {
+        //相当于libc::select(nfds, readfds, writefds, errorfds, timeout)
+        return original(nfds, readfds, writefds, errorfds, timeout);
+    }
+    //select会修改fd_set，先保存一份
+    let sets = [readfds, writefds, errorfds].map(|set| unsafe { set.as_ref().copied() });
+    let mut events = Vec::new();
+    for fd in 0..nfds {
+        let mut interest = 0;
+        for (set, event) in sets
+            .iter()
+            .zip([libc::POLLIN, libc::POLLOUT, libc::POLLPRI])
+        {
+            if let Some(set) = set {
+                if unsafe { libc::FD_ISSET(fd, set) } {
+                    interest |= event;
+                }
+            }
+        }
+        if interest != 0 {
+            events.push((fd, interest));
+        }
+    }
+    loop {
+        for (target, set) in [readfds, writefds, errorfds].into_iter().zip(sets) {
+            if let Some(set) = set {
+                unsafe { *target = set };
+            }
+        }
+        let mut zero = libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        };
+        let r = original(nfds, readfds, writefds, errorfds, &mut zero);
+        let left_time = left_time(timeout_time);
+        if r != 0 || left_time == Some(Duration::ZERO) {
+            #[cfg(target_os = "linux")]
+            if !timeout.is_null() {
+                //linux下select会把timeout修改为剩余时间
+                let left_time = left_time.unwrap_or(Duration::ZERO);
+                unsafe {
+                    (*timeout).tv_sec = left_time.as_secs() as _;
+                    (*timeout).tv_usec = left_time.subsec_micros() as _;
+                }
+            }
+            return r;
+        }
+        //挂起当前协程，直到任意一个fd就绪或者超时
+        match Scheduler::wait_event(&events, left_time) {
+            Ok(true) => {}
+            Ok(false) => {
+                for (target, set) in [readfds, writefds, errorfds].into_iter().zip(sets) {
+                    if let Some(set) = set {
+                        unsafe { *target = set };
+                    }
+                }
+                return original(nfds, readfds, writefds, errorfds, timeout);
             }
+            Err(error) => return set_errno(error),
         }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[no_mangle]
+pub extern "C" fn epoll_wait(
+    epfd: libc::c_int,
+    events: *mut libc::epoll_event,
+    maxevents: libc::c_int,
+    timeout: libc::c_int,
+) -> libc::c_int {
+    //获取原始系统函数epoll_wait
+    let original = original::EPOLL_WAIT.get();
+    //不需要等待或者不在协程中，相当于libc::epoll_wait(epfd, events, maxevents, timeout)
+    if timeout == 0 || !switch::hooked() {
+        return original(epfd, events, maxevents, timeout);
+    }
+    let timeout_time = millis_to_timeout_time(timeout);
+    loop {
+        let r = original(epfd, events, maxevents, 0);
+        if r != 0 {
+            return r;
+        }
+        let left_time = match left_time(timeout_time) {
+            Some(Duration::ZERO) => return 0,
+            left_time => left_time,
+        };
+        //epoll实例有事件就绪时，epfd本身可读
+        match Scheduler::wait_event(&[(epfd, libc::POLLIN)], left_time) {
+            Ok(true) => {}
+            Ok(false) => {
+                return original(
+                    epfd,
+                    events,
+                    maxevents,
+                    left_time.map_or(-1, duration_to_millis),
+                )
+            }
+            Err(error) => return set_errno(error),
+        }
+    }
+}
+
+//设置errno，返回-1
+fn set_errno(error: std::io::Error) -> libc::c_int {
+    let errno = error.raw_os_error().unwrap_or(libc::EIO);
+    #[cfg(target_os = "linux")]
+    unsafe {
+        *libc::__errno_location() = errno
     };
-    //相当于libc::select(nfds, readfs, writefds, errorfds, timeout)
-    original(nfds, readfs, writefds, errorfds, timeout)
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "tvos",
+        target_os = "watchos"
+    ))]
+    unsafe {
+        *libc::__error() = errno
+    };
+    -1
+}
+
+fn millis_to_timeout_time(timeout: libc::c_int) -> Option<u64> {
+    if timeout < 0 {
+        //一直等待
+        return None;
+    }
+    Some(timer::get_timeout_time(Duration::from_millis(
+        timeout as u64,
+    )))
+}
+
+fn left_time(timeout_time: Option<u64>) -> Option<Duration> {
+    timeout_time.map(|timeout_time| Duration::from_nanos(timeout_time.saturating_sub(timer::now())))
+}
+
+fn duration_to_millis(duration: Duration) -> libc::c_int {
+    duration
+        .as_nanos()
+        .div_ceil(1_000_000)
+        .min(libc::c_int::MAX as u128) as libc::c_int
 }
 
-static mut KEVENT: Option<
-    extern "C" fn(
-        libc::c_int,
-        *const libc::kevent,
-        libc::c_int,
-        *mut libc::kevent,
-        libc::c_int,
-        *const libc::timespec,
-    ) -> libc::c_int,
-> = None;
+//tv_sec为负数或者tv_usec不在[0, 1_000_000)内时返回None，交给原始函数处理（返回EINVAL或者自行规整）
+fn timeval_to_duration(timeval: &libc::timeval) -> Option<Duration> {
+    if timeval.tv_sec < 0 || !(0..1_000_000).contains(&timeval.tv_usec) {
+        return None;
+    }
+    Some(Duration::new(
+        timeval.tv_sec as u64,
+        timeval.tv_usec as u32 * 1000,
+    ))
+}
 
 #[cfg(any(
     target_os = "macos",
@@ -112,315 +303,2186 @@ pub fn kevent(
 ) -> libc::c_int {
     //todo
     //获取原始系统函数kevent
-    let original = unsafe {
-        match KEVENT {
-            Some(original) => original,
-            None => {
-                let original =
-                    std::mem::transmute::<
-                        _,
-                        extern "C" fn(
-                            libc::c_int,
-                            *const libc::kevent,
-                            libc::c_int,
-                            *mut libc::kevent,
-                            libc::c_int,
-                            *const libc::timespec,
-                        ) -> libc::c_int,
-                    >(libc::dlsym(libc::RTLD_NEXT, "kevent".as_ptr() as _));
-                KEVENT = Some(original);
-                original
+    let original = original::KEVENT.get();
+    //相当于libc::kevent(kq, changelist, nchanges, eventlist, nevents, timeout)
+    original(kq, changelist, nchanges, eventlist, nevents, timeout)
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn close(fd: libc::c_int) -> libc::c_int {
+    //获取原始系统函数close
+    let original = original::CLOSE.get();
+    //先唤醒等待者、删除fd状态再关闭，fd关闭后可能马上被别的线程复用
+    Scheduler::close_event(fd);
+    fd::remove(fd);
+    //相当于libc::close(fd)
+    original(fd)
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn shutdown(socket: libc::c_int, how: libc::c_int) -> libc::c_int {
+    //获取原始系统函数shutdown
+    let original = original::SHUTDOWN.get();
+    //相当于libc::shutdown(socket, how)
+    let r = original(socket, how);
+    if r == 0 {
+        //fd仍然有效，让等待者重新检查fd的状态
+        Scheduler::shutdown_event(socket);
+    }
+    r
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn dup(fd: libc::c_int) -> libc::c_int {
+    //获取原始系统函数dup
+    let original = original::DUP.get();
+    //相当于libc::dup(fd)
+    let r = original(fd);
+    if r >= 0 {
+        //新fd和原fd共享文件状态，同时覆盖新fd号上过期的状态
+        fd::copy(fd, r);
+    }
+    r
+}
+
+//dup2和dup3会先关闭new_fd，和close一样先唤醒等待new_fd的协程
+fn before_dup2(old_fd: libc::c_int, new_fd: libc::c_int) {
+    if old_fd != new_fd && original_fcntl(old_fd, libc::F_GETFD, 0) >= 0 {
+        Scheduler::close_event(new_fd);
+    }
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn dup2(old_fd: libc::c_int, new_fd: libc::c_int) -> libc::c_int {
+    //获取原始系统函数dup2
+    let original = original::DUP2.get();
+    before_dup2(old_fd, new_fd);
+    //相当于libc::dup2(old_fd, new_fd)
+    let r = original(old_fd, new_fd);
+    if r >= 0 {
+        fd::copy(old_fd, r);
+    }
+    r
+}
+
+#[cfg(target_os = "linux")]
+#[no_mangle]
+pub extern "C" fn dup3(
+    old_fd: libc::c_int,
+    new_fd: libc::c_int,
+    flags: libc::c_int,
+) -> libc::c_int {
+    //获取原始系统函数dup3
+    let original = original::DUP3.get();
+    before_dup2(old_fd, new_fd);
+    //相当于libc::dup3(old_fd, new_fd, flags)
+    let r = original(old_fd, new_fd, flags);
+    if r >= 0 {
+        fd::copy(old_fd, r);
+    }
+    r
+}
+
+//第三个参数最多一个整数或指针，按long传给可变参数的原始函数
+pub(crate) fn original_fcntl(fd: libc::c_int, cmd: libc::c_int, arg: libc::c_long) -> libc::c_int {
+    //获取原始系统函数fcntl
+    let original = original::FCNTL.get();
+    //相当于libc::fcntl(fd, cmd, arg)
+    unsafe { original(fd, cmd, arg) }
+}
+
+//apple上由variadic.c读取可变参数后调用
+#[cfg(unix)]
+#[no_mangle]
+extern "C" fn open_coroutine_fcntl(
+    fd: libc::c_int,
+    cmd: libc::c_int,
+    arg: libc::c_long,
+) -> libc::c_int {
+    match cmd {
+        libc::F_GETFL => {
+            let r = original_fcntl(fd, cmd, arg);
+            match fd::get(fd) {
+                //对用户隐藏运行时设置的非阻塞
+                Some(state) if r >= 0 && state.runtime_nonblocking && !state.user_nonblocking => {
+                    r & !libc::O_NONBLOCK
+                }
+                _ => r,
+            }
+        }
+        libc::F_SETFL => {
+            let user_nonblocking = arg as libc::c_int & libc::O_NONBLOCK != 0;
+            let arg = match fd::get(fd) {
+                Some(state) if state.runtime_nonblocking => arg | libc::O_NONBLOCK as libc::c_long,
+                _ => arg,
+            };
+            let r = original_fcntl(fd, cmd, arg);
+            if r == 0 {
+                fd::update(fd, |state| state.user_nonblocking = user_nonblocking);
             }
+            r
         }
+        libc::F_DUPFD | libc::F_DUPFD_CLOEXEC => {
+            let r = original_fcntl(fd, cmd, arg);
+            if r >= 0 {
+                //新fd和原fd共享文件状态
+                fd::copy(fd, r);
+            }
+            r
+        }
+        _ => original_fcntl(fd, cmd, arg),
+    }
+}
+
+//apple上由variadic.c读取可变参数后调用
+#[cfg(unix)]
+#[no_mangle]
+extern "C" fn open_coroutine_ioctl(
+    fd: libc::c_int,
+    request: libc::c_ulong,
+    arg: *mut c_void,
+) -> libc::c_int {
+    //获取原始系统函数ioctl
+    let original = original::IOCTL.get();
+    if request != libc::FIONBIO || arg.is_null() {
+        //相当于libc::ioctl(fd, request, arg)
+        return unsafe { original(fd, request, arg) };
+    }
+    let user_nonblocking = unsafe { *(arg as *const libc::c_int) } != 0;
+    let r = match fd::get(fd) {
+        //运行时接管的socket在内核中保持非阻塞
+        Some(state) if state.runtime_nonblocking => {
+            let mut nonblocking: libc::c_int = 1;
+            unsafe { original(fd, request, &mut nonblocking as *mut libc::c_int) }
+        }
+        _ => unsafe { original(fd, request, arg) },
     };
-    //相当于libc::kevent(kq, changelist, nchanges, eventlist, nevents, timeout)
-    original(kq, changelist, nchanges, eventlist, nevents, timeout)
+    if r == 0 {
+        fd::update(fd, |state| state.user_nonblocking = user_nonblocking);
+    }
+    r
 }
 
-// #[cfg(target_os = "linux")]
-// #[no_mangle]
-// pub fn epoll_wait(
-//     epfd: libc::c_int,
-//     events: *mut libc::epoll_event,
-//     maxevents: libc::c_int,
-//     timeout: libc::c_int,
-// ) -> libc::c_int {
-//     todo!()
-// }
-//
-// //socket相关
-// #[cfg(unix)]
-// #[no_mangle]
-// pub fn setsockopt(
-//     socket: libc::c_int,
-//     level: libc::c_int,
-//     name: libc::c_int,
-//     value: *const libc::c_void,
-//     option_len: libc::socklen_t,
-// ) -> libc::c_int {
-//     todo!()
-// }
-//
-// #[cfg(unix)]
-// #[no_mangle]
-// pub fn accept(
-//     socket: libc::c_int,
-//     address: *mut libc::sockaddr,
-//     address_len: *mut libc::socklen_t,
-// ) -> libc::c_int {
-//     //需要强制把socket设置为非阻塞
-//     todo!()
-// }
-//
-// #[cfg(unix)]
-// #[no_mangle]
-// pub fn connect(
-//     socket: libc::c_int,
-//     address: *const libc::sockaddr,
-//     len: libc::socklen_t,
-// ) -> libc::c_int {
-//     //需要强制把socket设置为非阻塞
-//     todo!()
-// }
-//
-// #[cfg(unix)]
-// #[no_mangle]
-// pub fn close(fd: libc::c_int) -> libc::c_int {
-//     todo!()
-// }
-//
-// //读数据
-// #[cfg(unix)]
-// #[no_mangle]
-// pub fn read(fd: libc::c_int, buf: *mut libc::c_void, count: libc::size_t) -> libc::ssize_t {
-//     todo!()
-// }
-//
-// #[cfg(any(
-//     target_os = "macos",
-//     target_os = "ios",
-//     target_os = "tvos",
-//     target_os = "watchos",
-//     target_os = "freebsd",
-//     target_os = "dragonfly",
-//     target_os = "openbsd",
-//     target_os = "netbsd"
-// ))]
-// #[no_mangle]
-// pub fn readv(fd: libc::c_int, iov: *const libc::iovec, iovcnt: libc::c_int) -> libc::ssize_t {
-//     todo!()
-// }
-//
-// #[cfg(unix)]
-// #[no_mangle]
-// pub fn recv(
-//     socket: libc::c_int,
-//     buf: *mut libc::c_void,
-//     len: libc::size_t,
-//     flags: libc::c_int,
-// ) -> libc::ssize_t {
-//     todo!()
-// }
-//
-// #[cfg(any(
-//     target_os = "macos",
-//     target_os = "ios",
-//     target_os = "tvos",
-//     target_os = "watchos",
-//     target_os = "freebsd",
-//     target_os = "dragonfly",
-//     target_os = "openbsd",
-//     target_os = "netbsd"
-// ))]
-// #[no_mangle]
-// pub fn recvfrom(
-//     socket: libc::c_int,
-//     buf: *mut libc::c_void,
-//     len: libc::size_t,
-//     flags: libc::c_int,
-//     addr: *mut libc::sockaddr,
-//     addrlen: *mut libc::socklen_t,
-// ) -> libc::ssize_t {
-//     todo!()
-// }
-//
-// #[cfg(any(
-//     target_os = "macos",
-//     target_os = "ios",
-//     target_os = "tvos",
-//     target_os = "watchos",
-//     target_os = "freebsd",
-//     target_os = "dragonfly",
-//     target_os = "openbsd",
-//     target_os = "netbsd"
-// ))]
-// #[no_mangle]
-// pub fn recvmsg(fd: libc::c_int, msg: *mut libc::msghdr, flags: libc::c_int) -> libc::ssize_t {
-//     todo!()
-// }
-//
-// //写数据
-// #[cfg(unix)]
-// #[no_mangle]
-// pub fn write(fd: libc::c_int, buf: *const libc::c_void, count: libc::size_t) -> libc::ssize_t {
-//     todo!()
-// }
-//
-// #[cfg(any(
-//     target_os = "macos",
-//     target_os = "ios",
-//     target_os = "tvos",
-//     target_os = "watchos",
-//     target_os = "freebsd",
-//     target_os = "dragonfly",
-//     target_os = "openbsd",
-//     target_os = "netbsd"
-// ))]
-// #[no_mangle]
-// pub fn writev(fd: libc::c_int, iov: *const libc::iovec, iovcnt: libc::c_int) -> libc::ssize_t {
-//     todo!()
-// }
-//
-// #[cfg(unix)]
-// #[no_mangle]
-// pub fn send(
-//     socket: libc::c_int,
-//     buf: *const libc::c_void,
-//     len: libc::size_t,
-//     flags: libc::c_int,
-// ) -> libc::ssize_t {
-//     todo!()
-// }
-//
-// #[cfg(unix)]
-// #[no_mangle]
-// pub fn sendto(
-//     socket: libc::c_int,
-//     buf: *const libc::c_void,
-//     len: libc::size_t,
-//     flags: libc::c_int,
-//     addr: *const libc::sockaddr,
-//     addrlen: libc::socklen_t,
-// ) -> libc::ssize_t {
-//     todo!()
-// }
-//
-// #[cfg(any(
-//     target_os = "macos",
-//     target_os = "ios",
-//     target_os = "tvos",
-//     target_os = "watchos",
-//     target_os = "freebsd",
-//     target_os = "dragonfly",
-//     target_os = "openbsd",
-//     target_os = "netbsd"
-// ))]
-// #[no_mangle]
-// pub fn sendmsg(fd: libc::c_int, msg: *const libc::msghdr, flags: libc::c_int) -> libc::ssize_t {
-//     todo!()
-// }
-
-//sleep相关
+//socket相关
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[cfg(unix)]
 #[no_mangle]
-pub extern "C" fn sleep(secs: libc::c_uint) -> libc::c_uint {
-    let rqtp = libc::timespec {
-        tv_sec: secs as i64,
-        tv_nsec: 0,
+pub extern "C" fn setsockopt(
+    socket: libc::c_int,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: *const c_void,
+    option_len: libc::socklen_t,
+) -> libc::c_int {
+    //获取原始系统函数setsockopt
+    let original = original::SETSOCKOPT.get();
+    //相当于libc::setsockopt(socket, level, name, value, option_len)
+    let r = original(socket, level, name, value, option_len);
+    if r == 0
+        && level == libc::SOL_SOCKET
+        && (name == libc::SO_RCVTIMEO || name == libc::SO_SNDTIMEO)
+        && !value.is_null()
+        && option_len as usize >= std::mem::size_of::<libc::timeval>()
+    {
+        //不合法的timeval已经交给原始函数处理，不记录
+        let Some(timeout) = timeval_to_duration(unsafe { &*(value as *const libc::timeval) })
+        else {
+            return r;
+        };
+        //0表示永不超时
+        let timeout = Some(timeout).filter(|timeout| !timeout.is_zero());
+        fd::update(socket, |state| {
+            if name == libc::SO_RCVTIMEO {
+                state.recv_timeout = timeout;
+            } else {
+                state.send_timeout = timeout;
+            }
+        });
+    }
+    r
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn accept(
+    socket: libc::c_int,
+    address: *mut libc::sockaddr,
+    address_len: *mut libc::socklen_t,
+) -> libc::c_int {
+    //获取原始系统函数accept
+    let original = original::ACCEPT.get();
+    //相当于libc::accept(socket, address, address_len)
+    blocking_io(socket, libc::POLLIN, true, move || {
+        original(socket, address, address_len)
+    })
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn connect(
+    socket: libc::c_int,
+    address: *const libc::sockaddr,
+    len: libc::socklen_t,
+) -> libc::c_int {
+    //获取原始系统函数connect
+    let original = original::CONNECT.get();
+    let state = match take_over(socket) {
+        Some(state) => state,
+        //相当于libc::connect(socket, address, len)
+        None => return original(socket, address, len),
     };
-    let mut rmtp = libc::timespec {
+    let r = original(socket, address, len);
+    if r == 0 || std::io::Error::last_os_error().raw_os_error() != Some(libc::EINPROGRESS) {
+        return r;
+    }
+    //等待连接完成，超时时间同SO_SNDTIMEO
+    let timeout_time = state.send_timeout.map(timer::get_timeout_time);
+    loop {
+        let mut pollfd = libc::pollfd {
+            fd: socket,
+            events: libc::POLLOUT,
+            revents: 0,
+        };
+        if poll(&mut pollfd, 1, 0) > 0 {
+            break;
+        }
+        let left_time = left_time(timeout_time);
+        if left_time == Some(Duration::ZERO) {
+            return set_errno(std::io::Error::from_raw_os_error(libc::EINPROGRESS));
+        }
+        if let Err(error) = wait_fd(socket, libc::POLLOUT, left_time) {
+            return set_errno(error);
+        }
+    }
+    let mut error: libc::c_int = 0;
+    let mut error_len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    if unsafe {
+        libc::getsockopt(
+            socket,
+            libc::SOL_SOCKET,
+            libc::SO_ERROR,
+            &mut error as *mut libc::c_int as *mut c_void,
+            &mut error_len,
+        )
+    } < 0
+    {
+        return -1;
+    }
+    if error != 0 {
+        return set_errno(std::io::Error::from_raw_os_error(error));
+    }
+    0
+}
+
+//读数据
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn read(fd: libc::c_int, buf: *mut c_void, count: libc::size_t) -> libc::ssize_t {
+    //获取原始系统函数read
+    let original = original::READ.get();
+    //相当于libc::read(fd, buf, count)
+    blocking_io(fd, libc::POLLIN, true, move || original(fd, buf, count))
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn readv(
+    fd: libc::c_int,
+    iov: *const libc::iovec,
+    iovcnt: libc::c_int,
+) -> libc::ssize_t {
+    //获取原始系统函数readv
+    let original = original::READV.get();
+    //相当于libc::readv(fd, iov, iovcnt)
+    blocking_io(fd, libc::POLLIN, true, move || original(fd, iov, iovcnt))
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn recv(
+    socket: libc::c_int,
+    buf: *mut c_void,
+    len: libc::size_t,
+    flags: libc::c_int,
+) -> libc::ssize_t {
+    //获取原始系统函数recv
+    let original = original::RECV.get();
+    if flags & libc::MSG_DONTWAIT != 0 {
+        //用户要求本次调用不阻塞
+        return original(socket, buf, len, flags);
+    }
+    //相当于libc::recv(socket, buf, len, flags)
+    blocking_io(socket, libc::POLLIN, true, move || {
+        original(socket, buf, len, flags)
+    })
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn recvfrom(
+    socket: libc::c_int,
+    buf: *mut c_void,
+    len: libc::size_t,
+    flags: libc::c_int,
+    addr: *mut libc::sockaddr,
+    addrlen: *mut libc::socklen_t,
+) -> libc::ssize_t {
+    //获取原始系统函数recvfrom
+    let original = original::RECVFROM.get();
+    if flags & libc::MSG_DONTWAIT != 0 {
+        //用户要求本次调用不阻塞
+        return original(socket, buf, len, flags, addr, addrlen);
+    }
+    //相当于libc::recvfrom(socket, buf, len, flags, addr, addrlen)
+    blocking_io(socket, libc::POLLIN, true, move || {
+        original(socket, buf, len, flags, addr, addrlen)
+    })
+}
+
+//recvmsg可以通过SCM_RIGHTS接收fd，常用于unix socket
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn recvmsg(
+    socket: libc::c_int,
+    msg: *mut libc::msghdr,
+    flags: libc::c_int,
+) -> libc::ssize_t {
+    //获取原始系统函数recvmsg
+    let original = original::RECVMSG.get();
+    if flags & libc::MSG_DONTWAIT != 0 {
+        //用户要求本次调用不阻塞
+        return original(socket, msg, flags);
+    }
+    //相当于libc::recvmsg(socket, msg, flags)
+    blocking_io(socket, libc::POLLIN, true, move || {
+        original(socket, msg, flags)
+    })
+}
+
+//写数据
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn write(fd: libc::c_int, buf: *const c_void, count: libc::size_t) -> libc::ssize_t {
+    //获取原始系统函数write
+    let original = original::WRITE.get();
+    //相当于libc::write(fd, buf, count)
+    blocking_io(fd, libc::POLLOUT, false, move || original(fd, buf, count))
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn writev(
+    fd: libc::c_int,
+    iov: *const libc::iovec,
+    iovcnt: libc::c_int,
+) -> libc::ssize_t {
+    //获取原始系统函数writev
+    let original = original::WRITEV.get();
+    //相当于libc::writev(fd, iov, iovcnt)
+    blocking_io(fd, libc::POLLOUT, false, move || original(fd, iov, iovcnt))
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn send(
+    socket: libc::c_int,
+    buf: *const c_void,
+    len: libc::size_t,
+    flags: libc::c_int,
+) -> libc::ssize_t {
+    //获取原始系统函数send
+    let original = original::SEND.get();
+    if flags & libc::MSG_DONTWAIT != 0 {
+        //用户要求本次调用不阻塞
+        return original(socket, buf, len, flags);
+    }
+    //相当于libc::send(socket, buf, len, flags)
+    blocking_io(socket, libc::POLLOUT, false, move || {
+        original(socket, buf, len, flags)
+    })
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn sendto(
+    socket: libc::c_int,
+    buf: *const c_void,
+    len: libc::size_t,
+    flags: libc::c_int,
+    addr: *const libc::sockaddr,
+    addrlen: libc::socklen_t,
+) -> libc::ssize_t {
+    //获取原始系统函数sendto
+    let original = original::SENDTO.get();
+    if flags & libc::MSG_DONTWAIT != 0 {
+        //用户要求本次调用不阻塞
+        return original(socket, buf, len, flags, addr, addrlen);
+    }
+    //相当于libc::sendto(socket, buf, len, flags, addr, addrlen)
+    blocking_io(socket, libc::POLLOUT, false, move || {
+        original(socket, buf, len, flags, addr, addrlen)
+    })
+}
+
+//sendmsg可以通过SCM_RIGHTS发送fd，常用于unix socket
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn sendmsg(
+    socket: libc::c_int,
+    msg: *const libc::msghdr,
+    flags: libc::c_int,
+) -> libc::ssize_t {
+    //获取原始系统函数sendmsg
+    let original = original::SENDMSG.get();
+    if flags & libc::MSG_DONTWAIT != 0 {
+        //用户要求本次调用不阻塞
+        return original(socket, msg, flags);
+    }
+    //相当于libc::sendmsg(socket, msg, flags)
+    blocking_io(socket, libc::POLLOUT, false, move || {
+        original(socket, msg, flags)
+    })
+}
+
+//文件相关，无法非阻塞，在协程中交给阻塞线程池
+//apple上由variadic.c读取可变参数后调用，mode只在创建文件时有效
+#[cfg(unix)]
+#[no_mangle]
+extern "C" fn open_coroutine_open(
+    path: *const libc::c_char,
+    flags: libc::c_int,
+    mode: libc::c_int,
+) -> libc::c_int {
+    //获取原始系统函数open
+    let original = original::OPEN.get();
+    if !switch::hooked() {
+        //相当于libc::open(path, flags, mode)
+        return unsafe { original(path, flags, mode) };
+    }
+    offload(move || unsafe { original(path, flags, mode) })
+}
+
+#[cfg(target_os = "linux")]
+#[no_mangle]
+extern "C" fn open_coroutine_open64(
+    path: *const libc::c_char,
+    flags: libc::c_int,
+    mode: libc::c_int,
+) -> libc::c_int {
+    //获取原始系统函数open64
+    let original = original::OPEN64.get();
+    if !switch::hooked() {
+        //相当于libc::open64(path, flags, mode)
+        return unsafe { original(path, flags, mode) };
+    }
+    offload(move || unsafe { original(path, flags, mode) })
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn fsync(fd: libc::c_int) -> libc::c_int {
+    //获取原始系统函数fsync
+    let original = original::FSYNC.get();
+    if !switch::hooked() {
+        //相当于libc::fsync(fd)
+        return original(fd);
+    }
+    offload(move || original(fd))
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn stat(path: *const libc::c_char, buf: *mut libc::stat) -> libc::c_int {
+    //获取原始系统函数stat
+    let original = original::STAT.get();
+    if !switch::hooked() {
+        //相当于libc::stat(path, buf)
+        return original(path, buf);
+    }
+    offload(move || original(path, buf))
+}
+
+//DNS解析
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn getaddrinfo(
+    node: *const libc::c_char,
+    service: *const libc::c_char,
+    hints: *const libc::addrinfo,
+    res: *mut *mut libc::addrinfo,
+) -> libc::c_int {
+    //获取原始系统函数getaddrinfo
+    let original = original::GETADDRINFO.get();
+    if !switch::hooked() {
+        //相当于libc::getaddrinfo(node, service, hints, res)
+        return original(node, service, hints, res);
+    }
+    offload(move || original(node, service, hints, res))
+}
+
+//线程相关，OPEN_COROUTINE_THREADS开启时，新线程在协程中执行
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn pthread_create(
+    thread: *mut libc::pthread_t,
+    attr: *const libc::pthread_attr_t,
+    start_routine: extern "C" fn(*mut c_void) -> *mut c_void,
+    arg: *mut c_void,
+) -> libc::c_int {
+    //获取原始系统函数pthread_create
+    let original = original::PTHREAD_CREATE.get();
+    if preload::policy() == preload::Policy::None
+        || !switch::is_enabled()
+        || open_coroutine::blocking::is_spawning()
+    {
+        //相当于libc::pthread_create(thread, attr, start_routine, arg)
+        return original(thread, attr, start_routine, arg);
+    }
+    let start = Box::into_raw(Box::new((start_routine, arg)));
+    let r = original(thread, attr, preload::coroutine_start, start as *mut c_void);
+    if r != 0 {
+        drop(unsafe { Box::from_raw(start) });
+    }
+    r
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Main =
+    extern "C" fn(libc::c_int, *mut *mut libc::c_char, *mut *mut libc::c_char) -> libc::c_int;
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+static MAIN: std::sync::OnceLock<Main> = std::sync::OnceLock::new();
+
+/// OPEN_COROUTINE_THREADS=all时，main函数在协程中执行
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+#[no_mangle]
+pub extern "C" fn __libc_start_main(
+    main: Main,
+    argc: libc::c_int,
+    argv: *mut *mut libc::c_char,
+    init: *const c_void,
+    fini: *const c_void,
+    rtld_fini: *const c_void,
+    stack_end: *mut c_void,
+) -> libc::c_int {
+    //获取原始系统函数__libc_start_main
+    let original = original::LIBC_START_MAIN.get();
+    if preload::policy() != preload::Policy::All {
+        return original(main, argc, argv, init, fini, rtld_fini, stack_end);
+    }
+    let _ = MAIN.set(main);
+    original(coroutine_main, argc, argv, init, fini, rtld_fini, stack_end)
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+extern "C" fn coroutine_main(
+    argc: libc::c_int,
+    argv: *mut *mut libc::c_char,
+    envp: *mut *mut libc::c_char,
+) -> libc::c_int {
+    let main = *MAIN.get().expect("main is not set");
+    preload::run_in_coroutine(move || main(argc, argv, envp) as isize as *mut c_void) as isize
+        as libc::c_int
+}
+
+//同步原语相关，同一线程上的协程互相等待时挂起协程，避免线程死锁
+
+//等待mutex的协程没有被唤醒时，重新尝试加锁的间隔
+const MUTEX_RETRY: Duration = Duration::from_millis(10);
+
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn pthread_mutex_lock(mutex: *mut libc::pthread_mutex_t) -> libc::c_int {
+    //获取原始系统函数pthread_mutex_lock
+    let original = original::PTHREAD_MUTEX_LOCK.get();
+    let (waker, node) = match Scheduler::waker() {
+        Some(waker) if switch::hooked() => waker,
+        //相当于libc::pthread_mutex_lock(mutex)
+        _ => return original(mutex),
+    };
+    let id = node.id();
+    let address = mutex as usize;
+    loop {
+        match unsafe { libc::pthread_mutex_trylock(mutex) } {
+            libc::EBUSY => {}
+            0 => {
+                sync::set_owner(address, id);
+                return 0;
+            }
+            r => return r,
+        }
+        if sync::owner(address) == Some(id) {
+            //当前协程重复加锁，由原始函数处理：errorcheck返回EDEADLK，normal和原来一样死锁
+            return original(mutex);
+        }
+        //先登记再重试，保证之后的unlock一定能看到当前协程
+        let waiter = sync::Waiter::new(waker.clone(), node.clone());
+        sync::MUTEXES.add(address, waiter.clone());
+        match unsafe { libc::pthread_mutex_trylock(mutex) } {
+            libc::EBUSY => {}
+            r => {
+                //删除失败说明同时被unlock唤醒了，把唤醒让给下一个等待者
+                if !sync::MUTEXES.remove(address, &waiter) {
+                    sync::MUTEXES.signal(address, false);
+                }
+                if r == 0 {
+                    sync::set_owner(address, id);
+                }
+                return r;
+            }
+        }
+        //libc内部释放的锁(比如原始的pthread_cond_wait)不经过hook，不会唤醒等待者，定期重试
+        let retry_time = Some(timer::get_timeout_time(MUTEX_RETRY));
+        while !waiter.is_signaled() {
+            let left_time = left_time(retry_time);
+            if left_time == Some(Duration::ZERO) {
+                sync::MUTEXES.remove(address, &waiter);
+                break;
+            }
+            Scheduler::wait(left_time);
+        }
+    }
+}
+
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn pthread_mutex_unlock(mutex: *mut libc::pthread_mutex_t) -> libc::c_int {
+    //获取原始系统函数pthread_mutex_unlock
+    let original = original::PTHREAD_MUTEX_UNLOCK.get();
+    //相当于libc::pthread_mutex_unlock(mutex)
+    let r = original(mutex);
+    if r == 0 {
+        sync::remove_owner(mutex as usize);
+        //唤醒一个等待该mutex的协程，被唤醒的协程重新尝试加锁
+        sync::MUTEXES.signal(mutex as usize, false);
+    }
+    r
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn pthread_cond_wait(
+    cond: *mut libc::pthread_cond_t,
+    mutex: *mut libc::pthread_mutex_t,
+) -> libc::c_int {
+    //获取原始系统函数pthread_cond_wait
+    let original = original::PTHREAD_COND_WAIT.get();
+    if !switch::hooked() {
+        //相当于libc::pthread_cond_wait(cond, mutex)
+        return original(cond, mutex);
+    }
+    cond_wait(cond, mutex, None, || original(cond, mutex))
+}
+
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn pthread_cond_timedwait(
+    cond: *mut libc::pthread_cond_t,
+    mutex: *mut libc::pthread_mutex_t,
+    abstime: *const libc::timespec,
+) -> libc::c_int {
+    //获取原始系统函数pthread_cond_timedwait
+    let original = original::PTHREAD_COND_TIMEDWAIT.get();
+    if !switch::hooked() || abstime.is_null() {
+        //相当于libc::pthread_cond_timedwait(cond, mutex, abstime)
+        return original(cond, mutex, abstime);
+    }
+    let fallback = || original(cond, mutex, abstime);
+    let abstime = unsafe { *abstime };
+    if abstime.tv_nsec < 0 || abstime.tv_nsec >= 1_000_000_000 {
+        return libc::EINVAL;
+    }
+    //abstime默认基于CLOCK_REALTIME
+    let mut now = libc::timespec {
         tv_sec: 0,
         tv_nsec: 0,
     };
-    nanosleep(&rqtp, &mut rmtp);
-    rmtp.tv_sec as u32
+    unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut now) };
+    let left_time = Duration::new(abstime.tv_sec.max(0) as u64, abstime.tv_nsec as u32)
+        .saturating_sub(Duration::new(now.tv_sec.max(0) as u64, now.tv_nsec as u32));
+    cond_wait(
+        cond,
+        mutex,
+        Some(timer::get_timeout_time(left_time)),
+        fallback,
+    )
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn pthread_cond_signal(cond: *mut libc::pthread_cond_t) -> libc::c_int {
+    //获取原始系统函数pthread_cond_signal
+    let original = original::PTHREAD_COND_SIGNAL.get();
+    //相当于libc::pthread_cond_signal(cond)，同时唤醒一个等待的协程
+    let r = original(cond);
+    sync::CONDS.signal(cond as usize, false);
+    r
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn pthread_cond_broadcast(cond: *mut libc::pthread_cond_t) -> libc::c_int {
+    //获取原始系统函数pthread_cond_broadcast
+    let original = original::PTHREAD_COND_BROADCAST.get();
+    //相当于libc::pthread_cond_broadcast(cond)，同时唤醒所有等待的协程
+    let r = original(cond);
+    sync::CONDS.signal(cond as usize, true);
+    r
+}
+
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn sem_wait(sem: *mut libc::sem_t) -> libc::c_int {
+    //获取原始系统函数sem_wait
+    let original = original::SEM_WAIT.get();
+    if !switch::hooked() {
+        //相当于libc::sem_wait(sem)
+        return original(sem);
+    }
+    spin_wait(|| {
+        if unsafe { libc::sem_trywait(sem) } == 0 {
+            return Some(0);
+        }
+        match std::io::Error::last_os_error().raw_os_error() {
+            Some(libc::EAGAIN) => None,
+            _ => Some(-1),
+        }
+    })
+}
+
+//先自旋，失败后挂起协程一小段时间再重试，让同一线程上持有锁的协程有机会执行
+fn spin_wait<R>(mut try_once: impl FnMut() -> Option<R>) -> R {
+    let mut backoff = Duration::from_micros(10);
+    loop {
+        for _ in 0..16 {
+            if let Some(r) = try_once() {
+                return r;
+            }
+            std::hint::spin_loop();
+        }
+        Scheduler::wait(Some(backoff));
+        backoff = (backoff * 2).min(Duration::from_millis(1));
+    }
+}
+
+//释放mutex并挂起协程，直到被pthread_cond_signal/pthread_cond_broadcast唤醒或者超时，
+//协程没有提交给调度器、无法被唤醒时交给fallback阻塞线程
+fn cond_wait(
+    cond: *mut libc::pthread_cond_t,
+    mutex: *mut libc::pthread_mutex_t,
+    timeout_time: Option<u64>,
+    fallback: impl FnOnce() -> libc::c_int,
+) -> libc::c_int {
+    let waiter = match Scheduler::waker() {
+        Some((waker, node)) => sync::Waiter::new(waker, node),
+        None => return fallback(),
+    };
+    //先登记再释放mutex，保证之后的signal一定能看到当前协程
+    sync::CONDS.add(cond as usize, waiter.clone());
+    let r = pthread_mutex_unlock(mutex);
+    if r != 0 {
+        sync::CONDS.remove(cond as usize, &waiter);
+        return r;
+    }
+    let mut r = 0;
+    while !waiter.is_signaled() {
+        let left_time = left_time(timeout_time);
+        if left_time == Some(Duration::ZERO) {
+            //删除失败说明超时的同时被唤醒了
+            if sync::CONDS.remove(cond as usize, &waiter) {
+                r = libc::ETIMEDOUT;
+            }
+            break;
+        }
+        Scheduler::wait(left_time);
+    }
+    match pthread_mutex_lock(mutex) {
+        0 => r,
+        error => error,
+    }
+}
+
+//运行时是否接管fd上的阻塞调用，用户设置了非阻塞时不接管，
+//在协程中会把socket强制设置为非阻塞
+fn take_over(fd: libc::c_int) -> Option<fd::FdState> {
+    let in_coroutine = switch::hooked();
+    let state = if in_coroutine {
+        fd::load(fd)
+    } else {
+        fd::get(fd)
+    }?;
+    if state.user_nonblocking {
+        return None;
+    }
+    if state.runtime_nonblocking || (in_coroutine && fd::force_nonblocking(fd)) {
+        return Some(state);
+    }
+    None
+}
+
+//阻塞式IO的通用处理，recv为true时使用SO_RCVTIMEO，否则使用SO_SNDTIMEO
+fn blocking_io<R: PartialEq + From<i8> + Send + 'static>(
+    fd: libc::c_int,
+    events: libc::c_short,
+    recv: bool,
+    mut f: impl FnMut() -> R + 'static,
+) -> R {
+    let state = match take_over(fd) {
+        Some(state) => state,
+        None => {
+            let in_coroutine = switch::hooked();
+            if in_coroutine && fd::get(fd).is_some_and(|state| state.regular) {
+                //普通文件总是"就绪"的，但读写仍可能阻塞在磁盘上
+                return offload(f);
+            }
+            if in_coroutine && fd::get(fd).is_some_and(|state| !state.user_nonblocking) {
+                //非socket不会被设置为非阻塞，等fd就绪后再调用，避免阻塞线程
+                let mut pollfd = libc::pollfd {
+                    fd,
+                    events,
+                    revents: 0,
+                };
+                while poll(&mut pollfd, 1, 0) == 0 {
+                    if let Err(error) = wait_fd(fd, events, None) {
+                        set_errno(error);
+                        return R::from(-1);
+                    }
+                }
+            }
+            return f();
+        }
+    };
+    let timeout = if recv {
+        state.recv_timeout
+    } else {
+        state.send_timeout
+    };
+    let timeout_time = timeout.map(timer::get_timeout_time);
+    loop {
+        let r = f();
+        if r != R::from(-1) || std::io::Error::last_os_error().kind() != ErrorKind::WouldBlock {
+            return r;
+        }
+        let left_time = left_time(timeout_time);
+        if left_time == Some(Duration::ZERO) {
+            //超时，同阻塞fd上SO_RCVTIMEO/SO_SNDTIMEO的行为
+            set_errno(std::io::Error::from_raw_os_error(libc::EAGAIN));
+            return r;
+        }
+        if let Err(error) = wait_fd(fd, events, left_time) {
+            set_errno(error);
+            return R::from(-1);
+        }
+    }
+}
+
+//在阻塞线程池中执行，期间挂起当前协程，errno会被带回当前线程
+fn offload<R: Send + 'static>(f: impl FnOnce() -> R + 'static) -> R {
+    //f只捕获了原始函数和调用参数，协程挂起期间参数指向的内存一直有效
+    struct Task<F>(F);
+    unsafe impl<F> Send for Task<F> {}
+    let task = Task(f);
+    let (r, errno) = open_coroutine::blocking::spawn_blocking(move || {
+        let task = task;
+        let r = (task.0)();
+        (r, std::io::Error::last_os_error())
+    });
+    set_errno(errno);
+    r
+}
+
+//等待fd就绪或者超时，在协程中挂起协程，否则阻塞线程
+fn wait_fd(
+    fd: libc::c_int,
+    events: libc::c_short,
+    timeout: Option<Duration>,
+) -> std::io::Result<()> {
+    if Scheduler::wait_event(&[(fd, events)], timeout)? {
+        return Ok(());
+    }
+    let mut pollfd = libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+    if poll(&mut pollfd, 1, timeout.map_or(-1, duration_to_millis)) < 0 {
+        let error = std::io::Error::last_os_error();
+        if error.kind() != ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+//信号处理函数被替换成先记录线程处理了信号的trampoline，让sleep中的协程返回EINTR，
+//oldact里返回的仍是用户注册的处理函数
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn sigaction(
+    signum: libc::c_int,
+    act: *const libc::sigaction,
+    oldact: *mut libc::sigaction,
+) -> libc::c_int {
+    //获取原始系统函数sigaction
+    let original = original::SIGACTION.get();
+    let previous = signal::handler(signum);
+    let wrapped = if act.is_null() {
+        None
+    } else {
+        signal::wrap(signum, unsafe { &*act })
+    };
+    //相当于libc::sigaction(signum, act, oldact)
+    let r = original(
+        signum,
+        wrapped.as_ref().map_or(act, |w| w as *const _),
+        oldact,
+    );
+    if r == 0 && !oldact.is_null() {
+        signal::unwrap(unsafe { &mut *oldact }, previous);
+    }
+    r
+}
+
+//glibc的signal直接调用内部的sigaction，不经过上面的hook，按glibc的BSD语义重新实现
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn signal(signum: libc::c_int, handler: libc::sighandler_t) -> libc::sighandler_t {
+    let mut act: libc::sigaction = unsafe { std::mem::zeroed() };
+    act.sa_sigaction = handler;
+    act.sa_flags = libc::SA_RESTART;
+    let mut old: libc::sigaction = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::sigemptyset(&mut act.sa_mask);
+        if libc::sigaddset(&mut act.sa_mask, signum) < 0 {
+            return libc::SIG_ERR;
+        }
+    }
+    if sigaction(signum, &act, &mut old) < 0 {
+        return libc::SIG_ERR;
+    }
+    old.sa_sigaction
 }
 
+//sleep相关，在协程中时挂起协程而不是线程，不在协程中时直接调用原始函数
 #[cfg(unix)]
 #[no_mangle]
-pub extern "C" fn usleep(secs: libc::c_uint) -> libc::c_int {
-    let secs = secs as i64;
-    let sec = secs / 1_000_000;
-    let nsec = (secs - sec * 1_000_000) * 1000;
+pub extern "C" fn sleep(secs: libc::c_uint) -> libc::c_uint {
     let rqtp = libc::timespec {
-        tv_sec: sec,
-        tv_nsec: nsec,
+        tv_sec: secs as libc::time_t,
+        tv_nsec: 0,
     };
     let mut rmtp = libc::timespec {
         tv_sec: 0,
         tv_nsec: 0,
     };
-    nanosleep(&rqtp, &mut rmtp)
+    if nanosleep(&rqtp, &mut rmtp) < 0 {
+        //同glibc，剩余时间四舍五入到秒
+        return rmtp.tv_sec as libc::c_uint + (rmtp.tv_nsec >= 500_000_000) as libc::c_uint;
+    }
+    0
 }
 
-static mut NANOSLEEP: Option<
-    extern "C" fn(*const libc::timespec, *mut libc::timespec) -> libc::c_int,
-> = None;
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn usleep(usecs: libc::c_uint) -> libc::c_int {
+    let rqtp = libc::timespec {
+        tv_sec: (usecs / 1_000_000) as libc::time_t,
+        tv_nsec: (usecs % 1_000_000) as libc::c_long * 1000,
+    };
+    nanosleep(&rqtp, ptr::null_mut())
+}
 
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[cfg(unix)]
 #[no_mangle]
 pub extern "C" fn nanosleep(rqtp: *const libc::timespec, rmtp: *mut libc::timespec) -> libc::c_int {
-    let nanos_time = unsafe { (*rqtp).tv_sec * 1_000_000_000 + (*rqtp).tv_nsec } as u64;
-    let timeout_time = timer::get_timeout_time(Duration::from_nanos(nanos_time));
-    Scheduler::current().try_timed_schedule(Duration::from_nanos(nanos_time));
-    // 可能schedule完还剩一些时间，此时本地队列没有任务可做
-    // 后续考虑work-steal，需要在Scheduler增加timed_schedule实现
-    let schedule_finished_time = timer::now();
-    let left_time = (timeout_time - schedule_finished_time) as i64;
-    if left_time <= 0 {
-        unsafe {
-            (*rmtp).tv_sec = 0;
-            (*rmtp).tv_nsec = 0;
+    //获取原始系统函数nanosleep
+    let original = original::NANOSLEEP.get();
+    if switch::hooked() {
+        //linux上nanosleep按CLOCK_MONOTONIC计时
+        match coroutine_sleep(libc::CLOCK_MONOTONIC, false, rqtp, rmtp) {
+            Some(0) => return 0,
+            Some(errno) => return set_errno(std::io::Error::from_raw_os_error(errno)),
+            None => {}
         }
-        return 0;
     }
-    let sec = left_time / 1_000_000_000;
-    let nsec = left_time - sec * 1_000_000_000;
-    let rqtp = libc::timespec {
-        tv_sec: sec,
-        tv_nsec: nsec,
+    //相当于libc::nanosleep(rqtp, rmtp)
+    original(rqtp, rmtp)
+}
+
+/// 同nanosleep，支持TIMER_ABSTIME，出错时直接返回错误码而不是设置errno
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[cfg(target_os = "linux")]
+#[no_mangle]
+pub extern "C" fn clock_nanosleep(
+    clock_id: libc::clockid_t,
+    flags: libc::c_int,
+    rqtp: *const libc::timespec,
+    rmtp: *mut libc::timespec,
+) -> libc::c_int {
+    //获取原始系统函数clock_nanosleep
+    let original = original::CLOCK_NANOSLEEP.get();
+    //CPU时间等时钟无法用定时器模拟，交给原始函数处理
+    if switch::hooked() && matches!(clock_id, libc::CLOCK_REALTIME | libc::CLOCK_MONOTONIC) {
+        let absolute = flags & libc::TIMER_ABSTIME != 0;
+        if let Some(errno) = coroutine_sleep(clock_id, absolute, rqtp, rmtp) {
+            return errno;
+        }
+    }
+    //相当于libc::clock_nanosleep(clock_id, flags, rqtp, rmtp)
+    original(clock_id, flags, rqtp, rmtp)
+}
+
+//在协程中sleep到clock_id时钟的指定时间，absolute为false时rqtp是相对时间，
+//返回0或者错误码，被信号打断时通过rmtp返回剩余时间，不在协程中时返回None
+fn coroutine_sleep(
+    clock_id: libc::clockid_t,
+    absolute: bool,
+    rqtp: *const libc::timespec,
+    rmtp: *mut libc::timespec,
+) -> Option<libc::c_int> {
+    if rqtp.is_null() {
+        return Some(libc::EFAULT);
+    }
+    let rqtp = unsafe { &*rqtp };
+    if !(0..1_000_000_000).contains(&rqtp.tv_nsec) || (!absolute && rqtp.tv_sec < 0) {
+        return Some(libc::EINVAL);
+    }
+    let now = match clock_now(clock_id) {
+        Some(now) => now,
+        None => return Some(libc::EINVAL),
     };
-    //获取原始系统函数nanosleep
-    let original = unsafe {
-        match NANOSLEEP {
-            Some(original) => original,
-            None => {
-                let original =
-                    std::mem::transmute::<
-                        _,
-                        extern "C" fn(*const libc::timespec, *mut libc::timespec) -> libc::c_int,
-                    >(libc::dlsym(libc::RTLD_NEXT, "nanosleep".as_ptr() as _));
-                NANOSLEEP = Some(original);
-                original
+    //绝对时间早于1970年，说明已经到时了
+    let request = Duration::new(rqtp.tv_sec.max(0) as u64, rqtp.tv_nsec as u32);
+    let deadline = if absolute {
+        request
+    } else {
+        now.saturating_add(request)
+    };
+    loop {
+        let left = deadline.saturating_sub(clock_now(clock_id).unwrap_or(deadline));
+        if left.is_zero() {
+            return Some(0);
+        }
+        //调度器的定时器和clock_id可能不是同一个时钟，醒来后需要重新检查
+        match Scheduler::sleep(left) {
+            Ok(true) => {}
+            Ok(false) => return None,
+            Err(error) => {
+                if !absolute && !rmtp.is_null() {
+                    let left = deadline.saturating_sub(clock_now(clock_id).unwrap_or(deadline));
+                    unsafe {
+                        (*rmtp).tv_sec = left.as_secs() as libc::time_t;
+                        (*rmtp).tv_nsec = left.subsec_nanos() as libc::c_long;
+                    }
+                }
+                return Some(error.raw_os_error().unwrap_or(libc::EINTR));
             }
         }
+    }
+}
+
+fn clock_now(clock_id: libc::clockid_t) -> Option<Duration> {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
     };
-    //相当于libc::nanosleep(&rqtp, rmtp)
-    original(&rqtp, rmtp)
+    if unsafe { libc::clock_gettime(clock_id, &mut now) } < 0 {
+        return None;
+    }
+    Some(Duration::new(now.tv_sec as u64, now.tv_nsec as u32))
 }
 
+/// 开启或关闭当前线程的hook，返回之前的状态
 #[no_mangle]
-pub extern "C" fn coroutine_crate(pointer: &'static mut c_void) {
-    let coroutine = unsafe {
-        ptr::read_unaligned(
-            pointer as *mut _
-                as *mut Coroutine<dyn FnOnce(Option<*mut c_void>) -> Option<*mut c_void>>,
-        )
-    };
-    Scheduler::current().submit(coroutine)
+pub extern "C" fn set_hook_enabled(enabled: bool) -> bool {
+    switch::set_enabled(enabled)
+}
+
+#[no_mangle]
+pub extern "C" fn is_hook_enabled() -> bool {
+    switch::is_enabled()
+}
+
+/// 当前是否在协程中执行
+#[no_mangle]
+pub extern "C" fn in_coroutine() -> bool {
+    Coroutine::current().is_some()
 }
 
+/// 同pthread_key_create，destructor在协程退出时调用，不在协程中设置的值在线程退出时调用
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
-pub extern "C" fn try_schedule() -> &'static mut c_void {
-    let list = Scheduler::current().try_schedule();
-    let result = Box::leak(Box::new(list));
-    unsafe { &mut *(result as *mut _ as *mut c_void) }
+pub extern "C" fn co_key_create(
+    key: *mut libc::pthread_key_t,
+    destructor: Option<unsafe extern "C" fn(*mut c_void)>,
+) -> libc::c_int {
+    if key.is_null() {
+        return libc::EINVAL;
+    }
+    match local::key_create(destructor) {
+        Ok(created) => {
+            unsafe { *key = created };
+            0
+        }
+        Err(error) => error.raw_os_error().unwrap_or(libc::EAGAIN),
+    }
 }
 
 #[no_mangle]
-pub extern "C" fn schedule() -> &'static mut c_void {
-    let list = Scheduler::current().schedule();
-    let result = Box::leak(Box::new(list));
-    unsafe { &mut *(result as *mut _ as *mut c_void) }
+pub extern "C" fn co_key_delete(key: libc::pthread_key_t) -> libc::c_int {
+    match local::key_delete(key) {
+        Ok(()) => 0,
+        Err(error) => error.raw_os_error().unwrap_or(libc::EINVAL),
+    }
+}
+
+/// 同pthread_getspecific，在协程中时获取当前协程的值
+#[no_mangle]
+pub extern "C" fn co_getspecific(key: libc::pthread_key_t) -> *mut c_void {
+    local::get_specific(key)
+}
+
+/// 同pthread_setspecific，在协程中时设置当前协程的值
+#[no_mangle]
+pub extern "C" fn co_setspecific(key: libc::pthread_key_t, value: *const c_void) -> libc::c_int {
+    match local::set_specific(key, value) {
+        Ok(()) => 0,
+        Err(error) => error.raw_os_error().unwrap_or(libc::EINVAL),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use open_coroutine::coroutine::Coroutine;
+    use open_coroutine::scheduler::Scheduler;
+    use std::os::raw::c_void;
+    use std::ptr;
+
+    fn pipe() -> (libc::c_int, libc::c_int) {
+        let mut fds = [0; 2];
+        assert_eq!(0, unsafe { libc::pipe(fds.as_mut_ptr()) });
+        (fds[0], fds[1])
+    }
+
+    fn socketpair() -> (libc::c_int, libc::c_int) {
+        let mut fds = [0; 2];
+        assert_eq!(0, unsafe {
+            libc::socketpair(libc::AF_UNIX, libc::SOCK_STREAM, 0, fds.as_mut_ptr())
+        });
+        (fds[0], fds[1])
+    }
+
+    fn write_one(fd: libc::c_int) {
+        assert_eq!(1, unsafe {
+            libc::write(fd, [1u8].as_ptr() as *const c_void, 1)
+        });
+    }
+
+    #[test]
+    fn test_poll() {
+        let (reader, writer) = pipe();
+        let mut result = (0, 0);
+        let pointer = &mut result as *mut (libc::c_int, libc::c_short);
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let mut pollfd = libc::pollfd {
+                    fd: reader,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                let r = unsafe { libc::poll(&mut pollfd, 1, 1000) };
+                unsafe { *pointer = (r, pollfd.revents) };
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                write_one(writer);
+                param
+            },
+            None,
+        ));
+        let start = timer::now();
+        assert_eq!(2, scheduler.schedule().len());
+        assert!(timer::now() - start < 1_000_000_000);
+        assert_eq!((1, libc::POLLIN), result);
+        unsafe {
+            libc::close(reader);
+            libc::close(writer);
+        }
+    }
+
+    #[test]
+    fn test_poll_timeout() {
+        let (reader, writer) = pipe();
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let mut pollfd = libc::pollfd {
+                    fd: reader,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                assert_eq!(0, unsafe { libc::poll(&mut pollfd, 1, 100) });
+                unsafe { (*pointer).push("poll") };
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                unsafe { (*pointer).push("other") };
+                param
+            },
+            None,
+        ));
+        let start = timer::now();
+        assert_eq!(2, scheduler.schedule().len());
+        assert!(timer::now() - start >= 100_000_000);
+        //poll等待期间，线程可以执行别的协程
+        assert_eq!(vec!["other", "poll"], result);
+        unsafe {
+            libc::close(reader);
+            libc::close(writer);
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let (reader, writer) = pipe();
+        let mut result = (0, false);
+        let pointer = &mut result as *mut (libc::c_int, bool);
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| unsafe {
+                let mut readfds = std::mem::zeroed::<libc::fd_set>();
+                libc::FD_SET(reader, &mut readfds);
+                let mut timeout = libc::timeval {
+                    tv_sec: 1,
+                    tv_usec: 0,
+                };
+                let r = libc::select(
+                    reader + 1,
+                    &mut readfds,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    &mut timeout,
+                );
+                *pointer = (r, libc::FD_ISSET(reader, &readfds));
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                write_one(writer);
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!((1, true), result);
+        unsafe {
+            libc::close(reader);
+            libc::close(writer);
+        }
+    }
+
+    #[test]
+    fn test_select_invalid_timeout() {
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<(libc::c_int, Option<i32>)>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| unsafe {
+                for (tv_sec, tv_usec) in [(0, -1), (-1, 0)] {
+                    let mut timeout = libc::timeval { tv_sec, tv_usec };
+                    let r = libc::select(
+                        0,
+                        std::ptr::null_mut(),
+                        std::ptr::null_mut(),
+                        std::ptr::null_mut(),
+                        &mut timeout,
+                    );
+                    (*pointer).push((r, std::io::Error::last_os_error().raw_os_error()));
+                }
+                param
+            },
+            None,
+        ));
+        assert_eq!(1, scheduler.schedule().len());
+        assert_eq!(vec![(-1, Some(libc::EINVAL)); 2], result);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_epoll_wait() {
+        let (reader, writer) = pipe();
+        let epfd = unsafe { libc::epoll_create1(0) };
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: reader as u64,
+        };
+        assert_eq!(0, unsafe {
+            libc::epoll_ctl(epfd, libc::EPOLL_CTL_ADD, reader, &mut event)
+        });
+        let mut result = (0, 0);
+        let pointer = &mut result as *mut (libc::c_int, u64);
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let mut events = [libc::epoll_event { events: 0, u64: 0 }; 8];
+                let r = unsafe { libc::epoll_wait(epfd, events.as_mut_ptr(), 8, 1000) };
+                unsafe { *pointer = (r, events[0].u64) };
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                write_one(writer);
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!((1, reader as u64), result);
+        unsafe {
+            libc::close(epfd);
+            libc::close(reader);
+            libc::close(writer);
+        }
+    }
+
+    #[test]
+    fn test_close() {
+        let (reader, writer) = pipe();
+        let mut result = (0, None);
+        let pointer = &mut result as *mut (libc::c_int, Option<i32>);
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let mut pollfd = libc::pollfd {
+                    fd: reader,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                let r = unsafe { libc::poll(&mut pollfd, 1, 1000) };
+                let errno = std::io::Error::last_os_error().raw_os_error();
+                unsafe { *pointer = (r, errno) };
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                assert_eq!(0, unsafe { libc::close(reader) });
+                param
+            },
+            None,
+        ));
+        let start = timer::now();
+        assert_eq!(2, scheduler.schedule().len());
+        assert!(timer::now() - start < 1_000_000_000);
+        //等待者被唤醒，并收到EBADF
+        assert_eq!((-1, Some(libc::EBADF)), result);
+        unsafe { libc::close(writer) };
+    }
+
+    #[test]
+    fn test_user_nonblocking() {
+        let (reader, writer) = socketpair();
+        let mut result = (0isize, None, 0);
+        let pointer = &mut result as *mut (isize, Option<i32>, libc::c_int);
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                unsafe {
+                    let flags = libc::fcntl(reader, libc::F_GETFL);
+                    assert_eq!(
+                        0,
+                        libc::fcntl(reader, libc::F_SETFL, flags | libc::O_NONBLOCK)
+                    );
+                    //用户设置了非阻塞，直接返回EAGAIN，不挂起
+                    let mut buf = [0u8; 1];
+                    let r = libc::read(reader, buf.as_mut_ptr() as *mut c_void, 1);
+                    let errno = std::io::Error::last_os_error().raw_os_error();
+                    *pointer = (r, errno, libc::fcntl(reader, libc::F_GETFL));
+                }
+                param
+            },
+            None,
+        ));
+        assert_eq!(1, scheduler.schedule().len());
+        assert_eq!(-1, result.0);
+        assert_eq!(Some(libc::EAGAIN), result.1);
+        assert_ne!(0, result.2 & libc::O_NONBLOCK);
+        unsafe {
+            libc::close(reader);
+            libc::close(writer);
+        }
+    }
+
+    #[test]
+    fn test_read() {
+        let (reader, writer) = socketpair();
+        let mut result = (0isize, 0);
+        let pointer = &mut result as *mut (isize, libc::c_int);
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                unsafe {
+                    let mut buf = [0u8; 1];
+                    let r = libc::read(reader, buf.as_mut_ptr() as *mut c_void, 1);
+                    *pointer = (r, libc::fcntl(reader, libc::F_GETFL));
+                }
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                write_one(writer);
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(1, result.0);
+        //运行时设置的非阻塞对用户不可见
+        assert_eq!(0, result.1 & libc::O_NONBLOCK);
+        assert!(crate::fd::get(reader).unwrap().runtime_nonblocking);
+        unsafe {
+            libc::close(reader);
+            libc::close(writer);
+        }
+        assert!(crate::fd::get(reader).is_none());
+    }
+
+    #[test]
+    fn test_stale_fd_state() {
+        let (reader, writer) = socketpair();
+        let (pipe_reader, pipe_writer) = pipe();
+        //模拟运行时接管过的socket
+        assert!(crate::fd::force_nonblocking(reader));
+        assert!(crate::fd::force_nonblocking(writer));
+        //dup2覆盖了被接管的socket，新的fd是阻塞的pipe
+        assert_eq!(reader, unsafe { libc::dup2(pipe_reader, reader) });
+        assert!(crate::fd::get(reader).is_none());
+        //没有经过hook替换的fd，比如glibc内部的close之后复用了fd号
+        assert_eq!(writer, (crate::original::DUP2.get())(pipe_writer, writer));
+        assert!(crate::fd::get(writer).is_none());
+        let state = crate::fd::load(writer).unwrap();
+        assert!(!state.socket && !state.runtime_nonblocking && !state.user_nonblocking);
+        unsafe {
+            for fd in [reader, writer, pipe_reader, pipe_writer] {
+                libc::close(fd);
+            }
+        }
+    }
+
+    #[test]
+    fn test_recvmsg() {
+        let (receiver, sender) = socketpair();
+        let (reader, writer) = pipe();
+        let mut result = (0isize, -1);
+        let pointer = &mut result as *mut (isize, libc::c_int);
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| unsafe {
+                let mut buf = [0u8; 1];
+                let mut iov = libc::iovec {
+                    iov_base: buf.as_mut_ptr() as *mut c_void,
+                    iov_len: 1,
+                };
+                let mut control = [0u64; 4];
+                let mut msg: libc::msghdr = std::mem::zeroed();
+                msg.msg_iov = &mut iov;
+                msg.msg_iovlen = 1;
+                msg.msg_control = control.as_mut_ptr() as *mut c_void;
+                msg.msg_controllen = std::mem::size_of_val(&control) as _;
+                let r = libc::recvmsg(receiver, &mut msg, 0);
+                let cmsg = libc::CMSG_FIRSTHDR(&msg);
+                assert_eq!(libc::SCM_RIGHTS, (*cmsg).cmsg_type);
+                *pointer = (r, *(libc::CMSG_DATA(cmsg) as *const libc::c_int));
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| unsafe {
+                let mut iov = libc::iovec {
+                    iov_base: [1u8].as_ptr() as *mut c_void,
+                    iov_len: 1,
+                };
+                let mut control = [0u64; 4];
+                let mut msg: libc::msghdr = std::mem::zeroed();
+                msg.msg_iov = &mut iov;
+                msg.msg_iovlen = 1;
+                msg.msg_control = control.as_mut_ptr() as *mut c_void;
+                msg.msg_controllen = libc::CMSG_SPACE(4) as _;
+                let cmsg = libc::CMSG_FIRSTHDR(&msg);
+                (*cmsg).cmsg_level = libc::SOL_SOCKET;
+                (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+                (*cmsg).cmsg_len = libc::CMSG_LEN(4) as _;
+                *(libc::CMSG_DATA(cmsg) as *mut libc::c_int) = writer;
+                assert_eq!(1, libc::sendmsg(sender, &msg, 0));
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(1, result.0);
+        //收到的fd和writer指向同一个管道
+        write_one(result.1);
+        let mut buf = [0u8; 1];
+        assert_eq!(1, unsafe {
+            libc::read(reader, buf.as_mut_ptr() as *mut c_void, 1)
+        });
+        unsafe {
+            for fd in [receiver, sender, reader, writer, result.1] {
+                libc::close(fd);
+            }
+        }
+    }
+
+    #[test]
+    fn test_recv_timeout() {
+        let (reader, writer) = socketpair();
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                unsafe {
+                    let timeout = libc::timeval {
+                        tv_sec: 0,
+                        tv_usec: 100_000,
+                    };
+                    assert_eq!(
+                        0,
+                        libc::setsockopt(
+                            reader,
+                            libc::SOL_SOCKET,
+                            libc::SO_RCVTIMEO,
+                            &timeout as *const libc::timeval as *const c_void,
+                            std::mem::size_of::<libc::timeval>() as libc::socklen_t,
+                        )
+                    );
+                    let mut buf = [0u8; 1];
+                    let r = libc::recv(reader, buf.as_mut_ptr() as *mut c_void, 1, 0);
+                    assert_eq!(-1, r);
+                    assert_eq!(
+                        Some(libc::EAGAIN),
+                        std::io::Error::last_os_error().raw_os_error()
+                    );
+                    (*pointer).push("recv");
+                }
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                unsafe { (*pointer).push("other") };
+                param
+            },
+            None,
+        ));
+        let start = timer::now();
+        assert_eq!(2, scheduler.schedule().len());
+        assert!(timer::now() - start >= 100_000_000);
+        assert_eq!(vec!["other", "recv"], result);
+        unsafe {
+            libc::close(reader);
+            libc::close(writer);
+        }
+    }
+
+    #[test]
+    fn test_variadic() {
+        let path = std::env::temp_dir().join(format!("libhook-variadic-{}", std::process::id()));
+        let path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        unsafe {
+            //apple上可变参数经过C读取，mode只在O_CREAT时传入
+            let fd = libc::open(path.as_ptr(), libc::O_CREAT | libc::O_WRONLY, 0o600);
+            assert!(fd >= 0);
+            let mut stat: libc::stat = std::mem::zeroed();
+            assert_eq!(0, libc::fstat(fd, &mut stat));
+            assert_eq!(0o600, stat.st_mode & 0o777);
+            assert_eq!(0, libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC));
+            assert_eq!(libc::FD_CLOEXEC, libc::fcntl(fd, libc::F_GETFD));
+            libc::close(fd);
+            let fd = libc::open(path.as_ptr(), libc::O_RDONLY);
+            assert!(fd >= 0);
+            libc::close(fd);
+
+            let mut fds = [0; 2];
+            assert_eq!(0, libc::pipe(fds.as_mut_ptr()));
+            assert_eq!(3, libc::write(fds[1], b"abc".as_ptr() as *const c_void, 3));
+            let mut available: libc::c_int = 0;
+            assert_eq!(0, libc::ioctl(fds[0], libc::FIONREAD, &mut available));
+            assert_eq!(3, available);
+            libc::close(fds[0]);
+            libc::close(fds[1]);
+            libc::unlink(path.as_ptr());
+        }
+    }
+
+    #[test]
+    fn test_regular_file() {
+        let path = std::env::temp_dir().join(format!("libhook-{}", std::process::id()));
+        std::fs::write(&path, "hello").unwrap();
+        let path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        let mut result = (0isize, [0u8; 8], false, 0);
+        let pointer = &mut result as *mut (isize, [u8; 8], bool, libc::c_int);
+        let scheduler = Scheduler::current();
+        let file = path.as_ptr() as usize;
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                unsafe {
+                    //open、read在阻塞线程池中执行
+                    let fd = libc::open(file as *const libc::c_char, libc::O_RDONLY);
+                    assert!(fd >= 0);
+                    let r = libc::read(fd, (*pointer).1.as_mut_ptr() as *mut c_void, 8);
+                    (*pointer).0 = r;
+                    (*pointer).2 = crate::fd::get(fd).unwrap().regular;
+                    libc::close(fd);
+
+                    let mut hints: libc::addrinfo = std::mem::zeroed();
+                    hints.ai_flags = libc::AI_NUMERICHOST;
+                    let mut res = std::ptr::null_mut();
+                    (*pointer).3 = libc::getaddrinfo(
+                        "127.0.0.1\0".as_ptr() as *const libc::c_char,
+                        std::ptr::null(),
+                        &hints,
+                        &mut res,
+                    );
+                    libc::freeaddrinfo(res);
+                }
+                param
+            },
+            None,
+        ));
+        assert_eq!(1, scheduler.schedule().len());
+        assert_eq!(5, result.0);
+        assert_eq!(b"hello", &result.1[..5]);
+        assert!(result.2);
+        assert_eq!(0, result.3);
+        unsafe { libc::unlink(path.as_ptr()) };
+    }
+
+    #[test]
+    fn test_mutex() {
+        let mutex = Box::leak(Box::new(libc::PTHREAD_MUTEX_INITIALIZER)) as *mut _ as usize;
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let mutex = mutex as *mut libc::pthread_mutex_t;
+                unsafe {
+                    assert_eq!(0, libc::pthread_mutex_lock(mutex));
+                    (*pointer).push("lock1");
+                    //持有锁期间挂起
+                    libc::poll(std::ptr::null_mut(), 0, 50);
+                    (*pointer).push("unlock1");
+                    assert_eq!(0, libc::pthread_mutex_unlock(mutex));
+                }
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let mutex = mutex as *mut libc::pthread_mutex_t;
+                unsafe {
+                    //同一线程上的另一个协程持有锁，挂起当前协程而不是死锁
+                    assert_eq!(0, libc::pthread_mutex_lock(mutex));
+                    (*pointer).push("lock2");
+                    assert_eq!(0, libc::pthread_mutex_unlock(mutex));
+                }
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(vec!["lock1", "unlock1", "lock2"], result);
+    }
+
+    #[test]
+    fn test_mutex_relock() {
+        let mutex = Box::leak(Box::new(libc::PTHREAD_MUTEX_INITIALIZER)) as *mut _ as usize;
+        unsafe {
+            let mut attr: libc::pthread_mutexattr_t = std::mem::zeroed();
+            assert_eq!(0, libc::pthread_mutexattr_init(&mut attr));
+            assert_eq!(
+                0,
+                libc::pthread_mutexattr_settype(&mut attr, libc::PTHREAD_MUTEX_ERRORCHECK)
+            );
+            assert_eq!(
+                0,
+                libc::pthread_mutex_init(mutex as *mut libc::pthread_mutex_t, &attr)
+            );
+        }
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<libc::c_int>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let mutex = mutex as *mut libc::pthread_mutex_t;
+                unsafe {
+                    (*pointer).push(libc::pthread_mutex_lock(mutex));
+                    //持有锁的协程重复加锁，和原始函数一样返回EDEADLK
+                    (*pointer).push(libc::pthread_mutex_lock(mutex));
+                    (*pointer).push(libc::pthread_mutex_unlock(mutex));
+                }
+                param
+            },
+            None,
+        ));
+        assert_eq!(1, scheduler.schedule().len());
+        assert_eq!(vec![0, libc::EDEADLK, 0], result);
+    }
+
+    #[test]
+    fn test_cond_fallback() {
+        let mutex = Box::leak(Box::new(libc::PTHREAD_MUTEX_INITIALIZER)) as *mut _ as usize;
+        let cond = Box::leak(Box::new(libc::PTHREAD_COND_INITIALIZER)) as *mut _ as usize;
+        //协程没有提交给调度器，无法被唤醒，交给原始函数阻塞线程
+        let coroutine = Coroutine::new(
+            16 * 1024,
+            move |_| {
+                let mutex = mutex as *mut libc::pthread_mutex_t;
+                let cond = cond as *mut libc::pthread_cond_t;
+                unsafe {
+                    assert_eq!(0, libc::pthread_mutex_lock(mutex));
+                    let mut abstime = libc::timespec {
+                        tv_sec: 0,
+                        tv_nsec: 0,
+                    };
+                    libc::clock_gettime(libc::CLOCK_REALTIME, &mut abstime);
+                    abstime.tv_sec += 1;
+                    let r = libc::pthread_cond_timedwait(cond, mutex, &abstime);
+                    assert_eq!(0, libc::pthread_mutex_unlock(mutex));
+                    Some(r as usize as *mut c_void)
+                }
+            },
+            None,
+        );
+        let signal = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            let mutex = mutex as *mut libc::pthread_mutex_t;
+            unsafe {
+                assert_eq!(0, libc::pthread_mutex_lock(mutex));
+                assert_eq!(
+                    0,
+                    libc::pthread_cond_signal(cond as *mut libc::pthread_cond_t)
+                );
+                assert_eq!(0, libc::pthread_mutex_unlock(mutex));
+            }
+        });
+        assert_eq!(Some(0), coroutine.resume().get_result().map(|r| r as usize));
+        signal.join().unwrap();
+    }
+
+    #[test]
+    fn test_cond() {
+        let mutex = Box::leak(Box::new(libc::PTHREAD_MUTEX_INITIALIZER)) as *mut _ as usize;
+        let cond = Box::leak(Box::new(libc::PTHREAD_COND_INITIALIZER)) as *mut _ as usize;
+        let mut result = (Vec::new(), false, 0);
+        let pointer = &mut result as *mut (Vec<&str>, bool, libc::c_int);
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let mutex = mutex as *mut libc::pthread_mutex_t;
+                let cond = cond as *mut libc::pthread_cond_t;
+                unsafe {
+                    assert_eq!(0, libc::pthread_mutex_lock(mutex));
+                    //超时
+                    let mut abstime = libc::timespec {
+                        tv_sec: 0,
+                        tv_nsec: 0,
+                    };
+                    libc::clock_gettime(libc::CLOCK_REALTIME, &mut abstime);
+                    abstime.tv_nsec += 10_000_000;
+                    if abstime.tv_nsec >= 1_000_000_000 {
+                        abstime.tv_sec += 1;
+                        abstime.tv_nsec -= 1_000_000_000;
+                    }
+                    (*pointer).2 = libc::pthread_cond_timedwait(cond, mutex, &abstime);
+                    (*pointer).0.push("timeout");
+                    while !std::ptr::read_volatile(&(*pointer).1) {
+                        assert_eq!(0, libc::pthread_cond_wait(cond, mutex));
+                    }
+                    (*pointer).0.push("signaled");
+                    assert_eq!(0, libc::pthread_mutex_unlock(mutex));
+                }
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let mutex = mutex as *mut libc::pthread_mutex_t;
+                let cond = cond as *mut libc::pthread_cond_t;
+                unsafe {
+                    //等待另一个协程超时
+                    libc::poll(std::ptr::null_mut(), 0, 50);
+                    assert_eq!(0, libc::pthread_mutex_lock(mutex));
+                    (*pointer).1 = true;
+                    (*pointer).0.push("signal");
+                    assert_eq!(0, libc::pthread_cond_signal(cond));
+                    assert_eq!(0, libc::pthread_mutex_unlock(mutex));
+                }
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(libc::ETIMEDOUT, result.2);
+        assert_eq!(vec!["timeout", "signal", "signaled"], result.0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sem() {
+        let sem = Box::leak(Box::new(unsafe { std::mem::zeroed::<libc::sem_t>() })) as *mut _;
+        assert_eq!(0, unsafe { libc::sem_init(sem, 0, 0) });
+        let sem = sem as usize;
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                assert_eq!(0, unsafe { libc::sem_wait(sem as *mut libc::sem_t) });
+                unsafe { (*pointer).push("wait") };
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                unsafe {
+                    (*pointer).push("post");
+                    assert_eq!(0, libc::sem_post(sem as *mut libc::sem_t));
+                }
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(vec!["post", "wait"], result);
+    }
+
+    #[test]
+    fn test_disable_hook() {
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                assert!(crate::in_coroutine());
+                //关闭hook后poll会阻塞线程
+                let enabled = crate::set_hook_enabled(false);
+                unsafe { libc::poll(std::ptr::null_mut(), 0, 50) };
+                crate::set_hook_enabled(enabled);
+                unsafe { (*pointer).push("poll") };
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                unsafe { (*pointer).push("other") };
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(vec!["poll", "other"], result);
+        assert!(!crate::in_coroutine());
+    }
+
+    #[test]
+    fn test_sleep() {
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                assert_eq!(0, unsafe { libc::usleep(100_000) });
+                unsafe { (*pointer).push("usleep") };
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let rqtp = libc::timespec {
+                    tv_sec: 0,
+                    tv_nsec: 50_000_000,
+                };
+                //rmtp可以为空
+                assert_eq!(0, unsafe { libc::nanosleep(&rqtp, std::ptr::null_mut()) });
+                unsafe { (*pointer).push("nanosleep") };
+                param
+            },
+            None,
+        ));
+        let start = timer::now();
+        assert_eq!(2, scheduler.schedule().len());
+        assert!(timer::now() - start >= 100_000_000);
+        //两个协程同时sleep，线程没有被阻塞
+        assert_eq!(vec!["nanosleep", "usleep"], result);
+    }
+
+    #[test]
+    fn test_sleep_invalid() {
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let rqtp = libc::timespec {
+                    tv_sec: 0,
+                    tv_nsec: 1_000_000_000,
+                };
+                assert_eq!(-1, unsafe { libc::nanosleep(&rqtp, std::ptr::null_mut()) });
+                assert_eq!(
+                    Some(libc::EINVAL),
+                    std::io::Error::last_os_error().raw_os_error()
+                );
+                param
+            },
+            None,
+        ));
+        assert_eq!(1, scheduler.schedule().len());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_clock_nanosleep() {
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let mut rqtp = libc::timespec {
+                    tv_sec: 0,
+                    tv_nsec: 0,
+                };
+                unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut rqtp) };
+                rqtp.tv_nsec += 50_000_000;
+                if rqtp.tv_nsec >= 1_000_000_000 {
+                    rqtp.tv_sec += 1;
+                    rqtp.tv_nsec -= 1_000_000_000;
+                }
+                let r = unsafe {
+                    libc::clock_nanosleep(
+                        libc::CLOCK_MONOTONIC,
+                        libc::TIMER_ABSTIME,
+                        &rqtp,
+                        std::ptr::null_mut(),
+                    )
+                };
+                assert_eq!(0, r);
+                let mut now = libc::timespec {
+                    tv_sec: 0,
+                    tv_nsec: 0,
+                };
+                unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+                assert!((now.tv_sec, now.tv_nsec) >= (rqtp.tv_sec, rqtp.tv_nsec));
+                unsafe { (*pointer).push("clock_nanosleep") };
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                unsafe { (*pointer).push("other") };
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(vec!["other", "clock_nanosleep"], result);
+    }
+
+    extern "C" fn ignore_signal(_: libc::c_int) {}
+
+    #[test]
+    fn test_sleep_interrupted() {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = ignore_signal as *const () as usize;
+            assert_eq!(
+                0,
+                libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut())
+            );
+        }
+        let mut result = (
+            0,
+            libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+        );
+        let pointer = &mut result as *mut (libc::c_int, libc::timespec);
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let rqtp = libc::timespec {
+                    tv_sec: 2,
+                    tv_nsec: 0,
+                };
+                unsafe {
+                    assert_eq!(-1, libc::nanosleep(&rqtp, &mut (*pointer).1));
+                    (*pointer).0 = std::io::Error::last_os_error().raw_os_error().unwrap();
+                }
+                param
+            },
+            None,
+        ));
+        let thread = unsafe { libc::pthread_self() } as usize;
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            unsafe { libc::pthread_kill(thread as libc::pthread_t, libc::SIGUSR1) };
+        });
+        assert_eq!(1, scheduler.schedule().len());
+        handle.join().unwrap();
+        assert_eq!(libc::EINTR, result.0);
+        //剩余时间约为1.9秒
+        assert_eq!(1, result.1.tv_sec);
+        assert!(result.1.tv_nsec > 500_000_000);
+    }
+
+    //信号在另一个协程执行期间到达，线程没有阻塞在epoll_wait里，sleep仍然要返回EINTR
+    #[test]
+    fn test_sleep_signal_while_running() {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = ignore_signal as *const () as usize;
+            assert_eq!(
+                0,
+                libc::sigaction(libc::SIGUSR2, &action, std::ptr::null_mut())
+            );
+            //oldact里返回的是注册的处理函数，不是内部的trampoline
+            let mut old: libc::sigaction = std::mem::zeroed();
+            assert_eq!(
+                0,
+                libc::sigaction(libc::SIGUSR2, std::ptr::null(), &mut old)
+            );
+            assert_eq!(ignore_signal as *const () as usize, old.sa_sigaction);
+            assert_eq!(0, old.sa_flags & libc::SA_SIGINFO);
+        }
+        let mut result = 0;
+        let pointer = &mut result as *mut libc::c_int;
+        let thread = unsafe { libc::pthread_self() } as usize;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let rqtp = libc::timespec {
+                    tv_sec: 2,
+                    tv_nsec: 0,
+                };
+                unsafe {
+                    assert_eq!(-1, libc::nanosleep(&rqtp, std::ptr::null_mut()));
+                    *pointer = std::io::Error::last_os_error().raw_os_error().unwrap();
+                }
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                //不让出地占用线程，期间信号被处理
+                unsafe { libc::pthread_kill(thread as libc::pthread_t, libc::SIGUSR2) };
+                let start = std::time::Instant::now();
+                while start.elapsed() < std::time::Duration::from_millis(100) {
+                    std::hint::spin_loop();
+                }
+                param
+            },
+            None,
+        ));
+        let start = std::time::Instant::now();
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(libc::EINTR, result);
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_specific() {
+        let mut key = 0;
+        assert_eq!(0, crate::co_key_create(&mut key, None));
+        assert_eq!(0, crate::co_setspecific(key, 10 as *const c_void));
+        let mut result = 0usize;
+        let pointer = &mut result as *mut usize;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                //协程里看不到线程上设置的值
+                assert!(crate::co_getspecific(key).is_null());
+                assert_eq!(0, crate::co_setspecific(key, 20 as *const c_void));
+                unsafe { *pointer = crate::co_getspecific(key) as usize };
+                param
+            },
+            None,
+        ));
+        assert_eq!(1, scheduler.schedule().len());
+        assert_eq!(20, result);
+        assert_eq!(10, crate::co_getspecific(key) as usize);
+        assert_eq!(0, crate::co_key_delete(key));
+        assert_eq!(libc::EINVAL, crate::co_setspecific(key, ptr::null()));
+    }
 }
@@ -0,0 +1,198 @@
+use std::ffi::CStr;
+use std::os::raw::c_void;
+use std::sync::OnceLock;
+
+/// 被hook的原始系统函数，第一次使用时通过dlsym(RTLD_NEXT)解析，之后直接读缓存，
+/// 多线程并发解析也是安全的
+#[derive(Debug)]
+pub struct Symbol<F> {
+    name: &'static CStr,
+    //glibc中同一符号有多个版本时，指定要用的版本
+    version: Option<&'static CStr>,
+    function: OnceLock<F>,
+}
+
+impl<F: Copy> Symbol<F> {
+    pub const fn new(name: &'static CStr, version: Option<&'static CStr>) -> Self {
+        Symbol {
+            name,
+            version,
+            function: OnceLock::new(),
+        }
+    }
+
+    pub fn name(&self) -> &'static CStr {
+        self.name
+    }
+
+    /// 原始函数，无法解析时panic
+    pub fn get(&self) -> F {
+        if let Some(function) = self.resolve() {
+            return function;
+        }
+        panic!(
+            "open-coroutine: failed to resolve original function {}: {}",
+            self.name.to_string_lossy(),
+            dlerror()
+        )
+    }
+
+    /// 解析原始函数，无法解析时返回None，下次调用会重新尝试
+    pub fn resolve(&self) -> Option<F> {
+        if let Some(function) = self.function.get() {
+            return Some(*function);
+        }
+        let pointer = self.lookup();
+        if pointer.is_null() {
+            return None;
+        }
+        assert_eq!(
+            std::mem::size_of::<F>(),
+            std::mem::size_of::<*mut c_void>(),
+            "{:?} is not a function pointer",
+            self.name
+        );
+        //F是函数指针类型，由宏保证
+        let function = unsafe { std::mem::transmute_copy::<*mut c_void, F>(&pointer) };
+        Some(*self.function.get_or_init(|| function))
+    }
+
+    fn lookup(&self) -> *mut c_void {
+        #[cfg(all(target_os = "linux", target_env = "gnu"))]
+        if let Some(version) = self.version {
+            let pointer =
+                unsafe { libc::dlvsym(libc::RTLD_NEXT, self.name.as_ptr(), version.as_ptr()) };
+            if !pointer.is_null() {
+                return pointer;
+            }
+        }
+        #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+        let _ = self.version;
+        unsafe { libc::dlsym(libc::RTLD_NEXT, self.name.as_ptr()) }
+    }
+}
+
+fn dlerror() -> String {
+    let error = unsafe { libc::dlerror() };
+    if error.is_null() {
+        return "symbol not found".to_string();
+    }
+    unsafe { CStr::from_ptr(error) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+//const上下文中把带\0的字符串转为CStr，cbindgen依赖的syn无法解析c"..."字面量
+const fn c_str(bytes: &'static [u8]) -> &'static CStr {
+    match CStr::from_bytes_with_nul(bytes) {
+        Ok(name) => name,
+        Err(_) => panic!("symbol name must not contain \\0"),
+    }
+}
+
+/// 声明原始系统函数，格式为`NAME: "symbol" [@ "version"] => 函数类型;`，
+/// 新增hook时只需在下面的表中加一行
+macro_rules! originals {
+    ($(
+        $(#[$meta:meta])*
+        $name:ident: $symbol:literal $(@ $version:literal)? => $ty:ty;
+    )*) => {
+        $(
+            $(#[$meta])*
+            pub static $name: Symbol<$ty> = Symbol::new(
+                c_str(concat!($symbol, "\0").as_bytes()),
+                originals!(@version $($version)?),
+            );
+        )*
+
+        /// 解析所有原始函数，返回无法解析的符号
+        pub fn unresolved() -> Vec<&'static CStr> {
+            let mut unresolved = Vec::new();
+            $(
+                $(#[$meta])*
+                if $name.resolve().is_none() {
+                    unresolved.push($name.name());
+                }
+            )*
+            unresolved
+        }
+    };
+    (@version) => {
+        None
+    };
+    (@version $version:literal) => {
+        Some(c_str(concat!($version, "\0").as_bytes()))
+    };
+}
+
+originals! {
+    POLL: "poll" => extern "C" fn(*mut libc::pollfd, libc::nfds_t, libc::c_int) -> libc::c_int;
+    SELECT: "select" => extern "C" fn(libc::c_int, *mut libc::fd_set, *mut libc::fd_set, *mut libc::fd_set, *mut libc::timeval) -> libc::c_int;
+    #[cfg(target_os = "linux")]
+    EPOLL_WAIT: "epoll_wait" => extern "C" fn(libc::c_int, *mut libc::epoll_event, libc::c_int, libc::c_int) -> libc::c_int;
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "tvos",
+        target_os = "watchos"
+    ))]
+    KEVENT: "kevent" => extern "C" fn(libc::c_int, *const libc::kevent, libc::c_int, *mut libc::kevent, libc::c_int, *const libc::timespec) -> libc::c_int;
+    CLOSE: "close" => extern "C" fn(libc::c_int) -> libc::c_int;
+    SHUTDOWN: "shutdown" => extern "C" fn(libc::c_int, libc::c_int) -> libc::c_int;
+    DUP: "dup" => extern "C" fn(libc::c_int) -> libc::c_int;
+    DUP2: "dup2" => extern "C" fn(libc::c_int, libc::c_int) -> libc::c_int;
+    #[cfg(target_os = "linux")]
+    DUP3: "dup3" => extern "C" fn(libc::c_int, libc::c_int, libc::c_int) -> libc::c_int;
+    FCNTL: "fcntl" => unsafe extern "C" fn(libc::c_int, libc::c_int, ...) -> libc::c_int;
+    IOCTL: "ioctl" => unsafe extern "C" fn(libc::c_int, libc::c_ulong, ...) -> libc::c_int;
+    SETSOCKOPT: "setsockopt" => extern "C" fn(libc::c_int, libc::c_int, libc::c_int, *const c_void, libc::socklen_t) -> libc::c_int;
+    ACCEPT: "accept" => extern "C" fn(libc::c_int, *mut libc::sockaddr, *mut libc::socklen_t) -> libc::c_int;
+    CONNECT: "connect" => extern "C" fn(libc::c_int, *const libc::sockaddr, libc::socklen_t) -> libc::c_int;
+    READ: "read" => extern "C" fn(libc::c_int, *mut c_void, libc::size_t) -> libc::ssize_t;
+    READV: "readv" => extern "C" fn(libc::c_int, *const libc::iovec, libc::c_int) -> libc::ssize_t;
+    RECV: "recv" => extern "C" fn(libc::c_int, *mut c_void, libc::size_t, libc::c_int) -> libc::ssize_t;
+    RECVFROM: "recvfrom" => extern "C" fn(libc::c_int, *mut c_void, libc::size_t, libc::c_int, *mut libc::sockaddr, *mut libc::socklen_t) -> libc::ssize_t;
+    RECVMSG: "recvmsg" => extern "C" fn(libc::c_int, *mut libc::msghdr, libc::c_int) -> libc::ssize_t;
+    WRITE: "write" => extern "C" fn(libc::c_int, *const c_void, libc::size_t) -> libc::ssize_t;
+    WRITEV: "writev" => extern "C" fn(libc::c_int, *const libc::iovec, libc::c_int) -> libc::ssize_t;
+    SEND: "send" => extern "C" fn(libc::c_int, *const c_void, libc::size_t, libc::c_int) -> libc::ssize_t;
+    SENDTO: "sendto" => extern "C" fn(libc::c_int, *const c_void, libc::size_t, libc::c_int, *const libc::sockaddr, libc::socklen_t) -> libc::ssize_t;
+    SENDMSG: "sendmsg" => extern "C" fn(libc::c_int, *const libc::msghdr, libc::c_int) -> libc::ssize_t;
+    OPEN: "open" => unsafe extern "C" fn(*const libc::c_char, libc::c_int, ...) -> libc::c_int;
+    #[cfg(target_os = "linux")]
+    OPEN64: "open64" => unsafe extern "C" fn(*const libc::c_char, libc::c_int, ...) -> libc::c_int;
+    FSYNC: "fsync" => extern "C" fn(libc::c_int) -> libc::c_int;
+    STAT: "stat" => extern "C" fn(*const libc::c_char, *mut libc::stat) -> libc::c_int;
+    GETADDRINFO: "getaddrinfo" => extern "C" fn(*const libc::c_char, *const libc::c_char, *const libc::addrinfo, *mut *mut libc::addrinfo) -> libc::c_int;
+    PTHREAD_CREATE: "pthread_create" => extern "C" fn(*mut libc::pthread_t, *const libc::pthread_attr_t, extern "C" fn(*mut c_void) -> *mut c_void, *mut c_void) -> libc::c_int;
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    LIBC_START_MAIN: "__libc_start_main" => extern "C" fn(crate::Main, libc::c_int, *mut *mut libc::c_char, *const c_void, *const c_void, *const c_void, *mut c_void) -> libc::c_int;
+    PTHREAD_MUTEX_LOCK: "pthread_mutex_lock" => extern "C" fn(*mut libc::pthread_mutex_t) -> libc::c_int;
+    PTHREAD_MUTEX_UNLOCK: "pthread_mutex_unlock" => extern "C" fn(*mut libc::pthread_mutex_t) -> libc::c_int;
+    PTHREAD_COND_WAIT: "pthread_cond_wait" @ "GLIBC_2.3.2" => extern "C" fn(*mut libc::pthread_cond_t, *mut libc::pthread_mutex_t) -> libc::c_int;
+    PTHREAD_COND_TIMEDWAIT: "pthread_cond_timedwait" @ "GLIBC_2.3.2" => extern "C" fn(*mut libc::pthread_cond_t, *mut libc::pthread_mutex_t, *const libc::timespec) -> libc::c_int;
+    PTHREAD_COND_SIGNAL: "pthread_cond_signal" @ "GLIBC_2.3.2" => extern "C" fn(*mut libc::pthread_cond_t) -> libc::c_int;
+    PTHREAD_COND_BROADCAST: "pthread_cond_broadcast" @ "GLIBC_2.3.2" => extern "C" fn(*mut libc::pthread_cond_t) -> libc::c_int;
+    SEM_WAIT: "sem_wait" => extern "C" fn(*mut libc::sem_t) -> libc::c_int;
+    SIGACTION: "sigaction" => extern "C" fn(libc::c_int, *const libc::sigaction, *mut libc::sigaction) -> libc::c_int;
+    NANOSLEEP: "nanosleep" => extern "C" fn(*const libc::timespec, *mut libc::timespec) -> libc::c_int;
+    #[cfg(target_os = "linux")]
+    CLOCK_NANOSLEEP: "clock_nanosleep" => extern "C" fn(
+        libc::clockid_t,
+        libc::c_int,
+        *const libc::timespec,
+        *mut libc::timespec,
+    ) -> libc::c_int;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::original;
+
+    #[test]
+    fn test_unresolved() {
+        assert!(original::unresolved().is_empty());
+        assert_eq!(-1, (original::CLOSE.get())(-1));
+        assert_eq!("close", original::CLOSE.name().to_str().unwrap());
+    }
+}
@@ -0,0 +1,170 @@
+use open_coroutine::coroutine::{Coroutine, UserFunction};
+use open_coroutine::scheduler::Scheduler;
+use std::ffi::CStr;
+use std::os::raw::c_void;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+/// 环境变量OPEN_COROUTINE_THREADS，决定哪些线程在协程中执行
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Policy {
+    /// 默认，只有用户显式创建的协程会被hook接管
+    None,
+    /// 通过pthread_create创建的线程在协程中执行
+    Spawned,
+    /// 在Spawned的基础上，main函数也在协程中执行
+    All,
+}
+
+impl Policy {
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "spawned" => Policy::Spawned,
+            "all" => Policy::All,
+            _ => Policy::None,
+        }
+    }
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(Policy::None as u8);
+
+//环境变量OPEN_COROUTINE_STACK_SIZE，被接管的线程的协程栈大小
+static STACK_SIZE: AtomicUsize = AtomicUsize::new(2 * 1024 * 1024);
+
+pub fn policy() -> Policy {
+    match POLICY.load(Ordering::Acquire) {
+        1 => Policy::Spawned,
+        2 => Policy::All,
+        _ => Policy::None,
+    }
+}
+
+fn getenv(name: &str) -> Option<String> {
+    let value = unsafe { libc::getenv(name.as_ptr() as _) };
+    if value.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(value) }
+        .to_str()
+        .ok()
+        .map(str::to_string)
+}
+
+//加载时在main之前执行，只有LD_PRELOAD=libhook.so时才会生效
+#[used]
+#[cfg_attr(target_os = "linux", link_section = ".init_array")]
+#[cfg_attr(
+    any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "tvos",
+        target_os = "watchos"
+    ),
+    link_section = "__DATA,__mod_init_func"
+)]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+//LD_PRELOAD(macOS上是DYLD_INSERT_LIBRARIES)里有没有当前这个库，直接链接libhook时不接管线程
+fn preloaded() -> bool {
+    let name = if cfg!(target_os = "linux") {
+        "LD_PRELOAD\0"
+    } else {
+        "DYLD_INSERT_LIBRARIES\0"
+    };
+    let preload = match getenv(name) {
+        Some(preload) => preload,
+        None => return false,
+    };
+    let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+    if unsafe { libc::dladdr(constructor as *const c_void, &mut info) } == 0
+        || info.dli_fname.is_null()
+    {
+        return false;
+    }
+    let path = unsafe { CStr::from_ptr(info.dli_fname) }.to_string_lossy();
+    let file = Path::new(path.as_ref()).file_name();
+    preload
+        .split([':', ' '])
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| Path::new(entry).file_name() == file)
+}
+
+extern "C" fn constructor() {
+    if !preloaded() {
+        return;
+    }
+    let policy =
+        getenv("OPEN_COROUTINE_THREADS\0").map_or(Policy::None, |value| Policy::parse(&value));
+    if let Some(size) =
+        getenv("OPEN_COROUTINE_STACK_SIZE\0").and_then(|size| size.trim().parse().ok())
+    {
+        STACK_SIZE.store(size, Ordering::Release);
+    }
+    POLICY.store(policy as u8, Ordering::Release);
+    if policy != Policy::None {
+        //提前解析所有原始函数，尽早暴露无法解析的符号
+        for name in crate::original::unresolved() {
+            eprintln!(
+                "open-coroutine: failed to resolve original function {}",
+                name.to_string_lossy()
+            );
+        }
+        //初始化调度器
+        let _ = Scheduler::global();
+        let _ = Scheduler::current();
+    }
+}
+
+/// 在当前线程的调度器上以协程的方式执行f，直到所有协程执行完毕
+pub fn run_in_coroutine(f: impl FnOnce() -> *mut c_void + 'static) -> *mut c_void {
+    let scheduler = Scheduler::current();
+    let coroutine = Coroutine::new(STACK_SIZE.load(Ordering::Acquire), move |_| Some(f()), None);
+    let handle = Coroutine::<UserFunction>::from_raw(coroutine.as_ptr());
+    scheduler.execute(coroutine);
+    scheduler.schedule();
+    handle.get_result().unwrap_or(ptr::null_mut())
+}
+
+/// 被接管的线程的入口
+pub extern "C" fn coroutine_start(start: *mut c_void) -> *mut c_void {
+    let start = unsafe {
+        Box::from_raw(start as *mut (extern "C" fn(*mut c_void) -> *mut c_void, *mut c_void))
+    };
+    let (routine, arg) = *start;
+    run_in_coroutine(move || routine(arg))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::preload::{coroutine_start, Policy};
+    use open_coroutine::coroutine::Coroutine;
+    use std::os::raw::c_void;
+
+    extern "C" fn routine(arg: *mut c_void) -> *mut c_void {
+        assert!(Coroutine::current().is_some());
+        (arg as usize + 1) as *mut c_void
+    }
+
+    #[test]
+    fn test_policy() {
+        assert_eq!(Policy::None, Policy::parse(""));
+        assert_eq!(Policy::None, Policy::parse("none"));
+        assert_eq!(Policy::Spawned, Policy::parse("spawned"));
+        assert_eq!(Policy::All, Policy::parse(" ALL "));
+    }
+
+    #[test]
+    fn test_coroutine_start() {
+        let start = Box::into_raw(Box::new((
+            routine as extern "C" fn(*mut c_void) -> *mut c_void,
+            41usize as *mut c_void,
+        )));
+        let start = start as usize;
+        let r = std::thread::spawn(move || coroutine_start(start as *mut c_void) as usize)
+            .join()
+            .unwrap();
+        assert_eq!(42, r);
+    }
+}
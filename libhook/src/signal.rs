@@ -0,0 +1,82 @@
+use open_coroutine::scheduler::Scheduler;
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+//信号编号的上限，linux是65，macOS是32
+const SIGNALS: usize = 128;
+
+//通过hook注册的处理函数，由trampoline转发
+static HANDLERS: [AtomicUsize; SIGNALS] = [const { AtomicUsize::new(0) }; SIGNALS];
+
+//处理函数是否按SA_SIGINFO的方式调用
+static SIGINFO: [AtomicBool; SIGNALS] = [const { AtomicBool::new(false) }; SIGNALS];
+
+/// 用户注册的处理函数和是否带SA_SIGINFO
+pub type Handler = (libc::sighandler_t, bool);
+
+//同步产生的故障信号不会打断sleep，也不能干扰栈溢出检测，SIGKILL和SIGSTOP不能注册
+fn wrappable(signum: libc::c_int, handler: libc::sighandler_t) -> bool {
+    (1..SIGNALS as libc::c_int).contains(&signum)
+        && !matches!(
+            signum,
+            libc::SIGSEGV
+                | libc::SIGBUS
+                | libc::SIGILL
+                | libc::SIGFPE
+                | libc::SIGKILL
+                | libc::SIGSTOP
+        )
+        && handler != libc::SIG_DFL
+        && handler != libc::SIG_IGN
+}
+
+//先记录线程处理了信号，再调用用户的处理函数
+extern "C" fn trampoline(signum: libc::c_int, info: *mut libc::siginfo_t, context: *mut c_void) {
+    Scheduler::signaled();
+    let handler = HANDLERS[signum as usize].load(Ordering::Acquire);
+    if handler == 0 {
+        return;
+    }
+    if SIGINFO[signum as usize].load(Ordering::Acquire) {
+        let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut c_void) =
+            unsafe { std::mem::transmute(handler) };
+        handler(signum, info, context);
+    } else {
+        let handler: extern "C" fn(libc::c_int) = unsafe { std::mem::transmute(handler) };
+        handler(signum);
+    }
+}
+
+/// 当前通过hook注册的处理函数
+pub fn handler(signum: libc::c_int) -> Handler {
+    match usize::try_from(signum) {
+        Ok(i) if i < SIGNALS => (
+            HANDLERS[i].load(Ordering::Acquire),
+            SIGINFO[i].load(Ordering::Acquire),
+        ),
+        _ => (0, false),
+    }
+}
+
+/// 把act里用户的处理函数换成trampoline，不需要替换时返回None
+pub fn wrap(signum: libc::c_int, act: &libc::sigaction) -> Option<libc::sigaction> {
+    if !wrappable(signum, act.sa_sigaction) {
+        return None;
+    }
+    HANDLERS[signum as usize].store(act.sa_sigaction, Ordering::Release);
+    SIGINFO[signum as usize].store(act.sa_flags & libc::SA_SIGINFO != 0, Ordering::Release);
+    let mut wrapped = *act;
+    wrapped.sa_sigaction = trampoline as *const () as libc::sighandler_t;
+    wrapped.sa_flags |= libc::SA_SIGINFO;
+    Some(wrapped)
+}
+
+/// 把原始sigaction返回的trampoline还原成替换之前用户的处理函数
+pub fn unwrap(old: &mut libc::sigaction, previous: Handler) {
+    if old.sa_sigaction == trampoline as *const () as libc::sighandler_t {
+        old.sa_sigaction = previous.0;
+        if !previous.1 {
+            old.sa_flags &= !libc::SA_SIGINFO;
+        }
+    }
+}
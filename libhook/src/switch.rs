@@ -0,0 +1,38 @@
+use open_coroutine::coroutine::Coroutine;
+use std::cell::Cell;
+
+thread_local! {
+    //当前线程是否开启hook，默认开启
+    static ENABLED: Cell<bool> = const { Cell::new(true) };
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.try_with(Cell::get).unwrap_or(false)
+}
+
+/// 开启或关闭当前线程的hook，返回之前的状态
+pub fn set_enabled(enabled: bool) -> bool {
+    ENABLED
+        .try_with(|cell| cell.replace(enabled))
+        .unwrap_or(false)
+}
+
+/// 是否需要接管当前调用：开启了hook并且在协程中
+pub fn hooked() -> bool {
+    is_enabled() && Coroutine::current().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::switch::{hooked, is_enabled, set_enabled};
+
+    #[test]
+    fn test() {
+        assert!(is_enabled());
+        assert!(!hooked());
+        assert!(set_enabled(false));
+        assert!(!is_enabled());
+        assert!(!set_enabled(true));
+        assert!(is_enabled());
+    }
+}
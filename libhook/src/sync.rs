@@ -0,0 +1,135 @@
+use open_coroutine::reactor::{WakeNode, Waker};
+use open_coroutine::CoroutineId;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// 在条件变量或者mutex上等待的协程
+#[derive(Debug, Clone)]
+pub struct Waiter {
+    waker: Arc<Waker>,
+    node: Arc<WakeNode>,
+    signaled: Arc<AtomicBool>,
+}
+
+impl Waiter {
+    pub fn new(waker: Arc<Waker>, node: Arc<WakeNode>) -> Self {
+        Waiter {
+            waker,
+            node,
+            signaled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn is_signaled(&self) -> bool {
+        self.signaled.load(Ordering::Acquire)
+    }
+
+    fn signal(self) {
+        self.signaled.store(true, Ordering::Release);
+        self.waker.wake(&self.node);
+    }
+}
+
+/// 按同步原语的地址排队的协程
+#[derive(Debug)]
+pub struct WaitQueue {
+    waiters: Mutex<BTreeMap<usize, VecDeque<Waiter>>>,
+    //所有地址上的等待者数量，没有等待者时signal不需要加锁
+    len: AtomicUsize,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        WaitQueue {
+            waiters: Mutex::new(BTreeMap::new()),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn add(&self, address: usize, waiter: Waiter) {
+        let mut waiters = self.waiters.lock().unwrap_or_else(PoisonError::into_inner);
+        waiters.entry(address).or_default().push_back(waiter);
+        self.len.fetch_add(1, Ordering::SeqCst);
+        //和signal里的fence配对，登记后再重试的等待者不会错过唤醒
+        fence(Ordering::SeqCst);
+    }
+
+    /// 删除等待者，返回是否删除成功，失败说明已经被唤醒了
+    pub fn remove(&self, address: usize, waiter: &Waiter) -> bool {
+        let mut waiters = self.waiters.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(queue) = waiters.get_mut(&address) {
+            let len = queue.len();
+            queue.retain(|w| !Arc::ptr_eq(&w.signaled, &waiter.signaled));
+            let removed = len - queue.len();
+            if queue.is_empty() {
+                waiters.remove(&address);
+            }
+            self.len.fetch_sub(removed, Ordering::SeqCst);
+            return removed != 0;
+        }
+        false
+    }
+
+    /// 唤醒一个等待的协程，all为true时唤醒所有
+    pub fn signal(&self, address: usize, all: bool) {
+        fence(Ordering::SeqCst);
+        if self.len.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let woken: Vec<Waiter> = {
+            let mut waiters = self.waiters.lock().unwrap_or_else(PoisonError::into_inner);
+            let queue = match waiters.get_mut(&address) {
+                Some(queue) => queue,
+                None => return,
+            };
+            let woken: Vec<Waiter> = if all {
+                queue.drain(..).collect()
+            } else {
+                queue.pop_front().into_iter().collect()
+            };
+            if queue.is_empty() {
+                waiters.remove(&address);
+            }
+            self.len.fetch_sub(woken.len(), Ordering::SeqCst);
+            woken
+        };
+        for waiter in woken {
+            waiter.signal();
+        }
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//条件变量地址 -> 等待的协程
+pub static CONDS: WaitQueue = WaitQueue::new();
+
+//mutex地址 -> 等待的协程，由pthread_mutex_unlock唤醒
+pub static MUTEXES: WaitQueue = WaitQueue::new();
+
+thread_local! {
+    //当前线程上通过hook加锁的mutex地址 -> 持有锁的协程，用于识别协程重复加锁
+    static OWNERS: RefCell<HashMap<usize, CoroutineId>> = RefCell::new(HashMap::new());
+}
+
+pub fn set_owner(mutex: usize, owner: CoroutineId) {
+    _ = OWNERS.try_with(|owners| owners.borrow_mut().insert(mutex, owner));
+}
+
+pub fn remove_owner(mutex: usize) {
+    _ = OWNERS.try_with(|owners| owners.borrow_mut().remove(&mutex));
+}
+
+/// 持有mutex的协程，只记录当前线程上加的锁
+pub fn owner(mutex: usize) -> Option<CoroutineId> {
+    OWNERS
+        .try_with(|owners| owners.borrow().get(&mutex).copied())
+        .ok()
+        .flatten()
+}
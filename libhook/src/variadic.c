@@ -0,0 +1,39 @@
+/*
+ * 可变参数函数的hook，只在apple上编译：apple arm64上可变参数通过栈传递，
+ * 由C按调用约定读取可变参数，再交给rust按固定参数实现的open_coroutine_*
+ */
+#include <fcntl.h>
+#include <stdarg.h>
+
+int open_coroutine_fcntl(int fd, int cmd, long arg);
+int open_coroutine_ioctl(int fd, unsigned long request, void *arg);
+int open_coroutine_open(const char *path, int flags, int mode);
+
+/* 和libc一样，第三个参数最多一个整数或指针，统一按指针大小读取 */
+int fcntl(int fd, int cmd, ...) {
+    va_list args;
+    va_start(args, cmd);
+    void *arg = va_arg(args, void *);
+    va_end(args);
+    return open_coroutine_fcntl(fd, cmd, (long) arg);
+}
+
+int ioctl(int fd, unsigned long request, ...) {
+    va_list args;
+    va_start(args, request);
+    void *arg = va_arg(args, void *);
+    va_end(args);
+    return open_coroutine_ioctl(fd, request, arg);
+}
+
+/* mode只在创建文件时传入，其他情况下不能读取 */
+int open(const char *path, int flags, ...) {
+    int mode = 0;
+    if ((flags & O_CREAT) != 0) {
+        va_list args;
+        va_start(args, flags);
+        mode = va_arg(args, int);
+        va_end(args);
+    }
+    return open_coroutine_open(path, flags, mode);
+}
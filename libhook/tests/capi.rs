@@ -0,0 +1,26 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::process::Command;
+
+#[test]
+fn capi() {
+    //和外部的C程序一样，只使用生成的头文件和libhook动态库
+    let lib_dir = common::lib_dir();
+    let program = common::compile(
+        "capi_test.c",
+        [
+            format!("-I{}", env!("HOOK_INCLUDE_DIR")),
+            format!("-L{}", lib_dir.display()),
+            String::from("-lhook"),
+            format!("-Wl,-rpath,{}", lib_dir.display()),
+        ],
+    );
+    let output = Command::new(&program).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
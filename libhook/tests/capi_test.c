@@ -0,0 +1,156 @@
+/*
+ * open_coroutine.h的测试程序，由tests/capi.rs用安装的头文件编译并链接libhook.so，
+ * 独立运行，失败时输出出错的行号
+ */
+#include <errno.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "open_coroutine.h"
+
+#define CHECK(expr)          \
+    do {                     \
+        if (!(expr)) {       \
+            return __LINE__; \
+        }                    \
+    } while (0)
+
+#define COUNT 100
+
+static co_mutex_t *mutex;
+static int counter;
+static uint64_t ids[2];
+
+static void *produce(void *arg) {
+    co_channel_t *channel = arg;
+    for (intptr_t i = 1; i <= COUNT; i++) {
+        if (co_channel_send(channel, (void *) i) != 0) {
+            return (void *) -1;
+        }
+    }
+    co_channel_close(channel);
+    return NULL;
+}
+
+static void *consume(void *arg) {
+    co_channel_t *channel = arg;
+    intptr_t sum = 0;
+    void *value;
+    while (co_channel_recv(channel, &value) == 0) {
+        sum += (intptr_t) value;
+    }
+    return (void *) sum;
+}
+
+static void *increase(void *arg) {
+    ids[(intptr_t) arg] = co_current_id();
+    for (int i = 0; i < COUNT; i++) {
+        co_mutex_lock(mutex);
+        int value = counter;
+        //持有锁时让出，另一个协程拿不到锁
+        co_yield_now();
+        counter = value + 1;
+        co_mutex_unlock(mutex);
+    }
+    return NULL;
+}
+
+static void *wait_cancel(void *arg) {
+    (void) arg;
+    intptr_t slept = 0;
+    while (!co_cancelled()) {
+        co_sleep(1);
+        slept++;
+    }
+    return (void *) slept;
+}
+
+static void *cancel(void *arg) {
+    co_sleep(20);
+    return (void *) (intptr_t) co_cancel(arg);
+}
+
+static int run(void) {
+    CHECK(strcmp(co_version(), OPEN_COROUTINE_VERSION) == 0);
+    CHECK(co_current_id() == 0);
+    errno = 0;
+    CHECK(co_spawn(NULL, NULL, NULL, 0) == NULL);
+    CHECK(errno == EINVAL);
+    /* 栈大小超出限制 */
+    errno = 0;
+    CHECK(co_spawn(NULL, cancel, NULL, SIZE_MAX) == NULL);
+    CHECK(errno == EINVAL);
+
+    co_scheduler_t *scheduler = co_scheduler_create();
+    CHECK(scheduler != NULL);
+    CHECK(scheduler != co_scheduler_current());
+
+    //channel
+    co_channel_t *channel = co_channel_bounded(4);
+    co_handle_t *consumer = co_spawn(scheduler, consume, channel, 0);
+    co_handle_t *producer = co_spawn(scheduler, produce, channel, 0);
+    CHECK(co_handle_id(consumer) != co_handle_id(producer));
+    CHECK(co_scheduler_run(scheduler) == 2);
+    void *result = (void *) 1;
+    CHECK(co_join(producer, &result) == 0);
+    CHECK(result == NULL);
+    CHECK(co_join(consumer, &result) == 0);
+    CHECK((intptr_t) result == COUNT * (COUNT + 1) / 2);
+    CHECK(co_channel_send(channel, NULL) == EPIPE);
+    CHECK(co_channel_try_recv(channel, &result) == EPIPE);
+    co_channel_destroy(channel);
+
+    channel = co_channel_unbounded();
+    CHECK(co_channel_try_recv(channel, &result) == EAGAIN);
+    CHECK(co_channel_recv_timeout(channel, &result, 10) == ETIMEDOUT);
+    CHECK(co_channel_try_send(channel, (void *) 7) == 0);
+    CHECK(co_channel_len(channel) == 1);
+    CHECK(co_channel_recv(channel, &result) == 0);
+    CHECK((intptr_t) result == 7);
+    co_channel_destroy(channel);
+
+    //mutex
+    mutex = co_mutex_create();
+    CHECK(co_mutex_unlock(mutex) == EPERM);
+    co_handle_t *first = co_spawn(scheduler, increase, (void *) 0, 0);
+    co_handle_t *second = co_spawn(scheduler, increase, (void *) 1, 0);
+    CHECK(co_scheduler_run(scheduler) == 2);
+    CHECK(co_join(first, NULL) == 0);
+    CHECK(co_join(second, NULL) == 0);
+    CHECK(counter == 2 * COUNT);
+    CHECK(ids[0] != 0 && ids[1] != 0 && ids[0] != ids[1]);
+    CHECK(co_mutex_trylock(mutex) == 0);
+    CHECK(co_mutex_trylock(mutex) == EBUSY);
+    CHECK(co_mutex_destroy(mutex) == EBUSY);
+    CHECK(co_mutex_unlock(mutex) == 0);
+    CHECK(co_mutex_destroy(mutex) == 0);
+
+    //cancel
+    co_handle_t *waiting = co_spawn(scheduler, wait_cancel, NULL, 0);
+    co_handle_t *canceller = co_spawn(scheduler, cancel, waiting, 0);
+    CHECK(co_scheduler_destroy(scheduler) == EBUSY);
+    CHECK(co_scheduler_run_timeout(scheduler, 5000) == 2);
+    CHECK(co_join(canceller, &result) == 0);
+    CHECK(result == (void *) 1);
+    CHECK(!co_cancel(waiting));
+    CHECK(co_join(waiting, &result) == 0);
+    CHECK((intptr_t) result > 0);
+
+    //detach之后协程仍然会执行完
+    co_detach(co_spawn(NULL, cancel, NULL, 0));
+    CHECK(co_scheduler_run(NULL) == 1);
+
+    CHECK(co_scheduler_destroy(co_scheduler_current()) == EINVAL);
+    CHECK(co_scheduler_destroy(scheduler) == 0);
+    return 0;
+}
+
+int main(void) {
+    int line = run();
+    if (line != 0) {
+        fprintf(stderr, "capi_test.c:%d: check failed\n", line);
+        return 1;
+    }
+    return 0;
+}
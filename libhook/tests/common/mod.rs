@@ -0,0 +1,34 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// libhook的动态库和集成测试生成在同一个deps目录下
+pub fn lib_dir() -> PathBuf {
+    std::env::current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf()
+}
+
+/// 用build.rs里cc使用的C编译器把tests下的C程序编译成可执行文件，args放在源文件之后
+pub fn compile<I, S>(source: &str, args: I) -> PathBuf
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let source = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(source);
+    let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join(source.file_stem().unwrap());
+    let status = Command::new(env!("HOOK_CC"))
+        .args(["-Wall", "-Wextra", "-Werror"])
+        .arg(&source)
+        .arg("-o")
+        .arg(&output)
+        .args(args)
+        .status()
+        .unwrap();
+    assert!(status.success(), "failed to compile {}", source.display());
+    output
+}
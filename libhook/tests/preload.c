@@ -0,0 +1,30 @@
+/*
+ * LD_PRELOAD=libhook.so时的测试程序，不链接libhook，由tests/preload.rs编译运行，
+ * 输出main和pthread_create创建的线程是否在协程中执行
+ */
+#define _GNU_SOURCE
+#include <dlfcn.h>
+#include <pthread.h>
+#include <stdbool.h>
+#include <stdio.h>
+
+/* 没有预加载libhook时找不到in_coroutine */
+static bool in_coroutine(void) {
+    bool (*f)(void) = (bool (*)(void)) dlsym(RTLD_DEFAULT, "in_coroutine");
+    return f != NULL && f();
+}
+
+static void *routine(void *arg) {
+    *(bool *) arg = in_coroutine();
+    return NULL;
+}
+
+int main(void) {
+    bool thread = false;
+    pthread_t tid;
+    if (pthread_create(&tid, NULL, routine, &thread) != 0 || pthread_join(tid, NULL) != 0) {
+        return 1;
+    }
+    printf("main=%d thread=%d\n", in_coroutine(), thread);
+    return 0;
+}
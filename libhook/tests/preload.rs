@@ -0,0 +1,39 @@
+#![cfg(all(target_os = "linux", target_env = "gnu"))]
+
+mod common;
+
+use std::process::Command;
+
+#[test]
+fn preload() {
+    let program = common::compile("preload.c", ["-lpthread", "-ldl"]);
+    let library = common::lib_dir().join("libhook.so");
+    for (policy, expected) in [
+        ("none", "main=0 thread=0"),
+        ("spawned", "main=0 thread=1"),
+        ("all", "main=1 thread=1"),
+    ] {
+        let output = Command::new(&program)
+            .env("LD_PRELOAD", &library)
+            .env("OPEN_COROUTINE_THREADS", policy)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            output.status.success(),
+            "{}: {}",
+            policy,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(expected, stdout.trim(), "{}", policy);
+    }
+    //没有预加载时，即使设置了环境变量也不会接管
+    let output = Command::new(&program)
+        .env("OPEN_COROUTINE_THREADS", "all")
+        .output()
+        .unwrap();
+    assert_eq!(
+        "main=0 thread=0",
+        String::from_utf8_lossy(&output.stdout).trim()
+    );
+}
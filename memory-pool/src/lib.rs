@@ -12,62 +12,59 @@ use std::mem::ManuallyDrop;
 use std::ptr::NonNull;
 use std::sync::RwLock;
 
-static mut MEMORY_POOL: Lazy<RwLock<HashMap<usize, SizedMemoryPool>>> =
+static MEMORY_POOL: Lazy<RwLock<HashMap<usize, SizedMemoryPool>>> =
     Lazy::new(|| RwLock::new(HashMap::new()));
 
 pub fn get_memory_pool(size: usize) -> Option<NonNull<SizedMemoryPool>> {
-    unsafe {
-        match MEMORY_POOL.write() {
-            Ok(mut map) => match map.get_mut(&size) {
-                Some(pool) => NonNull::new(pool as *mut _ as *mut SizedMemoryPool),
-                None => None,
-            },
-            Err(_) => None,
-        }
+    match MEMORY_POOL.write() {
+        Ok(mut map) => match map.get_mut(&size) {
+            Some(pool) => NonNull::new(pool as *mut SizedMemoryPool),
+            None => None,
+        },
+        Err(_) => None,
     }
 }
 
 pub fn allocate(size: usize) -> Result<ManuallyDrop<Memory>, MemoryError> {
-    unsafe {
-        match MEMORY_POOL.write() {
-            Ok(mut map) => match map.get_mut(&size) {
-                Some(pool) => pool.allocate(),
-                None => {
-                    map.insert(size, SizedMemoryPool::new(size));
-                    map.get_mut(&size).unwrap().allocate()
-                }
-            },
-            Err(_) => allocate(size),
-        }
+    match MEMORY_POOL.write() {
+        Ok(mut map) => match map.get_mut(&size) {
+            Some(pool) => pool.allocate(),
+            None => {
+                map.insert(size, SizedMemoryPool::new(size));
+                map.get_mut(&size).unwrap().allocate()
+            }
+        },
+        Err(_) => allocate(size),
     }
 }
 
 pub fn revert(stack: ManuallyDrop<Memory>) {
-    unsafe {
-        match MEMORY_POOL.write() {
-            Ok(mut map) => {
-                if let Some(pool) = map.get_mut(&stack.len()) {
-                    pool.revert(stack);
-                }
+    match MEMORY_POOL.write() {
+        Ok(mut map) => {
+            if let Some(pool) = map.get_mut(&stack.len()) {
+                pool.revert(stack);
             }
-            Err(_) => revert(stack),
         }
+        Err(_) => revert(stack),
     }
 }
 
 pub fn drop(stack: ManuallyDrop<Memory>) {
-    unsafe {
-        match MEMORY_POOL.write() {
-            Ok(mut map) => {
-                if let Some(pool) = map.get_mut(&stack.len()) {
-                    pool.drop(stack);
-                }
+    match MEMORY_POOL.write() {
+        Ok(mut map) => {
+            if let Some(pool) = map.get_mut(&stack.len()) {
+                pool.drop(stack);
             }
-            Err(_) => drop(stack),
         }
+        Err(_) => drop(stack),
     }
 }
 
+/// 校验栈大小是否超过系统限制，小于最小值的会被向上取整
+pub fn check_size(size: usize) -> Result<(), MemoryError> {
+    Memory::check_size(size)
+}
+
 pub fn default() -> Result<ManuallyDrop<Memory>, MemoryError> {
     allocate(system::default_size(true))
 }
@@ -80,7 +80,25 @@ impl Memory {
     }
 
     /// Allocates a new stack of `size`.
-    fn allocate(mut size: usize, protected: bool) -> Result<Memory, MemoryError> {
+    fn allocate(size: usize, protected: bool) -> Result<Memory, MemoryError> {
+        let size = Memory::total_size(size, protected)?;
+        let mut ret = unsafe { system::allocate(size) };
+        if protected {
+            if let Ok(stack) = ret {
+                ret = unsafe { system::protect(&stack) };
+            }
+        }
+        ret.map_err(MemoryError::IoError)
+    }
+
+    /// Checks whether a protected stack of `size` can be allocated,
+    /// sizes below [`Memory::min_size`] are rounded up.
+    pub fn check_size(size: usize) -> Result<(), MemoryError> {
+        Memory::total_size(size, true).map(|_| ())
+    }
+
+    // The size actually mapped for a stack of `size`, including the guard page.
+    fn total_size(mut size: usize, protected: bool) -> Result<usize, MemoryError> {
         let page_size = system::page_size();
         let min_stack_size = system::min_size();
         let max_stack_size = system::max_size(false);
@@ -90,18 +108,10 @@ impl Memory {
             size = min_stack_size;
         }
         size = (size - 1) & !(page_size - 1);
-        if let Some(size) = size.checked_add(add) {
-            if size <= max_stack_size {
-                let mut ret = unsafe { system::allocate(size) };
-                if protected {
-                    if let Ok(stack) = ret {
-                        ret = unsafe { system::protect(&stack) };
-                    }
-                }
-                return ret.map_err(MemoryError::IoError);
-            }
+        match size.checked_add(add) {
+            Some(size) if size <= max_stack_size => Ok(size),
+            _ => Err(MemoryError::ExceedsMaximumSize(max_stack_size - add)),
         }
-        Err(MemoryError::ExceedsMaximumSize(max_stack_size - add))
     }
 
     /// Creates a (non-owning) representation of some stack memory.
@@ -135,6 +145,14 @@ impl Memory {
         self.protected
     }
 
+    /// Returns whether `address` lies in the guard page below bottom(),
+    /// i.e. whether a fault at `address` is a stack overflow.
+    pub fn is_guard(&self, address: *const c_void) -> bool {
+        let bottom = self.bottom as usize;
+        let address = address as usize;
+        self.protected && address < bottom && bottom - address <= system::page_size()
+    }
+
     /// Returns the size of the stack between top() and bottom().
     #[inline]
     pub fn len(&self) -> usize {
@@ -215,9 +233,8 @@ mod tests {
     #[test]
     fn stack_size_too_large() {
         let stack_size = system::max_size(true);
-        match Memory::allocate(stack_size, true) {
-            Err(MemoryError::ExceedsMaximumSize(_)) => panic!(),
-            _ => {}
+        if let Err(MemoryError::ExceedsMaximumSize(_)) = Memory::allocate(stack_size, true) {
+            panic!()
         }
         let stack_size = stack_size + 1;
         match Memory::allocate(stack_size, true) {
@@ -226,9 +243,8 @@ mod tests {
         }
 
         let stack_size = system::max_size(false);
-        match Memory::allocate(stack_size, false) {
-            Err(MemoryError::ExceedsMaximumSize(_)) => panic!(),
-            _ => {}
+        if let Err(MemoryError::ExceedsMaximumSize(_)) = Memory::allocate(stack_size, false) {
+            panic!()
         }
         let stack_size = stack_size + 1;
         match Memory::allocate(stack_size, false) {
@@ -238,6 +254,29 @@ mod tests {
     }
 
     #[test]
+    fn check_size() {
+        assert!(Memory::check_size(0).is_ok());
+        assert!(Memory::check_size(system::max_size(true)).is_ok());
+        match Memory::check_size(system::max_size(true) + 1) {
+            Err(MemoryError::ExceedsMaximumSize(..)) => {}
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn guard() {
+        let page_size = system::page_size();
+        let stack = Memory::new(page_size * 2).unwrap();
+        let bottom = stack.bottom() as usize;
+        assert!(stack.is_guard((bottom - 1) as *const c_void));
+        assert!(stack.is_guard((bottom - page_size) as *const c_void));
+        assert!(!stack.is_guard(stack.bottom()));
+        assert!(!stack.is_guard((bottom - page_size - 1) as *const c_void));
+        stack.drop();
+    }
+
+    #[test]
+    #[allow(clippy::clone_on_copy)]
     fn clone() {
         let size = system::min_size();
         let stack = Memory::new(size).unwrap();
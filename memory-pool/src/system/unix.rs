@@ -9,7 +9,6 @@ use std::io;
 use std::mem;
 use std::os::raw::c_void;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::usize;
 
 use crate::memory::Memory;
 
@@ -30,7 +29,7 @@ const MAP_STACK: libc::c_int = 0;
 const MAP_STACK: libc::c_int = libc::MAP_STACK;
 
 pub unsafe fn allocate(size: usize) -> io::Result<Memory> {
-    const NULL: *mut libc::c_void = 0 as *mut libc::c_void;
+    const NULL: *mut libc::c_void = std::ptr::null_mut();
     const PROT: libc::c_int = libc::PROT_READ | libc::PROT_WRITE;
     const TYPE: libc::c_int = libc::MAP_PRIVATE | libc::MAP_ANON | MAP_STACK;
 
@@ -50,10 +49,10 @@ pub unsafe fn allocate(size: usize) -> io::Result<Memory> {
 pub unsafe fn protect(stack: &Memory) -> io::Result<Memory> {
     let page_size = page_size();
 
-    debug_assert!(stack.len() % page_size == 0 && !stack.is_empty());
+    debug_assert!(stack.len().is_multiple_of(page_size) && !stack.is_empty());
 
     let ret = {
-        let bottom = stack.bottom() as *mut libc::c_void;
+        let bottom = stack.bottom();
         libc::mprotect(bottom, page_size, libc::PROT_NONE)
     };
 
@@ -66,7 +65,7 @@ pub unsafe fn protect(stack: &Memory) -> io::Result<Memory> {
 }
 
 pub unsafe fn deallocate(ptr: *mut c_void, size: usize) {
-    libc::munmap(ptr as *mut libc::c_void, size);
+    libc::munmap(ptr, size);
 }
 
 pub fn page_size() -> usize {
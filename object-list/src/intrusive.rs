@@ -0,0 +1,432 @@
+use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+/*
+侵入式链表，链接嵌入在节点里，入队出队只修改指针，不分配内存；
+链表不拥有节点，节点的内存由使用者管理，
+一个Link同一时间只能在一个链表里。
+open-coroutine的调度器队列和跨线程唤醒的队列都通过协程和无栈任务内嵌的链接排队
+ */
+
+/// 嵌入到节点里的链接
+pub struct Link<T> {
+    prev: *mut T,
+    //MpscList会被多个线程同时修改
+    next: AtomicPtr<T>,
+}
+
+//只保存其他节点的地址，能否跨线程取决于节点本身
+unsafe impl<T: Send> Send for Link<T> {}
+
+unsafe impl<T: Send> Sync for Link<T> {}
+
+impl<T> Link<T> {
+    pub const fn new() -> Self {
+        Link {
+            prev: ptr::null_mut(),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+}
+
+impl<T> Default for Link<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Debug for Link<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Link")
+            .field("prev", &self.prev)
+            .field("next", &self.next.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+/// # Safety
+/// link必须返回node内部的同一个Link
+pub unsafe trait Linked: Sized {
+    fn link(node: *mut Self) -> *mut Link<Self>;
+}
+
+unsafe fn prev<T: Linked>(node: *mut T) -> *mut T {
+    (*T::link(node)).prev
+}
+
+unsafe fn next<T: Linked>(node: *mut T) -> *mut T {
+    (*T::link(node)).next.load(Ordering::Relaxed)
+}
+
+unsafe fn set_prev<T: Linked>(node: *mut T, prev: *mut T) {
+    (*T::link(node)).prev = prev;
+}
+
+unsafe fn set_next<T: Linked>(node: *mut T, next: *mut T) {
+    (*T::link(node)).next.store(next, Ordering::Relaxed);
+}
+
+/// 双向链表，所有操作都是O(1)
+pub struct LinkedList<T: Linked> {
+    head: *mut T,
+    tail: *mut T,
+    len: usize,
+}
+
+impl<T: Linked> LinkedList<T> {
+    pub const fn new() -> Self {
+        LinkedList {
+            head: ptr::null_mut(),
+            tail: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_null()
+    }
+
+    pub fn front(&self) -> Option<*mut T> {
+        (!self.head.is_null()).then_some(self.head)
+    }
+
+    pub fn back(&self) -> Option<*mut T> {
+        (!self.tail.is_null()).then_some(self.tail)
+    }
+
+    /// # Safety
+    /// node必须有效，并且不在任何链表里
+    pub unsafe fn push_back(&mut self, node: *mut T) {
+        set_prev(node, self.tail);
+        set_next(node, ptr::null_mut());
+        match self.tail.is_null() {
+            true => self.head = node,
+            false => set_next(self.tail, node),
+        }
+        self.tail = node;
+        self.len += 1;
+    }
+
+    /// # Safety
+    /// node必须有效，并且不在任何链表里
+    pub unsafe fn push_front(&mut self, node: *mut T) {
+        set_prev(node, ptr::null_mut());
+        set_next(node, self.head);
+        match self.head.is_null() {
+            true => self.tail = node,
+            false => set_prev(self.head, node),
+        }
+        self.head = node;
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<*mut T> {
+        let node = self.front()?;
+        unsafe { self.remove(node) };
+        Some(node)
+    }
+
+    pub fn pop_back(&mut self) -> Option<*mut T> {
+        let node = self.back()?;
+        unsafe { self.remove(node) };
+        Some(node)
+    }
+
+    /// 把node插到prev之后，prev为None时插到头部
+    /// # Safety
+    /// node必须有效，并且不在任何链表里；prev必须在这个链表里
+    pub unsafe fn insert_after(&mut self, prev: Option<*mut T>, node: *mut T) {
+        let prev = match prev {
+            Some(prev) => prev,
+            None => return self.push_front(node),
+        };
+        let next = next(prev);
+        set_prev(node, prev);
+        set_next(node, next);
+        set_next(prev, node);
+        match next.is_null() {
+            true => self.tail = node,
+            false => set_prev(next, node),
+        }
+        self.len += 1;
+    }
+
+    /// 从链表中摘除node
+    /// # Safety
+    /// node必须在这个链表里
+    pub unsafe fn remove(&mut self, node: *mut T) {
+        let (prev, next) = (prev(node), next(node));
+        match prev.is_null() {
+            true => self.head = next,
+            false => set_next(prev, next),
+        }
+        match next.is_null() {
+            true => self.tail = prev,
+            false => set_prev(next, prev),
+        }
+        set_prev(node, ptr::null_mut());
+        set_next(node, ptr::null_mut());
+        self.len -= 1;
+    }
+
+    /// 把other的节点全部移动到尾部
+    pub fn append(&mut self, other: &mut LinkedList<T>) {
+        if other.is_empty() {
+            return;
+        }
+        match self.tail.is_null() {
+            true => self.head = other.head,
+            false => unsafe {
+                set_next(self.tail, other.head);
+                set_prev(other.head, self.tail);
+            },
+        }
+        self.tail = other.tail;
+        self.len += std::mem::take(other).len;
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            front: self.head,
+            back: self.tail,
+            len: self.len,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: Linked> Default for LinkedList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Linked> Debug for LinkedList<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+pub struct Iter<'a, T: Linked> {
+    front: *mut T,
+    back: *mut T,
+    //还没有遍历的节点数，两端相遇时停止
+    len: usize,
+    phantom: PhantomData<&'a LinkedList<T>>,
+}
+
+impl<T: Linked> Iterator for Iter<'_, T> {
+    type Item = *mut T;
+
+    fn next(&mut self) -> Option<*mut T> {
+        if self.len == 0 {
+            return None;
+        }
+        let node = self.front;
+        self.front = unsafe { next(node) };
+        self.len -= 1;
+        Some(node)
+    }
+}
+
+impl<T: Linked> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<*mut T> {
+        if self.len == 0 {
+            return None;
+        }
+        let node = self.back;
+        self.back = unsafe { prev(node) };
+        self.len -= 1;
+        Some(node)
+    }
+}
+
+/// 无锁的多生产者单消费者链表，任意线程都可以push，
+/// 消费者通过take一次取走全部节点
+pub struct MpscList<T: Linked> {
+    //最后push的节点，节点通过next指向更早push的节点
+    head: AtomicPtr<T>,
+}
+
+unsafe impl<T: Linked + Send> Send for MpscList<T> {}
+
+unsafe impl<T: Linked + Send> Sync for MpscList<T> {}
+
+impl<T: Linked> MpscList<T> {
+    pub const fn new() -> Self {
+        MpscList {
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+
+    /// 返回push之前链表是否为空
+    /// # Safety
+    /// node必须有效，并且不在任何链表里
+    pub unsafe fn push(&self, node: *mut T) -> bool {
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            set_next(node, head);
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return head.is_null(),
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    /// 取走全部节点，按push的顺序排列
+    pub fn take(&self) -> LinkedList<T> {
+        let mut node = self.head.swap(ptr::null_mut(), Ordering::Acquire);
+        let mut list = LinkedList::new();
+        while !node.is_null() {
+            unsafe {
+                let next = next(node);
+                list.push_front(node);
+                node = next;
+            }
+        }
+        list
+    }
+}
+
+impl<T: Linked> Default for MpscList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Linked> Debug for MpscList<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MpscList")
+            .field("head", &self.head.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intrusive::{Link, Linked, LinkedList, MpscList};
+    use std::ptr;
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct Node {
+        value: usize,
+        link: Link<Node>,
+    }
+
+    unsafe impl Linked for Node {
+        fn link(node: *mut Self) -> *mut Link<Self> {
+            unsafe { ptr::addr_of_mut!((*node).link) }
+        }
+    }
+
+    fn values(list: &LinkedList<Node>) -> Vec<usize> {
+        list.iter().map(|node| unsafe { (*node).value }).collect()
+    }
+
+    #[test]
+    fn test_linked_list() {
+        let mut nodes: Vec<Node> = (0..5)
+            .map(|value| Node {
+                value,
+                ..Default::default()
+            })
+            .collect();
+        let pointers: Vec<*mut Node> = nodes.iter_mut().map(|node| node as *mut Node).collect();
+        let mut list = LinkedList::new();
+        unsafe {
+            list.push_back(pointers[1]);
+            list.push_back(pointers[2]);
+            list.push_front(pointers[0]);
+        }
+        assert_eq!(vec![0, 1, 2], values(&list));
+        unsafe { list.remove(pointers[1]) };
+        assert_eq!(vec![0, 2], values(&list));
+        unsafe { list.insert_after(Some(pointers[0]), pointers[1]) };
+        assert_eq!(
+            vec![2, 1, 0],
+            list.iter()
+                .rev()
+                .map(|node| unsafe { (*node).value })
+                .collect::<Vec<_>>()
+        );
+        unsafe { list.remove(pointers[1]) };
+        unsafe { list.insert_after(Some(pointers[2]), pointers[1]) };
+        assert_eq!(vec![0, 2, 1], values(&list));
+        unsafe { list.remove(pointers[1]) };
+        let mut other = LinkedList::new();
+        unsafe {
+            other.push_back(pointers[3]);
+            other.push_back(pointers[4]);
+        }
+        list.append(&mut other);
+        assert!(other.is_empty());
+        assert_eq!(4, list.len());
+        assert_eq!(Some(pointers[4]), list.pop_back());
+        assert_eq!(Some(pointers[0]), list.pop_front());
+        assert_eq!(vec![2, 3], values(&list));
+        //移到另一个链表只修改指针
+        while let Some(node) = list.pop_front() {
+            unsafe { other.push_back(node) };
+        }
+        assert!(list.is_empty());
+        assert_eq!(vec![2, 3], values(&other));
+    }
+
+    #[test]
+    fn test_mpsc_list() {
+        const THREADS: usize = 4;
+        const COUNT: usize = 1000;
+        let list = Arc::new(MpscList::<Node>::new());
+        assert!(unsafe { list.push(Box::into_raw(Box::default())) });
+        assert!(!list.is_empty());
+        let threads: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let list = list.clone();
+                std::thread::spawn(move || {
+                    for value in t * COUNT..(t + 1) * COUNT {
+                        let node = Box::new(Node {
+                            value,
+                            ..Default::default()
+                        });
+                        unsafe { list.push(Box::into_raw(node)) };
+                    }
+                })
+            })
+            .collect();
+        let mut received = Vec::new();
+        while received.len() <= THREADS * COUNT {
+            let mut taken = list.take();
+            while let Some(node) = taken.pop_front() {
+                received.push(unsafe { Box::from_raw(node) }.value);
+            }
+        }
+        threads
+            .into_iter()
+            .for_each(|thread| thread.join().unwrap());
+        assert!(list.is_empty());
+        assert_eq!(0, received[0]);
+        //同一个线程push的节点保持顺序
+        for t in 0..THREADS {
+            let values: Vec<usize> = received[1..]
+                .iter()
+                .copied()
+                .filter(|value| value / COUNT == t)
+                .collect();
+            assert!((t * COUNT..(t + 1) * COUNT).eq(values));
+        }
+    }
+}
@@ -1,214 +1,190 @@
 use crossbeam_deque::{Steal, Worker};
+use std::collections::vec_deque::{IntoIter, Iter, IterMut};
 use std::collections::VecDeque;
 use std::os::raw::c_void;
-use std::ptr;
+
+pub mod intrusive;
 
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq)]
-pub struct ObjectList {
-    inner: VecDeque<*mut c_void>,
-}
-
-fn convert<T>(pointer: *mut c_void) -> Option<T> {
-    unsafe {
-        let node = Box::from_raw(pointer as *mut T);
-        Some(*node)
-    }
+pub struct ObjectList<T> {
+    inner: VecDeque<T>,
 }
 
-impl ObjectList {
+impl<T> ObjectList<T> {
     pub fn new() -> Self {
         ObjectList {
             inner: VecDeque::new(),
         }
     }
 
-    pub fn front<T>(&mut self) -> Option<&T> {
-        match self.inner.front() {
-            Some(value) => unsafe {
-                let result = ptr::read_unaligned(value) as *mut T;
-                Some(&*result)
-            },
-            None => None,
-        }
+    pub fn front(&self) -> Option<&T> {
+        self.inner.front()
     }
 
-    pub fn front_mut<T>(&mut self) -> Option<&mut T> {
-        match self.inner.front_mut() {
-            Some(value) => unsafe {
-                let result = ptr::read_unaligned(value) as *mut T;
-                Some(&mut *result)
-            },
-            None => None,
-        }
+    pub fn front_mut(&mut self) -> Option<&mut T> {
+        self.inner.front_mut()
     }
 
-    pub fn front_mut_raw(&mut self) -> Option<*mut c_void> {
-        self.inner
-            .front_mut()
-            .map(|value| unsafe { ptr::read_unaligned(value) })
+    pub fn push_front(&mut self, element: T) {
+        self.inner.push_front(element);
     }
 
-    pub fn push_front<T>(&mut self, element: T) {
-        let ptr = Box::leak(Box::new(element));
-        self.inner.push_front(ptr as *mut _ as *mut c_void);
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.inner.pop_front()
     }
 
-    pub fn push_front_raw(&mut self, ptr: *mut c_void) {
-        self.inner.push_front(ptr);
+    pub fn back(&self) -> Option<&T> {
+        self.inner.back()
     }
 
-    pub fn pop_front<T>(&mut self) -> Option<T> {
-        match self.inner.pop_front() {
-            Some(pointer) => convert(pointer),
-            None => None,
-        }
+    pub fn back_mut(&mut self) -> Option<&mut T> {
+        self.inner.back_mut()
     }
 
-    /// 如果是闭包，还是要获取裸指针再手动转换，不然类型有问题
-    pub fn pop_front_raw(&mut self) -> Option<*mut c_void> {
-        self.inner.pop_front()
+    pub fn push_back(&mut self, element: T) {
+        self.inner.push_back(element);
     }
 
-    pub fn back<T>(&mut self) -> Option<&T> {
-        match self.inner.back() {
-            Some(value) => unsafe {
-                let result = ptr::read_unaligned(value) as *mut T;
-                Some(&*result)
-            },
-            None => None,
-        }
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.inner.pop_back()
     }
 
-    pub fn back_mut<T>(&mut self) -> Option<&mut T> {
-        match self.inner.back_mut() {
-            Some(value) => unsafe {
-                let result = ptr::read_unaligned(value) as *mut T;
-                Some(&mut *result)
-            },
-            None => None,
-        }
+    pub fn len(&self) -> usize {
+        self.inner.len()
     }
 
-    pub fn back_mut_raw(&mut self) -> Option<*mut c_void> {
-        self.inner
-            .back_mut()
-            .map(|value| unsafe { ptr::read_unaligned(value) })
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.inner.get(index)
     }
 
-    pub fn push_back<T>(&mut self, element: T) {
-        let ptr = Box::leak(Box::new(element));
-        self.inner.push_back(ptr as *mut _ as *mut c_void);
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.inner.get_mut(index)
     }
 
-    pub fn push_back_raw(&mut self, ptr: *mut c_void) {
-        self.inner.push_back(ptr);
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
     }
 
-    pub fn pop_back<T>(&mut self) -> Option<T> {
-        match self.inner.pop_back() {
-            Some(pointer) => convert(pointer),
-            None => None,
+    pub fn move_front_to_back(&mut self) {
+        if let Some(element) = self.inner.pop_front() {
+            self.inner.push_back(element)
         }
     }
 
-    /// 如果是闭包，还是要获取裸指针再手动转换，不然类型有问题
-    pub fn pop_back_raw(&mut self) -> Option<*mut c_void> {
-        self.inner.pop_back()
+    /// 删除第index个元素，后面的元素依次前移
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        self.inner.remove(index)
     }
 
-    pub fn len(&self) -> usize {
-        self.inner.len()
+    /// 只保留f返回true的元素，保持原有顺序
+    pub fn retain(&mut self, f: impl FnMut(&T) -> bool) {
+        self.inner.retain(f)
     }
 
-    pub fn get<T>(&self, index: usize) -> Option<&T> {
-        match self.inner.get(index) {
-            Some(val) => unsafe {
-                let result = ptr::read_unaligned(val) as *mut T;
-                Some(&*result)
-            },
-            None => None,
-        }
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.inner.iter()
     }
 
-    pub fn get_mut<T>(&mut self, index: usize) -> Option<&mut T> {
-        match self.inner.get_mut(index) {
-            Some(val) => unsafe {
-                let result = ptr::read_unaligned(val) as *mut T;
-                Some(&mut *result)
-            },
-            None => None,
-        }
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        self.inner.iter_mut()
     }
 
-    pub fn get_mut_raw(&mut self, index: usize) -> Option<*mut c_void> {
-        self.inner
-            .get_mut(index)
-            .map(|pointer| unsafe { ptr::read_unaligned(pointer) })
+    /// 转换为裸指针，只用于通过FFI传递整个列表，需要用[`ObjectList::from_raw`]转换回来
+    pub fn into_raw(self) -> *mut c_void {
+        Box::into_raw(Box::new(self)) as *mut c_void
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.inner.is_empty()
-    }
-
-    pub fn move_front_to_back(&mut self) {
-        if let Some(pointer) = self.inner.pop_front() {
-            self.inner.push_back(pointer)
-        }
+    /// # Safety
+    /// 指针必须来自相同T的[`ObjectList::into_raw`]，并且只能转换一次
+    pub unsafe fn from_raw(pointer: *mut c_void) -> Self {
+        *Box::from_raw(pointer as *mut ObjectList<T>)
     }
 }
 
-impl Default for ObjectList {
+impl<T> Default for ObjectList<T> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl AsRef<ObjectList> for ObjectList {
-    fn as_ref(&self) -> &ObjectList {
+impl<T> AsRef<ObjectList<T>> for ObjectList<T> {
+    fn as_ref(&self) -> &ObjectList<T> {
         self
     }
 }
 
-impl AsMut<ObjectList> for ObjectList {
-    fn as_mut(&mut self) -> &mut ObjectList {
+impl<T> AsMut<ObjectList<T>> for ObjectList<T> {
+    fn as_mut(&mut self) -> &mut ObjectList<T> {
         &mut *self
     }
 }
 
+impl<T> Extend<T> for ObjectList<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.inner.extend(iter)
+    }
+}
+
+impl<T> FromIterator<T> for ObjectList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        ObjectList {
+            inner: VecDeque::from_iter(iter),
+        }
+    }
+}
+
+impl<T> IntoIterator for ObjectList<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inner.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a ObjectList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inner.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut ObjectList<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inner.iter_mut()
+    }
+}
+
 #[derive(Debug)]
-pub struct StealableObjectList {
+pub struct StealableObjectList<T> {
     //todo add head/tail field
-    inner: Worker<*mut c_void>,
+    inner: Worker<T>,
 }
 
-impl StealableObjectList {
+impl<T> StealableObjectList<T> {
     pub fn new() -> Self {
         StealableObjectList {
             inner: Worker::new_fifo(),
         }
     }
 
-    pub fn push_back<T>(&mut self, element: T) {
-        let ptr = Box::leak(Box::new(element));
-        self.inner.push(ptr as *mut _ as *mut c_void);
-    }
-
-    pub fn pop_front<T>(&mut self) -> Option<T> {
-        match self.inner.pop() {
-            Some(pointer) => convert(pointer),
-            None => None,
-        }
+    pub fn push_back(&mut self, element: T) {
+        self.inner.push(element);
     }
 
-    /// 如果是闭包，还是要获取裸指针再手动转换，不然类型有问题
-    pub fn pop_front_raw(&mut self) -> Option<*mut c_void> {
+    pub fn pop_front(&mut self) -> Option<T> {
         self.inner.pop()
     }
 
     pub fn move_front_to_back(&mut self) {
-        if let Some(pointer) = self.inner.pop() {
-            self.inner.push(pointer)
+        if let Some(element) = self.inner.pop() {
+            self.inner.push(element)
         }
     }
 
@@ -220,25 +196,25 @@ impl StealableObjectList {
         self.inner.is_empty()
     }
 
-    pub fn steal_to(&self, dest: &StealableObjectList) -> Steal<()> {
+    pub fn steal_to(&self, dest: &StealableObjectList<T>) -> Steal<()> {
         self.inner.stealer().steal_batch(&dest.inner)
     }
 }
 
-impl Default for StealableObjectList {
+impl<T> Default for StealableObjectList<T> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl AsRef<StealableObjectList> for StealableObjectList {
-    fn as_ref(&self) -> &StealableObjectList {
+impl<T> AsRef<StealableObjectList<T>> for StealableObjectList<T> {
+    fn as_ref(&self) -> &StealableObjectList<T> {
         self
     }
 }
 
-impl AsMut<StealableObjectList> for StealableObjectList {
-    fn as_mut(&mut self) -> &mut StealableObjectList {
+impl<T> AsMut<StealableObjectList<T>> for StealableObjectList<T> {
+    fn as_mut(&mut self) -> &mut StealableObjectList<T> {
         &mut *self
     }
 }
@@ -246,6 +222,7 @@ impl AsMut<StealableObjectList> for StealableObjectList {
 #[cfg(test)]
 mod tests {
     use crate::{ObjectList, StealableObjectList};
+    use std::rc::Rc;
 
     #[test]
     fn test() {
@@ -255,19 +232,49 @@ mod tests {
         assert_eq!(&1, list.front().unwrap());
         assert_eq!(&1, list.front().unwrap());
         assert!(!list.is_empty());
-        list.push_back(true);
-        assert_eq!(&true, list.back().unwrap());
-        assert_eq!(&true, list.back().unwrap());
+        list.push_back(2);
+        assert_eq!(&2, list.back().unwrap());
+        assert_eq!(&2, list.back().unwrap());
 
         assert_eq!(&1, list.get(0).unwrap());
         assert_eq!(&1, list.get(0).unwrap());
-        assert_eq!(&true, list.get_mut(1).unwrap());
-        assert_eq!(&true, list.get_mut(1).unwrap());
+        assert_eq!(&2, list.get_mut(1).unwrap());
+        assert_eq!(&2, list.get_mut(1).unwrap());
+
+        assert_eq!(2, list.pop_back().unwrap());
+        assert_eq!(1, list.pop_back().unwrap());
+    }
 
-        let b: bool = list.pop_back().unwrap();
-        assert_eq!(true, b);
-        let n: i32 = list.pop_back().unwrap();
-        assert_eq!(1, n);
+    #[test]
+    fn test_iter() {
+        let mut list: ObjectList<i32> = (0..10).collect();
+        list.retain(|n| n % 2 == 0);
+        assert_eq!(Some(4), list.remove(2));
+        assert_eq!(None, list.remove(4));
+        for n in &mut list {
+            *n += 1;
+        }
+        list.extend([9]);
+        assert_eq!(
+            vec![1, 3, 7, 9, 9],
+            list.iter().copied().collect::<Vec<_>>()
+        );
+        list.move_front_to_back();
+        assert_eq!(vec![3, 7, 9, 9, 1], list.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_drop() {
+        let value = Rc::new(());
+        let mut list = ObjectList::new();
+        list.push_back(value.clone());
+        list.push_front(value.clone());
+        let pointer = list.into_raw();
+        assert_eq!(3, Rc::strong_count(&value));
+        let list = unsafe { ObjectList::<Rc<()>>::from_raw(pointer) };
+        assert_eq!(2, list.len());
+        drop(list);
+        assert_eq!(1, Rc::strong_count(&value));
     }
 
     #[test]
@@ -276,12 +283,14 @@ mod tests {
         assert!(list.is_empty());
         list.push_back(1);
         assert!(!list.is_empty());
-        list.push_back(true);
+        list.push_back(2);
         assert_eq!(2, list.len());
 
-        let n: i32 = list.pop_front().unwrap();
-        assert_eq!(1, n);
-        let b: bool = list.pop_front().unwrap();
-        assert_eq!(true, b);
+        let other = StealableObjectList::new();
+        assert!(list.steal_to(&other).is_success());
+        let mut other = other;
+        assert_eq!(Some(1), other.pop_front());
+        assert_eq!(Some(2), list.pop_front());
+        assert!(list.pop_front().is_none());
     }
 }
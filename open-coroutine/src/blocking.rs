@@ -0,0 +1,169 @@
+use crate::scheduler::Scheduler;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+//阻塞线程池的最大线程数
+const MAX_THREADS: usize = 64;
+
+//空闲线程的存活时间
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+struct Pool {
+    jobs: VecDeque<Job>,
+    threads: usize,
+    idle: usize,
+}
+
+static POOL: Mutex<Pool> = Mutex::new(Pool {
+    jobs: VecDeque::new(),
+    threads: 0,
+    idle: 0,
+});
+
+static CONDVAR: Condvar = Condvar::new();
+
+thread_local! {
+    //当前线程是否正在创建阻塞线程
+    static SPAWNING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 当前线程是否正在创建阻塞线程，阻塞线程不能在协程中执行，
+/// 否则阻塞任务又会被hook交给阻塞线程池
+pub fn is_spawning() -> bool {
+    SPAWNING.with(Cell::get)
+}
+
+/// 在阻塞线程池中执行f，期间当前协程被挂起，完成后回到原来的调度器上继续执行；
+/// 不在协程中时直接在当前线程执行
+pub fn spawn_blocking<R: Send + 'static>(f: impl FnOnce() -> R + Send + 'static) -> R {
+    let (waker, node) = match Scheduler::waker() {
+        Some(waker) => waker,
+        None => return f(),
+    };
+    let result = Arc::new(Mutex::new(None));
+    let slot = result.clone();
+    execute(Box::new(move || {
+        let r = panic::catch_unwind(AssertUnwindSafe(f));
+        *slot.lock().unwrap_or_else(PoisonError::into_inner) = Some(r);
+        waker.wake(&node);
+    }));
+    let r = loop {
+        if let Some(r) = result.lock().unwrap_or_else(PoisonError::into_inner).take() {
+            break r;
+        }
+        Scheduler::wait(None);
+    };
+    match r {
+        Ok(r) => r,
+        //把阻塞任务中的panic传播给协程
+        Err(e) => panic::resume_unwind(e),
+    }
+}
+
+fn execute(job: Job) {
+    {
+        let mut pool = POOL.lock().unwrap_or_else(PoisonError::into_inner);
+        pool.jobs.push_back(job);
+        if pool.idle > 0 || pool.threads >= MAX_THREADS {
+            CONDVAR.notify_one();
+            return;
+        }
+        pool.threads += 1;
+    }
+    //不持有锁创建线程，避免阻塞其他提交任务和执行完任务的线程
+    SPAWNING.with(|spawning| spawning.set(true));
+    let spawned = thread::Builder::new()
+        .name("open-coroutine-blocking".to_string())
+        .spawn(work);
+    SPAWNING.with(|spawning| spawning.set(false));
+    if spawned.is_ok() {
+        return;
+    }
+    //线程数达到系统上限，由已有线程处理
+    let jobs = {
+        let mut pool = POOL.lock().unwrap_or_else(PoisonError::into_inner);
+        pool.threads -= 1;
+        if pool.threads > 0 {
+            return;
+        }
+        std::mem::take(&mut pool.jobs)
+    };
+    //一个线程都没有，只能在当前线程执行，否则任务永远不会完成
+    for job in jobs {
+        job();
+    }
+}
+
+fn work() {
+    let mut pool = POOL.lock().unwrap_or_else(PoisonError::into_inner);
+    loop {
+        if let Some(job) = pool.jobs.pop_front() {
+            drop(pool);
+            job();
+            pool = POOL.lock().unwrap_or_else(PoisonError::into_inner);
+            continue;
+        }
+        pool.idle += 1;
+        let (guard, result) = CONDVAR
+            .wait_timeout(pool, KEEP_ALIVE)
+            .unwrap_or_else(PoisonError::into_inner);
+        pool = guard;
+        pool.idle -= 1;
+        if result.timed_out() && pool.jobs.is_empty() {
+            pool.threads -= 1;
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blocking::spawn_blocking;
+    use crate::coroutine::Coroutine;
+    use crate::scheduler::Scheduler;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        //不在协程中，直接执行
+        assert_eq!(
+            thread::current().id(),
+            spawn_blocking(|| thread::current().id())
+        );
+
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let id = spawn_blocking(|| {
+                    thread::sleep(Duration::from_millis(100));
+                    thread::current().id()
+                });
+                assert_ne!(thread::current().id(), id);
+                unsafe { (*pointer).push("blocking") };
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                unsafe { (*pointer).push("other") };
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        //阻塞任务执行期间，线程可以执行别的协程
+        assert_eq!(vec!["other", "blocking"], result);
+    }
+}
@@ -0,0 +1,764 @@
+use crate::waiter::Waiter;
+use ring_buffer::RingBuffer;
+use std::collections::VecDeque;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/*
+多生产者多消费者channel，可以在协程和线程之间、不同调度器之间使用，
+send在channel满时、recv在channel空时挂起当前协程，不在协程中时挂起线程
+ */
+
+/// 创建容量为cap的channel，cap为0时每次send都要等到有接收者取走数据
+pub fn bounded<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    channel(Some(cap))
+}
+
+/// 创建没有容量限制的channel，send永远不会挂起
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    channel(None)
+}
+
+fn channel<T>(cap: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let channel = Arc::new(Channel {
+        cap,
+        state: Mutex::new(State {
+            queue: RingBuffer::default(),
+            ticket: 0,
+            rendezvous: cap == Some(0),
+            senders: 1,
+            receivers: 1,
+            parked_receivers: 0,
+            closed: false,
+            send_waiters: VecDeque::new(),
+            recv_waiters: VecDeque::new(),
+        }),
+    });
+    (
+        Sender {
+            channel: channel.clone(),
+        },
+        Receiver { channel },
+    )
+}
+
+struct Channel<T> {
+    //None表示没有容量限制，Some(0)表示rendezvous
+    cap: Option<usize>,
+    state: Mutex<State<T>>,
+}
+
+struct State<T> {
+    //数据和放入时分配的编号，rendezvous模式下发送者通过编号判断数据是否被取走；
+    //可扩容，容量限制由cap控制
+    queue: RingBuffer<(u64, T)>,
+    ticket: u64,
+    rendezvous: bool,
+    senders: usize,
+    receivers: usize,
+    //阻塞在recv里的接收者数量，rendezvous模式下只有它们一定会取走数据，select注册的不算
+    parked_receivers: usize,
+    closed: bool,
+    send_waiters: VecDeque<Arc<Waiter>>,
+    recv_waiters: VecDeque<Arc<Waiter>>,
+}
+
+impl<T> State<T> {
+    //没有发送者或者被关闭，不会再有新数据
+    fn is_disconnected(&self) -> bool {
+        self.closed || self.senders == 0
+    }
+
+    //没有接收者或者被关闭，不能再发送数据
+    fn is_send_closed(&self) -> bool {
+        self.closed || self.receivers == 0
+    }
+
+    fn push(&mut self, value: T) -> u64 {
+        self.ticket += 1;
+        if self.queue.push_back((self.ticket, value)).is_err() {
+            unreachable!("channel queue is growable !");
+        }
+        wake_one(&mut self.recv_waiters);
+        self.ticket
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let (_, value) = self.queue.pop_front()?;
+        if self.rendezvous {
+            //不知道取走的是哪个发送者的数据，全部唤醒重新检查
+            for waiter in self.send_waiters.drain(..) {
+                waiter.wake();
+            }
+        } else {
+            wake_one(&mut self.send_waiters);
+        }
+        Some(value)
+    }
+
+    //编号为ticket的数据在队列中的位置，不在说明已经被取走
+    fn position(&self, ticket: u64) -> Option<usize> {
+        self.queue.iter().position(|(t, _)| *t == ticket)
+    }
+
+    fn wake_all(&mut self) {
+        for waiter in self
+            .send_waiters
+            .drain(..)
+            .chain(self.recv_waiters.drain(..))
+        {
+            waiter.wake();
+        }
+    }
+}
+
+fn wake_one(waiters: &mut VecDeque<Arc<Waiter>>) {
+    if let Some(waiter) = waiters.pop_front() {
+        waiter.wake();
+    }
+}
+
+//等待者放弃等待时从队列中删除自己，如果已经被唤醒，把唤醒转交给下一个等待者
+fn cancel(waiters: &mut VecDeque<Arc<Waiter>>, waiter: &Arc<Waiter>) {
+    let len = waiters.len();
+    waiters.retain(|w| !Arc::ptr_eq(w, waiter));
+    if waiters.len() == len && waiter.is_notified() {
+        wake_one(waiters);
+    }
+}
+
+impl<T> Channel<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn is_full(&self, state: &State<T>) -> bool {
+        match self.cap {
+            //rendezvous模式下只有接收者阻塞在recv里时才能放入，
+            //select可能选择其他分支，放入的数据会没人取走
+            Some(0) => state.queue.len() >= state.parked_receivers,
+            Some(cap) => state.queue.len() >= cap,
+            None => false,
+        }
+    }
+
+    fn send(&self, value: T, deadline: Option<Instant>) -> Result<(), SendTimeoutError<T>> {
+        let mut state = self.lock();
+        loop {
+            if state.is_send_closed() {
+                return Err(SendTimeoutError::Disconnected(value));
+            }
+            if self.cap != Some(0) && !self.is_full(&state) {
+                state.push(value);
+                return Ok(());
+            }
+            if self.cap == Some(0) {
+                return self.handoff(state, value, deadline);
+            }
+            let waiter = Waiter::current();
+            state.send_waiters.push_back(waiter.clone());
+            drop(state);
+            let notified = waiter.wait(deadline);
+            state = self.lock();
+            if !notified {
+                cancel(&mut state.send_waiters, &waiter);
+                return Err(SendTimeoutError::Timeout(value));
+            }
+        }
+    }
+
+    //rendezvous模式，放入数据后等待接收者取走
+    fn handoff<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, State<T>>,
+        value: T,
+        deadline: Option<Instant>,
+    ) -> Result<(), SendTimeoutError<T>> {
+        let ticket = state.push(value);
+        loop {
+            let index = match state.position(ticket) {
+                Some(index) => index,
+                None => return Ok(()),
+            };
+            if state.is_send_closed() {
+                let (_, value) = state
+                    .queue
+                    .remove(index)
+                    .expect("value must be in the queue");
+                return Err(SendTimeoutError::Disconnected(value));
+            }
+            let waiter = Waiter::current();
+            state.send_waiters.push_back(waiter.clone());
+            drop(state);
+            let notified = waiter.wait(deadline);
+            state = self.lock();
+            if !notified {
+                cancel(&mut state.send_waiters, &waiter);
+                //数据还没被取走，找回来
+                return match state.position(ticket) {
+                    Some(index) => {
+                        let (_, value) = state
+                            .queue
+                            .remove(index)
+                            .expect("value must be in the queue");
+                        Err(SendTimeoutError::Timeout(value))
+                    }
+                    None => Ok(()),
+                };
+            }
+        }
+    }
+
+    fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.lock();
+        if state.is_send_closed() {
+            return Err(TrySendError::Disconnected(value));
+        }
+        if self.is_full(&state) {
+            return Err(TrySendError::Full(value));
+        }
+        state.push(value);
+        Ok(())
+    }
+
+    fn recv(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        let mut state = self.lock();
+        loop {
+            if let Some(value) = state.pop() {
+                return Ok(value);
+            }
+            if state.is_disconnected() {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let waiter = Waiter::current();
+            state.recv_waiters.push_back(waiter.clone());
+            state.parked_receivers += 1;
+            drop(state);
+            let notified = waiter.wait(deadline);
+            state = self.lock();
+            state.parked_receivers -= 1;
+            if !notified {
+                cancel(&mut state.recv_waiters, &waiter);
+                //超时的同时可能有数据到达
+                return state.pop().ok_or(RecvTimeoutError::Timeout);
+            }
+        }
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.lock();
+        if let Some(value) = state.pop() {
+            return Ok(value);
+        }
+        if state.is_disconnected() {
+            return Err(TryRecvError::Disconnected);
+        }
+        Err(TryRecvError::Empty)
+    }
+
+    fn close(&self) -> bool {
+        let mut state = self.lock();
+        if state.closed {
+            return false;
+        }
+        state.closed = true;
+        state.wake_all();
+        true
+    }
+}
+
+/// channel的发送端，可以clone
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Sender<T> {
+    /// 发送数据，channel满时挂起，所有接收者都被drop或者channel被关闭时返回错误
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.channel.send(value, None).map_err(|error| match error {
+            SendTimeoutError::Timeout(value) | SendTimeoutError::Disconnected(value) => {
+                SendError(value)
+            }
+        })
+    }
+
+    pub fn send_timeout(&self, value: T, timeout: Duration) -> Result<(), SendTimeoutError<T>> {
+        self.channel.send(value, Some(Instant::now() + timeout))
+    }
+
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.channel.try_send(value)
+    }
+
+    //select等待期间有空位或者channel关闭时唤醒waiter
+    pub(crate) fn add_waiter(&self, waiter: &Arc<Waiter>) {
+        self.channel.lock().send_waiters.push_back(waiter.clone());
+    }
+
+    pub(crate) fn remove_waiter(&self, waiter: &Arc<Waiter>) {
+        cancel(&mut self.channel.lock().send_waiters, waiter);
+    }
+
+    /// 关闭channel，之后send都会失败，接收者仍然可以取走已有的数据，
+    /// 返回是否由本次调用关闭
+    pub fn close(&self) -> bool {
+        self.channel.close()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.channel.lock().is_send_closed()
+    }
+
+    pub fn len(&self) -> usize {
+        self.channel.lock().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// channel的容量，None表示没有容量限制
+    pub fn capacity(&self) -> Option<usize> {
+        self.channel.cap
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.channel.lock().senders += 1;
+        Sender {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.channel.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            state.wake_all();
+        }
+    }
+}
+
+impl<T> Debug for Sender<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sender")
+            .field("capacity", &self.channel.cap)
+            .finish_non_exhaustive()
+    }
+}
+
+/// channel的接收端，可以clone，每条数据只会被一个接收者收到
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// 接收数据，channel空时挂起，所有发送者都被drop或者channel被关闭，
+    /// 并且已有的数据都被取走后返回错误
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.channel.recv(None).map_err(|_| RecvError)
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.channel.recv(Some(Instant::now() + timeout))
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.channel.try_recv()
+    }
+
+    //select等待期间有数据或者channel断开时唤醒waiter，
+    //rendezvous模式下不算在等待的接收者，try_send不会因此成功
+    pub(crate) fn add_waiter(&self, waiter: &Arc<Waiter>) {
+        self.channel.lock().recv_waiters.push_back(waiter.clone());
+    }
+
+    pub(crate) fn remove_waiter(&self, waiter: &Arc<Waiter>) {
+        cancel(&mut self.channel.lock().recv_waiters, waiter);
+    }
+
+    /// 同Sender::close
+    pub fn close(&self) -> bool {
+        self.channel.close()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.channel.lock().is_disconnected()
+    }
+
+    pub fn len(&self) -> usize {
+        self.channel.lock().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.channel.cap
+    }
+
+    /// 阻塞地迭代，直到channel断开
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { receiver: self }
+    }
+
+    /// 只迭代当前已有的数据
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        TryIter { receiver: self }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.channel.lock().receivers += 1;
+        Receiver {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.channel.lock();
+        state.receivers -= 1;
+        if state.receivers == 0 {
+            state.wake_all();
+        }
+    }
+}
+
+impl<T> Debug for Receiver<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Receiver")
+            .field("capacity", &self.channel.cap)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+pub struct Iter<'a, T> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+#[derive(Debug)]
+pub struct TryIter<'a, T> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<T> Iterator for TryIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+#[derive(Debug)]
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { receiver: self }
+    }
+}
+
+/// channel已断开，带回没有发送出去的数据
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SendError<T>(pub T);
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TrySendError<T> {
+    Full(T),
+    Disconnected(T),
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum SendTimeoutError<T> {
+    Timeout(T),
+    Disconnected(T),
+}
+
+/// channel已断开并且没有数据
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RecvError;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RecvTimeoutError {
+    Timeout,
+    Disconnected,
+}
+
+//同std，Debug不要求T: Debug
+impl<T> Debug for SendError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SendError").finish_non_exhaustive()
+    }
+}
+
+impl<T> Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("Full(..)"),
+            TrySendError::Disconnected(_) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> Debug for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(_) => f.write_str("Timeout(..)"),
+            SendTimeoutError::Disconnected(_) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> Display for SendError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("sending on a closed channel")
+    }
+}
+
+impl<T> Display for TrySendError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("sending on a full channel"),
+            TrySendError::Disconnected(_) => f.write_str("sending on a closed channel"),
+        }
+    }
+}
+
+impl<T> Display for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(_) => f.write_str("timed out waiting on send operation"),
+            SendTimeoutError::Disconnected(_) => f.write_str("sending on a closed channel"),
+        }
+    }
+}
+
+impl Display for RecvError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("receiving on a closed channel")
+    }
+}
+
+impl Display for TryRecvError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("receiving on an empty channel"),
+            TryRecvError::Disconnected => f.write_str("receiving on a closed channel"),
+        }
+    }
+}
+
+impl Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout => f.write_str("timed out waiting on receive operation"),
+            RecvTimeoutError::Disconnected => f.write_str("receiving on a closed channel"),
+        }
+    }
+}
+
+impl<T> std::error::Error for SendError<T> {}
+
+impl<T> std::error::Error for TrySendError<T> {}
+
+impl<T> std::error::Error for SendTimeoutError<T> {}
+
+impl std::error::Error for RecvError {}
+
+impl std::error::Error for TryRecvError {}
+
+impl std::error::Error for RecvTimeoutError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::channel::{
+        bounded, unbounded, RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError,
+    };
+    use crate::coroutine::Coroutine;
+    use crate::scheduler::Scheduler;
+    use std::time::Duration;
+
+    #[test]
+    fn test_bounded() {
+        let (sender, receiver) = bounded(1);
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<String>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                for i in 0..3 {
+                    //channel满时挂起，等待接收者
+                    sender.send(i).unwrap();
+                    unsafe { (*pointer).push(format!("send {}", i)) };
+                }
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                for i in &receiver {
+                    unsafe { (*pointer).push(format!("recv {}", i)) };
+                }
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(
+            vec!["send 0", "recv 0", "send 1", "recv 1", "send 2", "recv 2"],
+            result
+        );
+    }
+
+    #[test]
+    fn test_rendezvous() {
+        let (sender, receiver) = bounded(0);
+        assert_eq!(Err(TrySendError::Full(1)), sender.try_send(1));
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                sender.send(1).unwrap();
+                //send返回时数据已经被取走
+                unsafe { (*pointer).push("sent") };
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                unsafe { (*pointer).push("recv") };
+                assert_eq!(Ok(1), receiver.recv());
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(vec!["recv", "sent"], result);
+    }
+
+    #[test]
+    fn test_threads() {
+        let (sender, receiver) = unbounded();
+        let (done, finished) = bounded(0);
+        let producers: Vec<_> = (0..4)
+            .map(|i| {
+                let sender = sender.clone();
+                std::thread::spawn(move || {
+                    for j in 0..100 {
+                        sender.send(i * 100 + j).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+        //接收者在另一个线程的协程中
+        let consumer = std::thread::spawn(move || {
+            let scheduler = Scheduler::current();
+            scheduler.execute(Coroutine::new(
+                64 * 1024,
+                move |param| {
+                    let sum: usize = receiver.iter().sum();
+                    done.send(sum).unwrap();
+                    param
+                },
+                None,
+            ));
+            scheduler.schedule();
+        });
+        assert_eq!(Ok((0..400).sum::<usize>()), finished.recv());
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        consumer.join().unwrap();
+    }
+
+    #[test]
+    fn test_close() {
+        let (sender, receiver) = bounded(2);
+        sender.send(1).unwrap();
+        assert!(receiver.close());
+        assert!(!sender.close());
+        assert_eq!(Err(SendError(2)), sender.send(2));
+        assert_eq!(Err(TrySendError::Disconnected(2)), sender.try_send(2));
+        //已有的数据仍然可以取走
+        assert_eq!(Ok(1), receiver.recv());
+        assert_eq!(Err(RecvError), receiver.recv());
+
+        let (sender, receiver) = unbounded::<i32>();
+        assert_eq!(Err(TryRecvError::Empty), receiver.try_recv());
+        drop(sender);
+        assert_eq!(Err(TryRecvError::Disconnected), receiver.try_recv());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let (sender, receiver) = bounded::<i32>(0);
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                assert_eq!(
+                    Err(RecvTimeoutError::Timeout),
+                    receiver.recv_timeout(Duration::from_millis(20))
+                );
+                assert!(sender.send_timeout(1, Duration::from_millis(20)).is_err());
+                assert!(sender.is_empty());
+                param
+            },
+            None,
+        ));
+        assert_eq!(1, scheduler.schedule().len());
+    }
+}
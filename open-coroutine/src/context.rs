@@ -60,6 +60,7 @@ impl Context {
     }
 
     #[inline(always)]
+    #[allow(unused)]
     pub(crate) fn switch(to: &Context, param: *mut c_void) -> Transfer {
         unsafe { jump_fcontext(to.0, param) }
     }
@@ -97,10 +98,12 @@ impl Transfer {
         Transfer { context, data }
     }
 
+    #[allow(unused)]
     pub fn resume(self, data: *mut c_void) -> Transfer {
         self.context.resume(data)
     }
 
+    #[allow(unused)]
     pub fn switch(to: &Transfer) -> Transfer {
         Context::switch(&to.context, to.data)
     }
@@ -133,7 +136,7 @@ extern "C" {
     /// # Arguments
     /// * `to` - A pointer to the `Context` with whom we swap execution.
     /// * `param`  - An arbitrary argument that will be set as the `data` field
-    ///          of the `Transfer` object passed to the other Context.
+    ///   of the `Transfer` object passed to the other Context.
     #[inline(never)]
     #[allow(unused)]
     fn jump_fcontext(to: &'static c_void, param: *mut c_void) -> Transfer;
@@ -143,7 +146,7 @@ extern "C" {
     /// # Arguments
     /// * `to` - A pointer to the `Context` with whom we swap execution.
     /// * `p`  - An arbitrary argument that will be set as the `data` field
-    ///          of the `Transfer` object passed to the other Context.
+    ///   of the `Transfer` object passed to the other Context.
     /// * `f`  - A function to be invoked on `to` before returning.
     #[inline(never)]
     #[allow(unused)]
@@ -183,7 +186,7 @@ mod tests {
 
         let context = Context::new(ManuallyDrop::new(stack), context_function);
         // Allocate a Context on the stack.
-        let mut t = Transfer::new(context, 0 as *mut c_void);
+        let mut t = Transfer::new(context, std::ptr::null_mut());
 
         // Yield 10 times to `context_function()`.
         for _ in 0..10 {
@@ -191,7 +194,7 @@ mod tests {
             // The `data` value is not used in this example and is left at 0.
             // The first and every other call will return references to the actual `Context` data.
             print!("Resuming => ");
-            t.data = 0 as *mut c_void;
+            t.data = std::ptr::null_mut();
             t = Transfer::switch(&t);
 
             println!("Got {}", t.data as usize);
@@ -225,7 +228,7 @@ mod tests {
         let context = Context::new(ManuallyDrop::new(stack), context_function);
 
         // Allocate a Context on the stack.
-        let mut t = Transfer::new(context, 0 as *mut c_void);
+        let mut t = Transfer::new(context, std::ptr::null_mut());
 
         // Yield 10 times to `context_function()`.
         for i in 0..10 {
@@ -1,11 +1,23 @@
 use crate::context::{Context, Transfer};
+use crate::future::JoinHandle;
+use crate::local::Locals;
+use crate::queue::{Kind, Node};
+use crate::reactor::WakeNode;
+use crate::registry::{self, CoroutineHandle};
 use crate::scheduler::Scheduler;
-use id_generator::IdGenerator;
-use memory_pool::memory::Memory;
+use id_generator::{CoroutineId, SchedulerId};
+use memory_pool::memory::{Memory, MemoryError};
+use object_list::intrusive::LinkedList;
+use std::cell::RefCell;
+use std::fmt::{Debug, Display, Formatter};
+use std::io::{Error, ErrorKind};
+use std::marker::PhantomData;
 use std::mem::ManuallyDrop;
 use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
 use std::time::Duration;
-use std::{ptr, thread};
+use std::{fmt, ptr, thread};
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -28,15 +40,62 @@ pub enum Status {
     Exited,
 }
 
+/// 调度优先级
 #[repr(C)]
-#[derive(Debug)]
-pub struct Coroutine<F: ?Sized> {
-    id: usize,
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Priority {
+    ///就绪队列里没有其他协程时才执行
+    Low,
+    ///按就绪的顺序执行
+    #[default]
+    Normal,
+    ///就绪时排到就绪队列头部
+    High,
+}
+
+/// Builder默认的栈大小
+pub const DEFAULT_STACK_SIZE: usize = 64 * 1024;
+
+/// 擦除了具体类型的用户函数
+pub type UserFunction = dyn FnOnce(Option<*mut c_void>) -> Option<*mut c_void>;
+
+//创建协程时所在的调度器：正在运行的协程或任务所属的调度器，其次是当前线程的调度器
+fn owner() -> SchedulerId {
+    let scheduler = COROUTINES
+        .try_with(|coroutines| coroutines.borrow().last().copied())
+        .ok()
+        .flatten()
+        .and_then(|inner| unsafe { (*inner).scheduler })
+        .or_else(|| crate::task::current().map(|(scheduler, _)| scheduler));
+    match scheduler {
+        Some(scheduler) => unsafe { (*scheduler).get_id() },
+        None => Scheduler::try_current().map_or(SchedulerId::NONE, |scheduler| scheduler.get_id()),
+    }
+}
+
+thread_local! {
+    //当前线程上正在运行的协程，协程里可以再resume别的协程，所以是个栈
+    static COROUTINES: RefCell<Vec<*mut Inner>> = const { RefCell::new(Vec::new()) };
+}
+
+/// 协程的实际数据，分配在堆上，保证地址在协程的整个生命周期内不变
+#[repr(C)]
+struct Inner {
+    //调度器队列的链接，在队列之间移动协程不需要分配内存，必须是第一个字段
+    node: Node,
+    id: CoroutineId,
+    name: Option<String>,
+    //创建时间，单位纳秒
+    created: u64,
     stack: ManuallyDrop<Memory>,
+    //协程自己的上下文
     sp: Transfer,
+    //resume此协程的上下文，协程挂起或结束时跳回这里
+    caller: Option<Context>,
     status: Status,
+    priority: Priority,
     //用户函数
-    proc: Box<F>,
+    proc: Option<Box<UserFunction>>,
     //调用用户函数的参数
     param: Option<*mut c_void>,
     //调用用户函数的结果
@@ -45,225 +104,362 @@ pub struct Coroutine<F: ?Sized> {
     exec_time: u64,
     //下一个执行的协程
     next: Option<*mut c_void>,
-    //入口协程，也是出口
-    entrance: Option<*mut c_void>,
     scheduler: Option<*mut Scheduler>,
+    //协程局部变量
+    locals: Locals,
+    //别的线程通过它唤醒协程，挂起时调度器在上面记录协程的地址
+    wake: Arc<WakeNode>,
+    //提交给调度器后在注册表里的句柄
+    handle: Option<Arc<CoroutineHandle>>,
+    //指向该协程的句柄数量，最后一个句柄释放时回收协程
+    refs: usize,
 }
 
-impl<F> Coroutine<F>
-where
-    F: FnOnce(Option<*mut c_void>) -> Option<*mut c_void> + Sized,
-{
-    extern "C" fn coroutine_function(mut t: Transfer) {
+impl Inner {
+    //同步更新注册表里的状态
+    fn set_status(&mut self, status: Status) {
+        self.status = status;
+        if let Some(handle) = &self.handle {
+            handle.set_status(status);
+        }
+    }
+}
+
+/// 协程句柄，多个句柄可以指向同一个协程，最后一个句柄释放时回收协程
+#[repr(C)]
+pub struct Coroutine<F: ?Sized> {
+    inner: *mut Inner,
+    phantom: PhantomData<Box<F>>,
+}
+
+impl<F: ?Sized> Debug for Coroutine<F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         unsafe {
-            loop {
-                let context = t.data as *mut Coroutine<F>;
-                if timer::now() < (*context).exec_time {
-                    //优先继续执行其他协程，否则让出CPU时间片
-                    match (*context).scheduler {
-                        Some(scheduler) => {
-                            (*scheduler).try_schedule();
-                        }
-                        None => thread::yield_now(),
-                    }
-                    continue;
-                }
-                //设置协程状态为运行中
-                (*context).status = Status::Running;
-                let param = (*context).param as Option<*mut c_void>;
-                match param {
-                    Some(data) => {
-                        print!("coroutine_function {} => ", data as usize)
-                    }
-                    None => {
-                        print!("coroutine_function no param => ")
-                    }
-                }
-                //调用用户函数
-                let result = (*context).invoke();
-                match (*context).next {
-                    Some(pointer) => {
-                        //不回跳，继续执行下一个指定的协程
-                        let next = pointer
-                            as *mut Coroutine<
-                                dyn FnOnce(Option<*mut c_void>) -> Option<*mut c_void>,
-                            >;
-                        std::mem::forget((*next).resume());
-                    }
-                    None => {
-                        match (*context).entrance {
-                            Some(pointer) => {
-                                //跳回主线程
-                                t = t.resume(pointer);
-                            }
-                            None => {
-                                //不回跳，直接执行下一个协程
-                                if let Some(scheduler) = (*context).scheduler {
-                                    (*scheduler).try_schedule();
-                                }
-                                //如果没有，也能跑，只不过回跳次数会更多
-                            }
-                        }
-                    }
-                }
-                //返回新的上下文
-                let func = ptr::read_unaligned((*context).proc.as_ref());
-                let mut new_context = Coroutine::init(
-                    //设置协程状态为已完成，resume的时候，已经调用完了用户函数
-                    (*context).id,
-                    (*context).stack,
-                    Status::Finished,
-                    Box::new(func),
-                    param,
-                    None,
-                );
-                new_context.set_result(result);
-                t = t.resume(&mut new_context as *mut Coroutine<F> as *mut c_void);
+            let inner = &*self.inner;
+            f.debug_struct("Coroutine")
+                .field("id", &inner.id)
+                .field("name", &inner.name)
+                .field("stack", &inner.stack)
+                .field("sp", &inner.sp)
+                .field("status", &inner.status)
+                .field("priority", &inner.priority)
+                .field("param", &inner.param)
+                .field("result", &inner.result)
+                .field("exec_time", &inner.exec_time)
+                .field("next", &inner.next)
+                .finish()
+        }
+    }
+}
+
+/// 带名字时输出'name' (id)，否则只输出id，用于panic和栈溢出报告
+impl<F: ?Sized> Display for Coroutine<F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        unsafe {
+            let inner = &*self.inner;
+            match &inner.name {
+                Some(name) => write!(f, "'{}' ({})", name, inner.id),
+                None => write!(f, "{}", inner.id),
             }
         }
     }
+}
 
+extern "C" fn coroutine_function(t: Transfer) {
+    unsafe {
+        let inner = t.data as *mut Inner;
+        (*inner).caller = Some(t.context);
+        //设置协程状态为运行中
+        (*inner).set_status(Status::Running);
+        if let Some(proc) = (*inner).proc.take() {
+            let param = (*inner).param;
+            //调用用户函数，panic不能跨越上下文传播
+            (*inner).result = panic::catch_unwind(AssertUnwindSafe(|| proc(param)))
+                .ok()
+                .flatten();
+        }
+        //在协程里析构局部变量，析构时仍然可以访问协程局部变量
+        _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            crate::local::destroy(&mut (*inner).locals)
+        }));
+        (*inner).set_status(Status::Finished);
+        if let Some(pointer) = (*inner).next {
+            //继续执行下一个指定的协程
+            Coroutine::<UserFunction>::from_raw(pointer).resume();
+        }
+        //跳回调度者，之后不会再回到这里
+        let caller = (*inner).caller.take().expect("caller not found !");
+        caller.resume(inner as *mut c_void);
+    }
+    unreachable!("finished coroutine resumed !");
+}
+
+impl<F> Coroutine<F>
+where
+    F: FnOnce(Option<*mut c_void>) -> Option<*mut c_void> + Sized,
+{
     pub fn new(size: usize, proc: F, param: Option<*mut c_void>) -> Self {
-        let stack = memory_pool::allocate(size).expect("allocate stack failed !");
-        Coroutine::init(
-            IdGenerator::next_id("coroutine"),
-            stack,
-            Status::Created,
-            Box::new(proc),
-            param,
-            None,
-        )
+        Coroutine::create(size, proc, param, owner()).expect("allocate stack failed !")
     }
 
-    fn init(
-        id: usize,
-        stack: ManuallyDrop<Memory>,
-        status: Status,
-        proc: Box<F>,
+    fn create(
+        size: usize,
+        proc: F,
         param: Option<*mut c_void>,
-        next: Option<*mut c_void>,
-    ) -> Self {
-        let inner = Context::new(stack, Coroutine::<F>::coroutine_function);
-        // Allocate a Context on the stack.
-        let sp = Transfer::new(inner, ptr::null_mut());
-        let mut context = Coroutine {
+        scheduler: SchedulerId,
+    ) -> Result<Self, MemoryError> {
+        crate::report::install();
+        let stack = memory_pool::allocate(size)?;
+        let proc: Box<dyn FnOnce(Option<*mut c_void>) -> Option<*mut c_void> + '_> = Box::new(proc);
+        //闭包的生命周期由句柄上的F保证，擦除F的into_dyn和CoroutineList要求F: 'static
+        let proc: Box<UserFunction> = unsafe { std::mem::transmute(proc) };
+        let id = CoroutineId::next(scheduler);
+        let inner = Inner {
+            node: Node::new(Kind::Coroutine),
             id,
+            name: None,
+            created: timer::now(),
             stack,
-            sp,
-            status,
-            proc,
+            sp: Transfer::new(Context::new(stack, coroutine_function), ptr::null_mut()),
+            caller: None,
+            status: Status::Created,
+            priority: Priority::Normal,
+            proc: Some(proc),
             param,
             result: None,
             //默认轮询到了立刻执行
             exec_time: 0,
-            next,
-            entrance: None,
+            next: None,
             scheduler: None,
+            locals: Locals::new(),
+            wake: Arc::new(WakeNode::new(id)),
+            handle: None,
+            refs: 1,
         };
-        context.sp.data = &mut context as *mut Coroutine<F> as *mut c_void;
-        context
+        Ok(Coroutine {
+            inner: Box::into_raw(Box::new(inner)),
+            phantom: PhantomData,
+        })
+    }
+
+    /// 擦除用户函数的类型，以便放入调度器；擦除后不再能约束闭包的生命周期，所以要求F: 'static
+    pub fn into_dyn(self) -> Coroutine<UserFunction>
+    where
+        F: 'static,
+    {
+        Coroutine::from_raw(self.inner as *mut c_void)
+    }
+}
+
+impl Coroutine<UserFunction> {
+    pub fn builder<'a>() -> Builder<'a> {
+        Builder::new()
     }
 
-    fn invoke(&mut self) -> Option<*mut c_void> {
+    //队列持有的句柄转换为节点，不改变引用计数
+    pub(crate) fn into_node(self) -> *mut Node {
+        ManuallyDrop::new(self).inner as *mut Node
+    }
+
+    /// # Safety
+    /// node必须来自[`Coroutine::into_node`]，并且只能转换一次
+    pub(crate) unsafe fn from_node(node: *mut Node) -> Self {
+        Coroutine {
+            inner: node as *mut Inner,
+            phantom: PhantomData,
+        }
+    }
+
+    /// 获取当前线程上正在运行的协程，不在协程中时返回None
+    pub fn current() -> Option<Self> {
+        //线程退出时hook仍可能被调用，此时线程局部变量可能已被销毁
+        COROUTINES
+            .try_with(|coroutines| {
+                coroutines
+                    .borrow()
+                    .last()
+                    .map(|inner| Coroutine::from_raw(*inner as *mut c_void))
+            })
+            .ok()
+            .flatten()
+    }
+
+    /// 栈溢出的协程：address落在当前协程的栈保护页里，在信号处理函数中调用，不能panic
+    pub(crate) fn overflowed(address: *const c_void) -> Option<Self> {
+        let inner = COROUTINES
+            .try_with(|coroutines| coroutines.try_borrow().ok()?.last().copied())
+            .ok()
+            .flatten()?;
+        unsafe { (*inner).stack.is_guard(address) }
+            .then(|| Coroutine::from_raw(inner as *mut c_void))
+    }
+
+    /// 挂起当前协程，跳回调度者，直到被再次resume
+    /// 调用前应该先设置好协程状态，调度者根据状态决定协程的去向
+    pub fn suspend() {
+        if let Some(coroutine) = Coroutine::current() {
+            unsafe {
+                let inner = coroutine.inner;
+                let caller = (*inner).caller.take().expect("caller not found !");
+                let t = caller.resume(inner as *mut c_void);
+                //再次被resume时，调度者可能已经换了
+                (*inner).caller = Some(t.context);
+                (*inner).set_status(Status::Running);
+            }
+        }
+    }
+}
+
+impl<F: ?Sized> Drop for Coroutine<F> {
+    fn drop(&mut self) {
         unsafe {
-            let func = ptr::read_unaligned(self.proc.as_ref());
-            let result = func(self.param);
-            self.set_result(result);
-            self.set_status(Status::Finished);
-            result
+            (*self.inner).refs -= 1;
+            if (*self.inner).refs == 0 {
+                //没有退出的协程，归还栈并从注册表注销
+                self.exit();
+                drop(Box::from_raw(self.inner));
+            }
         }
     }
 }
 
 impl<F: ?Sized> Coroutine<F> {
+    /// 通过指针构造新的句柄，指针必须来自仍然存活的协程的[`Coroutine::as_ptr`]
+    pub fn from_raw(pointer: *mut c_void) -> Self {
+        let inner = pointer as *mut Inner;
+        unsafe { (*inner).refs += 1 };
+        Coroutine {
+            inner,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut c_void {
+        self.inner as *mut c_void
+    }
+
     pub fn resume(&self) -> Self {
         //使用构造方法传入的参数，直接切换上下文
-        self.switch(&self.sp)
+        self.switch()
     }
 
     pub fn resume_with(&mut self, param: Option<*mut c_void>) -> Self {
         //覆盖用户参数
         self.set_param(param);
         //切换上下文
-        self.switch(&self.sp)
+        self.switch()
     }
 
-    pub fn resume_to(
-        &self,
-        to: &Coroutine<impl FnOnce(Option<*mut c_void>) -> Option<*mut c_void>>,
-    ) -> Self {
-        self.switch(&to.sp)
-    }
-
-    fn switch(&self, to: &Transfer) -> Self {
-        let sp = Transfer::switch(to);
-        let context = sp.data as *mut Coroutine<F>;
-        unsafe { ptr::read_unaligned(context) }
+    fn switch(&self) -> Self {
+        if !self.is_finished() {
+            unsafe {
+                //未到执行时间，优先执行其他协程，否则让出CPU时间片
+                while timer::now() < self.get_execute_time() {
+                    match (*self.inner).scheduler {
+                        Some(scheduler) => {
+                            (*scheduler).try_schedule();
+                        }
+                        None => thread::yield_now(),
+                    }
+                }
+                COROUTINES.with(|coroutines| coroutines.borrow_mut().push(self.inner));
+                let t = (*self.inner).sp.context.resume(self.inner as *mut c_void);
+                //协程挂起或结束，保存它的上下文
+                (*self.inner).sp.context = t.context;
+                COROUTINES.with(|coroutines| coroutines.borrow_mut().pop());
+            }
+        }
+        Coroutine::from_raw(self.as_ptr())
     }
 
     pub fn delay(&mut self, delay: Duration) -> Self {
-        self.set_delay(delay).set_status(Status::Suspend).resume()
+        self.set_delay(delay).suspend_if_alive().resume()
     }
 
     pub fn delay_with(&mut self, delay: Duration, param: Option<*mut c_void>) -> Self {
         self.set_delay(delay)
-            .set_status(Status::Suspend)
+            .suspend_if_alive()
             //覆盖用户参数
             .resume_with(param)
     }
 
+    fn suspend_if_alive(&mut self) -> &mut Self {
+        if !self.is_finished() {
+            self.set_status(Status::Suspend);
+        }
+        self
+    }
+
+    fn is_finished(&self) -> bool {
+        matches!(self.get_status(), Status::Finished | Status::Exited)
+    }
+
     pub fn exit(&mut self) {
+        if self.get_status() == Status::Exited {
+            return;
+        }
         self.set_status(Status::Exited);
         //只归还，不删除
-        memory_pool::revert(self.stack);
+        memory_pool::revert(unsafe { (*self.inner).stack });
+        if unsafe { (*self.inner).handle.take() }.is_some() {
+            registry::unregister(self.get_id());
+        }
     }
 
     ///下方开始get/set
-    pub fn get_id(&self) -> usize {
-        let context = self.sp.data as *mut Coroutine<F>;
-        unsafe { (*context).id }
+    pub fn get_id(&self) -> CoroutineId {
+        unsafe { (*self.inner).id }
+    }
+
+    pub fn get_name(&self) -> Option<&str> {
+        unsafe { (*self.inner).name.as_deref() }
+    }
+
+    /// 提交给调度器之前设置才会出现在注册表里
+    pub fn set_name(&mut self, name: impl Into<String>) -> &mut Self {
+        unsafe { (*self.inner).name = Some(name.into()) };
+        self
+    }
+
+    /// 创建时间，单位纳秒
+    pub fn get_created(&self) -> u64 {
+        unsafe { (*self.inner).created }
     }
 
     pub fn set_param(&mut self, param: Option<*mut c_void>) -> &mut Self {
-        self.param = param;
-        unsafe {
-            let context = self.sp.data as *mut Coroutine<F>;
-            (*context).param = param;
-        }
+        unsafe { (*self.inner).param = param };
         self
     }
 
     pub fn get_param(&self) -> Option<*mut c_void> {
-        let context = self.sp.data as *mut Coroutine<F>;
-        unsafe { (*context).param }
+        unsafe { (*self.inner).param }
     }
 
     pub fn set_result(&mut self, result: Option<*mut c_void>) -> &mut Self {
-        self.result = result;
-        unsafe {
-            let context = self.sp.data as *mut Coroutine<F>;
-            (*context).result = result;
-        }
+        unsafe { (*self.inner).result = result };
         self
     }
 
     pub fn get_result(&self) -> Option<*mut c_void> {
-        let context = self.sp.data as *mut Coroutine<F>;
-        unsafe { (*context).result }
+        unsafe { (*self.inner).result }
     }
 
     pub fn set_status(&mut self, status: Status) -> &mut Self {
-        self.status = status;
-        unsafe {
-            let context = self.sp.data as *mut Coroutine<F>;
-            (*context).status = status;
-        }
+        unsafe { (*self.inner).set_status(status) };
         self
     }
 
     pub fn get_status(&self) -> Status {
-        let context = self.sp.data as *mut Coroutine<F>;
-        unsafe { (*context).status }
+        unsafe { (*self.inner).status }
+    }
+
+    pub fn get_priority(&self) -> Priority {
+        unsafe { (*self.inner).priority }
+    }
+
+    pub fn set_priority(&mut self, priority: Priority) -> &mut Self {
+        unsafe { (*self.inner).priority = priority };
+        self
     }
 
     pub fn set_delay(&mut self, delay: Duration) -> &mut Self {
@@ -272,81 +468,279 @@ impl<F: ?Sized> Coroutine<F> {
     }
 
     pub fn get_execute_time(&self) -> u64 {
-        let context = self.sp.data as *mut Coroutine<F>;
-        unsafe { (*context).exec_time }
+        unsafe { (*self.inner).exec_time }
     }
 
     pub fn set_execute_time(&mut self, time: u64) -> &mut Self {
         //覆盖执行时间
-        self.exec_time = time;
-        unsafe {
-            let context = self.sp.data as *mut Coroutine<F>;
-            (*context).exec_time = time;
-        }
+        unsafe { (*self.inner).exec_time = time };
         self
     }
 
     pub fn get_next(&self) -> Option<*mut c_void> {
-        let context = self.sp.data as *mut Coroutine<F>;
-        unsafe { (*context).next }
+        unsafe { (*self.inner).next }
     }
 
     pub fn set_next(
         &mut self,
         next: &mut Coroutine<impl FnOnce(Option<*mut c_void>) -> Option<*mut c_void> + ?Sized>,
     ) -> &mut Self {
-        let pointer = next as *mut _ as *mut c_void;
-        self.set_next_ptr(pointer)
+        self.set_next_ptr(next.as_ptr())
     }
 
     pub fn set_next_ptr(&mut self, pointer: *mut c_void) -> &mut Self {
-        self.next = Some(pointer);
-        unsafe {
-            let context = self.sp.data as *mut Coroutine<F>;
-            (*context).next = Some(pointer);
-        }
+        unsafe { (*self.inner).next = Some(pointer) };
         self
     }
 
     pub fn has_next(&self) -> bool {
-        self.next.is_some()
+        self.get_next().is_some()
     }
 
-    pub fn get_entrance(&self) -> Option<*mut c_void> {
-        let context = self.sp.data as *mut Coroutine<F>;
-        unsafe { (*context).entrance }
+    pub(crate) fn locals(&self) -> *mut Locals {
+        unsafe { ptr::addr_of_mut!((*self.inner).locals) }
     }
 
-    #[allow(unused)]
-    pub(crate) fn set_entrance(
-        &mut self,
-        entrance: &Coroutine<impl FnOnce(Option<*mut c_void>) -> Option<*mut c_void> + ?Sized>,
-    ) -> &mut Self {
-        unsafe {
-            let mut entrance = ptr::read_unaligned(entrance.sp.context.0);
-            let pointer = &mut entrance as *mut c_void;
-            self.entrance = Some(pointer);
-            let context = self.sp.data as *mut Coroutine<F>;
-            (*context).entrance = Some(pointer);
-        }
+    pub(crate) fn get_handle(&self) -> Option<Arc<CoroutineHandle>> {
+        unsafe { (*self.inner).handle.clone() }
+    }
+
+    pub(crate) fn set_handle(&mut self, handle: Option<Arc<CoroutineHandle>>) -> &mut Self {
+        unsafe { (*self.inner).handle = handle };
         self
     }
 
-    #[allow(unused)]
+    pub(crate) fn wake_node(&self) -> &Arc<WakeNode> {
+        unsafe { &(*self.inner).wake }
+    }
+
+    pub(crate) fn get_scheduler(&self) -> Option<*mut Scheduler> {
+        unsafe { (*self.inner).scheduler }
+    }
+
     pub(crate) fn set_scheduler(&mut self, scheduler: &mut Scheduler) -> &mut Self {
-        unsafe {
-            self.scheduler = Some(scheduler as *mut Scheduler);
-            let context = self.sp.data as *mut Coroutine<F>;
-            (*context).scheduler = Some(scheduler as *mut Scheduler);
+        unsafe { (*self.inner).scheduler = Some(scheduler as *mut Scheduler) };
+        self
+    }
+}
+
+/// 协程的构造器，通过[`Coroutine::builder`]创建
+#[derive(Debug)]
+pub struct Builder<'a> {
+    name: Option<String>,
+    stack_size: usize,
+    priority: Priority,
+    delay: Option<Duration>,
+    scheduler: Option<&'a mut Scheduler>,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new() -> Self {
+        Builder {
+            name: None,
+            stack_size: DEFAULT_STACK_SIZE,
+            priority: Priority::Normal,
+            delay: None,
+            scheduler: None,
         }
+    }
+
+    /// 名字会出现在注册表、panic和栈溢出报告里
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// 小于最小值的栈大小会被向上取整
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = size;
+        self
+    }
+
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
         self
     }
+
+    /// 协程提交到的调度器，默认是当前线程的调度器
+    pub fn scheduler(mut self, scheduler: &'a mut Scheduler) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    /// 创建协程，不提交给调度器，栈大小超过memory_pool的限制时返回InvalidInput
+    pub fn build<F>(self, proc: F, param: Option<*mut c_void>) -> std::io::Result<Coroutine<F>>
+    where
+        F: FnOnce(Option<*mut c_void>) -> Option<*mut c_void> + 'static,
+    {
+        let scheduler = match &self.scheduler {
+            Some(scheduler) => scheduler.get_id(),
+            None => owner(),
+        };
+        self.create(proc, param, scheduler)
+    }
+
+    /// 创建协程并提交给调度器，通过返回的JoinHandle等待f的返回值
+    pub fn spawn<F, T>(mut self, f: F) -> std::io::Result<JoinHandle<T>>
+    where
+        F: FnOnce() -> T + 'static,
+        T: 'static,
+    {
+        let scheduler = self.scheduler.take().unwrap_or_else(Scheduler::current);
+        let id = scheduler.get_id();
+        crate::future::spawn(scheduler, f, |proc| self.create(proc, None, id))
+    }
+
+    fn create<F>(
+        &self,
+        proc: F,
+        param: Option<*mut c_void>,
+        scheduler: SchedulerId,
+    ) -> std::io::Result<Coroutine<F>>
+    where
+        F: FnOnce(Option<*mut c_void>) -> Option<*mut c_void>,
+    {
+        let size = self.stack_size;
+        let mut coroutine = memory_pool::check_size(size)
+            .and_then(|_| Coroutine::create(size, proc, param, scheduler))
+            .map_err(|error| match error {
+                MemoryError::ExceedsMaximumSize(max) => Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("stack size {} exceeds the maximum {} !", size, max),
+                ),
+                MemoryError::IoError(error) => error,
+            })?;
+        if let Some(name) = &self.name {
+            coroutine.set_name(name.clone());
+        }
+        if let Some(delay) = self.delay {
+            coroutine.set_delay(delay);
+        }
+        coroutine.set_priority(self.priority);
+        Ok(coroutine)
+    }
+}
+
+impl Default for Builder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 通过协程内嵌的链接串起来的协程队列，入队出队只修改指针；
+/// 一个协程同一时间只能在一个队列里
+#[derive(Debug, Default)]
+pub struct CoroutineList {
+    inner: LinkedList<Node>,
+}
+
+impl CoroutineList {
+    pub fn new() -> Self {
+        CoroutineList {
+            inner: LinkedList::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    //队列持有入队的句柄，出队时交还
+    fn link<F: ?Sized + 'static>(coroutine: Coroutine<F>) -> *mut Node {
+        let inner = ManuallyDrop::new(coroutine).inner;
+        unsafe { Node::enqueue(inner as *mut Node) }
+    }
+
+    fn unlink(node: *mut Node) -> Coroutine<UserFunction> {
+        unsafe { Coroutine::from_node(Node::dequeue(node)) }
+    }
+
+    fn execute_time(node: *mut Node) -> u64 {
+        unsafe { (*(node as *mut Inner)).exec_time }
+    }
+
+    /// 协程已经在某个队列里时panic
+    pub fn push_back<F: ?Sized + 'static>(&mut self, coroutine: Coroutine<F>) {
+        unsafe { self.inner.push_back(CoroutineList::link(coroutine)) };
+    }
+
+    /// 协程已经在某个队列里时panic
+    pub fn push_front<F: ?Sized + 'static>(&mut self, coroutine: Coroutine<F>) {
+        unsafe { self.inner.push_front(CoroutineList::link(coroutine)) };
+    }
+
+    /// 按执行时间插入，执行时间相同的排在后面，从尾部开始查找，
+    /// 队列里的协程在出队之前不能修改执行时间；协程已经在某个队列里时panic
+    pub fn insert<F: ?Sized + 'static>(&mut self, coroutine: Coroutine<F>) {
+        let time = coroutine.get_execute_time();
+        let prev = self
+            .inner
+            .iter()
+            .rev()
+            .find(|node| CoroutineList::execute_time(*node) <= time);
+        unsafe {
+            self.inner
+                .insert_after(prev, CoroutineList::link(coroutine))
+        };
+    }
+
+    pub fn front(&self) -> Option<Coroutine<UserFunction>> {
+        self.inner
+            .front()
+            .map(|node| Coroutine::from_raw(node as *mut c_void))
+    }
+
+    pub fn pop_front(&mut self) -> Option<Coroutine<UserFunction>> {
+        self.inner.pop_front().map(CoroutineList::unlink)
+    }
+
+    /// 从队列中摘除协程，交还队列持有的句柄
+    /// # Safety
+    /// 协程必须在这个队列里
+    pub unsafe fn remove<F: ?Sized>(
+        &mut self,
+        coroutine: &Coroutine<F>,
+    ) -> Coroutine<UserFunction> {
+        let node = coroutine.inner as *mut Node;
+        self.inner.remove(node);
+        CoroutineList::unlink(node)
+    }
+
+    /// 把other的协程全部移动到尾部
+    pub fn append(&mut self, other: &mut CoroutineList) {
+        self.inner.append(&mut other.inner);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Coroutine<UserFunction>> + '_ {
+        self.inner
+            .iter()
+            .map(|node| Coroutine::from_raw(node as *mut c_void))
+    }
+}
+
+impl Drop for CoroutineList {
+    fn drop(&mut self) {
+        while self.pop_front().is_some() {}
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::manual_dangling_ptr)]
 mod tests {
-    use crate::coroutine::Coroutine;
+    use crate::coroutine::{Coroutine, CoroutineList, Priority, Status, UserFunction};
+    use crate::scheduler::Scheduler;
+    use std::io::ErrorKind;
     use std::os::raw::c_void;
+    use std::rc::Rc;
     use std::time::Duration;
 
     #[test]
@@ -411,9 +805,157 @@ mod tests {
         );
         head.set_next(&mut middle);
         middle.set_next(&mut tail);
-
-        //设置出口为主线程
-        tail.set_entrance(&head);
         head.resume();
+        assert_eq!(Status::Finished, tail.get_status());
+    }
+
+    #[test]
+    fn suspend() {
+        let c = Coroutine::new(
+            2048,
+            |param| {
+                let mut current = Coroutine::<UserFunction>::current().unwrap();
+                current.set_status(Status::Suspend);
+                Coroutine::suspend();
+                param
+            },
+            Some(1usize as *mut c_void),
+        );
+        assert!(Coroutine::current().is_none());
+        c.resume();
+        assert_eq!(Status::Suspend, c.get_status());
+        assert_eq!(None, c.get_result());
+        c.resume();
+        assert_eq!(Status::Finished, c.get_status());
+        assert_eq!(Some(1usize as *mut c_void), c.get_result());
+    }
+
+    #[test]
+    fn coroutine_list() {
+        let coroutines: Vec<_> = (0..3)
+            .map(|_| Coroutine::new(2048, |param| param, None).into_dyn())
+            .collect();
+        let ids: Vec<_> = coroutines.iter().map(Coroutine::get_id).collect();
+        let mut list = CoroutineList::new();
+        list.push_back(Coroutine::<UserFunction>::from_raw(coroutines[1].as_ptr()));
+        list.push_front(Coroutine::<UserFunction>::from_raw(coroutines[0].as_ptr()));
+        let mut other = CoroutineList::new();
+        other.push_back(Coroutine::<UserFunction>::from_raw(coroutines[2].as_ptr()));
+        list.append(&mut other);
+        assert!(other.is_empty());
+        assert_eq!(ids, list.iter().map(|c| c.get_id()).collect::<Vec<_>>());
+        //在队列之间移动
+        let first = list.pop_front().unwrap();
+        assert_eq!(ids[0], first.get_id());
+        other.push_back(first);
+        assert_eq!(2, list.len());
+        assert_eq!(1, other.len());
+    }
+
+    #[test]
+    fn coroutine_list_insert() {
+        let coroutines: Vec<_> = [30, 10, 20, 10]
+            .into_iter()
+            .map(|time| {
+                let mut coroutine = Coroutine::new(2048, |param| param, None).into_dyn();
+                coroutine.set_execute_time(time);
+                coroutine
+            })
+            .collect();
+        let mut list = CoroutineList::new();
+        for coroutine in &coroutines {
+            list.insert(Coroutine::<UserFunction>::from_raw(coroutine.as_ptr()));
+        }
+        //按执行时间排序，相同时间的保持插入顺序
+        let ids: Vec<_> = [1, 3, 2, 0]
+            .iter()
+            .map(|i| coroutines[*i].get_id())
+            .collect();
+        assert_eq!(ids, list.iter().map(|c| c.get_id()).collect::<Vec<_>>());
+        assert_eq!(ids[0], list.front().unwrap().get_id());
+        let removed = unsafe { list.remove(&coroutines[2]) };
+        assert_eq!(coroutines[2].get_id(), removed.get_id());
+        assert_eq!(3, list.len());
+        //摘除后可以放入别的队列
+        let mut other = CoroutineList::new();
+        other.push_back(removed);
+        assert_eq!(1, other.len());
+    }
+
+    #[test]
+    #[should_panic(expected = "coroutine already queued !")]
+    fn coroutine_list_twice() {
+        let coroutine = Coroutine::new(2048, |param| param, None);
+        let mut list = CoroutineList::new();
+        list.push_back(Coroutine::<UserFunction>::from_raw(coroutine.as_ptr()));
+        list.push_back(coroutine);
+    }
+
+    #[test]
+    fn release() {
+        let counter = Rc::new(());
+        let captured = counter.clone();
+        let coroutine = Coroutine::new(
+            2048,
+            move |param| {
+                drop(captured);
+                param
+            },
+            None,
+        );
+        let other = Coroutine::<UserFunction>::from_raw(coroutine.as_ptr());
+        drop(coroutine);
+        assert_eq!(2, Rc::strong_count(&counter));
+        //最后一个句柄释放时，没有执行过的用户函数也会被释放
+        drop(other);
+        assert_eq!(1, Rc::strong_count(&counter));
+
+        let mut list = CoroutineList::new();
+        let captured = counter.clone();
+        list.push_back(Coroutine::new(
+            2048,
+            move |param| {
+                drop(captured);
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, Rc::strong_count(&counter));
+        drop(list);
+        assert_eq!(1, Rc::strong_count(&counter));
+    }
+
+    #[test]
+    fn builder() {
+        let mut scheduler = Scheduler::new();
+        let handle = Coroutine::builder()
+            .name("conn-42")
+            .stack_size(16 * 1024)
+            .priority(Priority::High)
+            .delay(Duration::from_millis(1))
+            .scheduler(&mut scheduler)
+            .spawn(|| {
+                let coroutine = Coroutine::current().unwrap();
+                assert_eq!(Some("conn-42"), coroutine.get_name());
+                assert_eq!(Priority::High, coroutine.get_priority());
+                format!("{}", coroutine)
+            })
+            .unwrap();
+        assert_eq!(scheduler.get_id(), handle.id().scheduler());
+        scheduler.schedule();
+        assert_eq!(
+            format!("'conn-42' ({})", handle.id()),
+            handle.join().unwrap()
+        );
+
+        let coroutine = Coroutine::builder().build(|param| param, None).unwrap();
+        assert_eq!(None, coroutine.get_name());
+        assert_eq!(coroutine.get_id().to_string(), coroutine.to_string());
+
+        let error = Coroutine::builder()
+            .stack_size(usize::MAX)
+            .build(|param| param, None)
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, error.kind());
     }
 }
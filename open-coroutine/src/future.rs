@@ -0,0 +1,418 @@
+use crate::coroutine::Coroutine;
+use crate::scheduler::Scheduler;
+use crate::sync::{lock, wait, WaitQueue};
+use crate::task;
+use crate::waiter::Waiter;
+use id_generator::CoroutineId;
+use std::fmt::{Debug, Formatter};
+use std::future::{poll_fn, Future};
+use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::{pin, Pin};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Duration;
+
+/*
+协程和rust future之间的桥接：
+await_future在协程里驱动future，Pending时挂起协程，被Waker唤醒后继续poll；
+JoinHandle实现了Future，async代码可以等待协程的结果；
+Scheduler::spawn_future把future作为无栈任务直接在调度器上执行，
+sleep和event让任务使用调度器的定时器和reactor
+ */
+
+impl Wake for Waiter {
+    fn wake(self: Arc<Self>) {
+        Waiter::wake(&self)
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        Waiter::wake(self)
+    }
+}
+
+/// 驱动future直到完成，Pending时挂起当前协程，被唤醒后重新poll，
+/// 不在协程中时挂起当前线程，相当于block_on
+pub fn await_future<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    loop {
+        //每次poll都换一个新的等待者，旧的Waker唤醒时最多造成一次虚假唤醒
+        let waiter = Waiter::current();
+        let waker = Waker::from(waiter.clone());
+        if let Poll::Ready(output) = future.as_mut().poll(&mut Context::from_waker(&waker)) {
+            return output;
+        }
+        waiter.wait(None);
+    }
+}
+
+/// 协程或者无栈任务的句柄，可以通过join或者.await获取返回值，
+/// panic时返回panic的内容，同std::thread::JoinHandle
+pub struct JoinHandle<T> {
+    id: CoroutineId,
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+struct JoinState<T> {
+    result: Option<std::thread::Result<T>>,
+    //结果已经被取走
+    taken: bool,
+    //.await时的Waker
+    waker: Option<Waker>,
+    //join时的等待者
+    waiters: WaitQueue,
+}
+
+impl<T> JoinState<T> {
+    fn new() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(JoinState {
+            result: None,
+            taken: false,
+            waker: None,
+            waiters: WaitQueue::new(),
+        }))
+    }
+}
+
+//保存结果并唤醒所有等待者
+fn complete<T>(state: &Mutex<JoinState<T>>, result: std::thread::Result<T>) {
+    let mut state = lock(state);
+    state.result = Some(result);
+    if let Some(waker) = state.waker.take() {
+        waker.wake();
+    }
+    state.waiters.notify_all();
+}
+
+/// 创建协程执行f，f的返回值通过JoinHandle获取，create用包装后的f创建协程
+pub(crate) fn spawn<F, T, P>(
+    scheduler: &mut Scheduler,
+    f: F,
+    create: impl FnOnce(
+        Box<dyn FnOnce(Option<*mut c_void>) -> Option<*mut c_void>>,
+    ) -> std::io::Result<Coroutine<P>>,
+) -> std::io::Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + 'static,
+    T: 'static,
+    P: FnOnce(Option<*mut c_void>) -> Option<*mut c_void> + 'static,
+{
+    let state = JoinState::new();
+    let completer = state.clone();
+    let coroutine = create(Box::new(move |param| {
+        complete(&completer, panic::catch_unwind(AssertUnwindSafe(f)));
+        param
+    }))?;
+    let id = coroutine.get_id();
+    scheduler.execute(coroutine);
+    Ok(JoinHandle { id, state })
+}
+
+/// 把future作为无栈任务提交给调度器，future的结果通过JoinHandle获取
+pub(crate) fn spawn_future<F>(scheduler: &mut Scheduler, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    let state = JoinState::new();
+    let completer = state.clone();
+    let mut future = Box::pin(future);
+    let id = scheduler.submit_future(poll_fn(move |cx| {
+        match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(Poll::Pending) => return Poll::Pending,
+            Ok(Poll::Ready(output)) => complete(&completer, Ok(output)),
+            Err(error) => complete(&completer, Err(error)),
+        }
+        Poll::Ready(())
+    }));
+    JoinHandle { id, state }
+}
+
+/// sleep返回的future，到时间后完成
+#[derive(Debug)]
+pub struct Sleep {
+    deadline: u64,
+}
+
+/// 等待duration，只能在Scheduler::spawn_future执行的任务中await，
+/// 使用调度器的定时器，不会阻塞线程
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        deadline: timer::get_timeout_time(duration),
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        if self.deadline <= timer::now() {
+            return Poll::Ready(());
+        }
+        let (scheduler, node) =
+            task::current().expect("Sleep must be polled by Scheduler::spawn_future");
+        unsafe { (*scheduler).add_timer(self.deadline, node.id()) };
+        Poll::Pending
+    }
+}
+
+/// event返回的future，fd就绪后完成，结果是poll返回的revents
+#[derive(Debug)]
+pub struct Event {
+    fd: libc::c_int,
+    events: libc::c_short,
+}
+
+/// 等待fd上的events事件，events同poll，只能在Scheduler::spawn_future执行的任务中await，
+/// 使用调度器的reactor，不会阻塞线程
+pub fn event(fd: libc::c_int, events: libc::c_short) -> Event {
+    Event { fd, events }
+}
+
+impl Future for Event {
+    type Output = libc::c_short;
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<libc::c_short> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd,
+            events: self.events,
+            revents: 0,
+        };
+        //可能是被定时器或者别的事件唤醒的，先检查fd是否已经就绪
+        if unsafe { libc::poll(&mut pollfd, 1, 0) } > 0 {
+            return Poll::Ready(pollfd.revents);
+        }
+        let (scheduler, node) =
+            task::current().expect("Event must be polled by Scheduler::spawn_future");
+        unsafe { (*scheduler).add_event(self.fd, self.events, &node) };
+        Poll::Pending
+    }
+}
+
+impl<T> JoinHandle<T> {
+    /// 协程或者任务的id
+    pub fn id(&self) -> CoroutineId {
+        self.id
+    }
+
+    pub fn is_finished(&self) -> bool {
+        let state = lock(&self.state);
+        state.taken || state.result.is_some()
+    }
+
+    /// 等待协程执行完成，在协程中时挂起当前协程，否则挂起当前线程；
+    /// 在协程所在调度器的线程上、不在协程中调用会一直阻塞
+    pub fn join(self) -> std::thread::Result<T> {
+        let mut state = lock(&self.state);
+        loop {
+            if let Some(result) = state.result.take() {
+                state.taken = true;
+                return result;
+            }
+            state = wait(&self.state, state, |s| &mut s.waiters, None).0;
+        }
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = std::thread::Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = lock(&self.state);
+        assert!(!state.taken, "JoinHandle polled after completion");
+        match state.result.take() {
+            Some(result) => {
+                state.taken = true;
+                Poll::Ready(result)
+            }
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("id", &self.id)
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coroutine::Coroutine;
+    use crate::future::{await_future, event, sleep};
+    use crate::scheduler::Scheduler;
+    use std::cell::RefCell;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Waker};
+    use std::time::Duration;
+
+    //由别的线程完成的future
+    #[derive(Default)]
+    struct Shared {
+        value: Option<i32>,
+        waker: Option<Waker>,
+    }
+
+    struct Oneshot(Arc<Mutex<Shared>>);
+
+    impl Future for Oneshot {
+        type Output = i32;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<i32> {
+            let mut shared = self.0.lock().unwrap();
+            match shared.value.take() {
+                Some(value) => Poll::Ready(value),
+                None => {
+                    shared.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    fn complete_later(shared: Arc<Mutex<Shared>>, value: i32) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            let mut shared = shared.lock().unwrap();
+            shared.value = Some(value);
+            if let Some(waker) = shared.waker.take() {
+                waker.wake();
+            }
+        })
+    }
+
+    #[test]
+    fn test_await_future() {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<i32>;
+        let scheduler = Scheduler::current();
+        let future = Oneshot(shared.clone());
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                let value = await_future(future);
+                unsafe { (*pointer).push(value) };
+                param
+            },
+            None,
+        ));
+        //等待future时只挂起协程，别的协程仍然可以执行
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                unsafe { (*pointer).push(0) };
+                param
+            },
+            None,
+        ));
+        let handle = complete_later(shared, 1);
+        assert_eq!(2, scheduler.schedule().len());
+        handle.join().unwrap();
+        assert_eq!(vec![0, 1], result);
+        //不在协程中时阻塞线程
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let handle = complete_later(shared.clone(), 2);
+        assert_eq!(2, await_future(Oneshot(shared)));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_join() {
+        let scheduler = Scheduler::current();
+        let first = scheduler.spawn(64 * 1024, || {
+            Scheduler::wait(Some(Duration::from_millis(10)));
+            1
+        });
+        let panicked = scheduler.spawn(64 * 1024, || -> i32 { panic!("oops") });
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<i32>;
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                //在协程中join只挂起协程
+                assert!(!first.is_finished());
+                unsafe { (*pointer).push(first.join().unwrap()) };
+                assert!(panicked.join().is_err());
+                param
+            },
+            None,
+        ));
+        scheduler.schedule();
+        assert_eq!(vec![1], result);
+    }
+
+    #[test]
+    fn test_future() {
+        //在别的线程上执行协程，当前线程await它的结果
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            let scheduler = Scheduler::current();
+            let handle = scheduler.spawn(64 * 1024, || {
+                Scheduler::wait(Some(Duration::from_millis(20)));
+                String::from("done")
+            });
+            sender.send(handle).unwrap();
+            scheduler.schedule();
+        });
+        let handle = receiver.recv().unwrap();
+        assert_eq!("done", await_future(async { handle.await.unwrap() }));
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_spawn_future() {
+        let result = Rc::new(RefCell::new(Vec::new()));
+        let mut fds = [0; 2];
+        assert_eq!(0, unsafe { libc::pipe(fds.as_mut_ptr()) });
+        let scheduler = Scheduler::current();
+        //无栈任务和协程共用定时器、reactor和就绪队列
+        let sleeping = result.clone();
+        let slept = scheduler.spawn_future(async move {
+            sleep(Duration::from_millis(40)).await;
+            sleeping.borrow_mut().push("sleep");
+        });
+        let reading = result.clone();
+        let read = scheduler.spawn_future(async move {
+            let revents = event(fds[0], libc::POLLIN).await;
+            assert_ne!(0, revents & libc::POLLIN);
+            reading.borrow_mut().push("event");
+            1
+        });
+        let coroutine = scheduler.spawn(64 * 1024, || {
+            Scheduler::wait(Some(Duration::from_millis(10)));
+            2
+        });
+        let panicked = scheduler.spawn_future(async { panic!("oops") });
+        let joining = result.clone();
+        let joined = scheduler.spawn_future(async move {
+            //任务可以await协程和别的任务
+            assert_eq!(2, coroutine.await.unwrap());
+            assert!(panicked.await.is_err());
+            joining.borrow_mut().push("join");
+            read.await.unwrap() + slept.await.map(|_| 1).unwrap()
+        });
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            assert_eq!(1, unsafe { libc::write(fds[1], [1u8].as_ptr() as _, 1) });
+        });
+        assert_eq!(1, scheduler.schedule().len());
+        writer.join().unwrap();
+        assert!(scheduler.is_empty());
+        assert_eq!(2, joined.join().unwrap());
+        assert_eq!(vec!["join", "event", "sleep"], *result.borrow());
+        unsafe {
+            libc::close(fds[0]);
+            libc::close(fds[1]);
+        }
+    }
+}
@@ -2,5 +2,35 @@ pub mod scheduler;
 
 pub mod coroutine;
 
+pub mod reactor;
+
+pub mod blocking;
+
+pub mod net;
+
+pub mod unix;
+
+pub mod channel;
+
+pub mod sync;
+
+pub mod select;
+
+pub mod local;
+
+pub mod future;
+
+pub mod registry;
+
+pub(crate) mod waiter;
+
+pub(crate) mod task;
+
+pub(crate) mod queue;
+
+pub(crate) mod report;
+
 /// 仅限框架内部使用的context
 pub(crate) mod context;
+
+pub use id_generator::{CoroutineId, SchedulerId};
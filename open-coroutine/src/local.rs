@@ -0,0 +1,323 @@
+use crate::coroutine::{Coroutine, UserFunction};
+use once_cell::sync::Lazy;
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{PoisonError, RwLock};
+
+/*
+协程局部变量，保存在协程上，协程在线程之间迁移也不会丢失，协程退出时析构；
+不在协程中时使用线程局部变量
+ */
+
+/// 协程局部变量的标识
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub(crate) enum LocalId {
+    //coroutine_local!定义的变量，用LocalKey的地址区分
+    Key(usize),
+    //co_setspecific设置的值
+    Specific(libc::pthread_key_t),
+}
+
+/// 协程上保存的所有局部变量
+pub(crate) type Locals = HashMap<LocalId, Box<dyn Any>>;
+
+/// 析构协程的局部变量，析构时可能又设置了新的局部变量，
+/// 同pthread最多重复PTHREAD_DESTRUCTOR_ITERATIONS次
+pub(crate) fn destroy(locals: *mut Locals) {
+    for _ in 0..4 {
+        let taken = std::mem::take(unsafe { &mut *locals });
+        if taken.is_empty() {
+            break;
+        }
+        drop(taken);
+    }
+}
+
+/// 协程局部变量，通过coroutine_local!定义
+pub struct LocalKey<T: 'static> {
+    init: fn() -> T,
+    //不在协程中时使用
+    fallback: &'static std::thread::LocalKey<T>,
+}
+
+impl<T: 'static> LocalKey<T> {
+    #[doc(hidden)]
+    pub const fn new(init: fn() -> T, fallback: &'static std::thread::LocalKey<T>) -> Self {
+        LocalKey { init, fallback }
+    }
+
+    /// 访问当前协程的值，第一次访问时初始化，不在协程中时访问当前线程的值
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let coroutine = match Coroutine::<UserFunction>::current() {
+            Some(coroutine) => coroutine,
+            None => return self.fallback.with(f),
+        };
+        let id = LocalId::Key(self as *const Self as usize);
+        let locals = coroutine.locals();
+        unsafe {
+            if !(&*locals).contains_key(&id) {
+                //初始化时可能访问别的协程局部变量，不能在持有entry时初始化
+                let value = (self.init)();
+                (&mut *locals).entry(id).or_insert_with(|| Box::new(value));
+            }
+            //Box里的值地址不变，HashMap扩容也不影响
+            let value = (&*locals)[&id]
+                .downcast_ref::<T>()
+                .expect("coroutine local type mismatch") as *const T;
+            f(&*value)
+        }
+    }
+}
+
+impl<T: 'static> Debug for LocalKey<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalKey").finish_non_exhaustive()
+    }
+}
+
+/// 定义协程局部变量，用法同thread_local!：
+/// ```ignore
+/// coroutine_local! {
+///     static TRACE_ID: Cell<u64> = Cell::new(0);
+/// }
+/// TRACE_ID.with(|id| id.set(1));
+/// ```
+#[macro_export]
+macro_rules! coroutine_local {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $crate::coroutine_local!($(#[$attr])* $vis static $name: $t = $init);
+        $crate::coroutine_local!($($rest)*);
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $(#[$attr])* $vis static $name: $crate::local::LocalKey<$t> = {
+            fn init() -> $t {
+                $init
+            }
+            ::std::thread_local! {
+                static FALLBACK: $t = init();
+            }
+            $crate::local::LocalKey::new(init, &FALLBACK)
+        };
+    };
+}
+
+/// co_setspecific设置的值，析构时调用key的destructor
+struct Specific {
+    value: *mut c_void,
+    generation: u64,
+    destructor: Option<unsafe extern "C" fn(*mut c_void)>,
+}
+
+impl Drop for Specific {
+    fn drop(&mut self) {
+        if let Some(destructor) = self.destructor {
+            //同pthread，值不为空才调用destructor
+            if !self.value.is_null() && is_alive(self.generation) {
+                unsafe { destructor(self.value) };
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Key {
+    //key被删除后可能被pthread复用，用generation区分
+    generation: u64,
+    destructor: Option<unsafe extern "C" fn(*mut c_void)>,
+}
+
+static KEYS: Lazy<RwLock<HashMap<libc::pthread_key_t, Key>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn find_key(key: libc::pthread_key_t) -> Option<Key> {
+    KEYS.read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&key)
+        .copied()
+}
+
+fn is_alive(generation: u64) -> bool {
+    KEYS.read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .values()
+        .any(|key| key.generation == generation)
+}
+
+/// 同pthread_key_create，返回的key同时也是pthread的key，
+/// destructor在协程退出时调用，不在协程中设置的值在线程退出时调用
+pub fn key_create(
+    destructor: Option<unsafe extern "C" fn(*mut c_void)>,
+) -> std::io::Result<libc::pthread_key_t> {
+    let mut key = 0;
+    let error = unsafe { libc::pthread_key_create(&mut key, destructor) };
+    if error != 0 {
+        return Err(std::io::Error::from_raw_os_error(error));
+    }
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    KEYS.write().unwrap_or_else(PoisonError::into_inner).insert(
+        key,
+        Key {
+            generation,
+            destructor,
+        },
+    );
+    Ok(key)
+}
+
+/// 同pthread_key_delete，不会调用destructor
+pub fn key_delete(key: libc::pthread_key_t) -> std::io::Result<()> {
+    if KEYS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&key)
+        .is_none()
+    {
+        return Err(std::io::Error::from_raw_os_error(libc::EINVAL));
+    }
+    match unsafe { libc::pthread_key_delete(key) } {
+        0 => Ok(()),
+        error => Err(std::io::Error::from_raw_os_error(error)),
+    }
+}
+
+/// 同pthread_getspecific，没有设置过时返回空指针
+pub fn get_specific(key: libc::pthread_key_t) -> *mut c_void {
+    let coroutine = match Coroutine::<UserFunction>::current() {
+        Some(coroutine) => coroutine,
+        None => return unsafe { libc::pthread_getspecific(key) },
+    };
+    let specific = unsafe { (&*coroutine.locals()).get(&LocalId::Specific(key)) }
+        .and_then(|value| value.downcast_ref::<Specific>());
+    match (specific, find_key(key)) {
+        (Some(specific), Some(current)) if specific.generation == current.generation => {
+            specific.value
+        }
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// 同pthread_setspecific，覆盖旧值时不会调用destructor
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub fn set_specific(key: libc::pthread_key_t, value: *const c_void) -> std::io::Result<()> {
+    let current = find_key(key).ok_or_else(|| std::io::Error::from_raw_os_error(libc::EINVAL))?;
+    let coroutine = match Coroutine::<UserFunction>::current() {
+        Some(coroutine) => coroutine,
+        None => {
+            return match unsafe { libc::pthread_setspecific(key, value) } {
+                0 => Ok(()),
+                error => Err(std::io::Error::from_raw_os_error(error)),
+            };
+        }
+    };
+    let specific = Specific {
+        value: value as *mut c_void,
+        generation: current.generation,
+        destructor: current.destructor,
+    };
+    let old =
+        unsafe { (&mut *coroutine.locals()).insert(LocalId::Specific(key), Box::new(specific)) };
+    if let Some(mut old) = old.and_then(|old| old.downcast::<Specific>().ok()) {
+        old.value = std::ptr::null_mut();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coroutine::Coroutine;
+    use crate::local::{get_specific, key_create, key_delete, set_specific};
+    use crate::scheduler::Scheduler;
+    use std::cell::Cell;
+    use std::os::raw::c_void;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Counter(Cell<usize>);
+
+    impl Drop for Counter {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(self.0.get(), Ordering::SeqCst);
+        }
+    }
+
+    coroutine_local! {
+        static COUNTER: Counter = Counter(Cell::new(0));
+        static NAME: Cell<&'static str> = Cell::new("none");
+    }
+
+    #[test]
+    fn test_local() {
+        NAME.with(|name| name.set("thread"));
+        let mut result: Vec<(&str, usize)> = Vec::new();
+        let pointer = &mut result as *mut Vec<(&str, usize)>;
+        let scheduler = Scheduler::current();
+        for (name, times) in [("a", 1usize), ("b", 10)] {
+            scheduler.execute(Coroutine::new(
+                64 * 1024,
+                move |param| {
+                    NAME.with(|n| n.set(name));
+                    for _ in 0..times {
+                        COUNTER.with(|counter| counter.0.set(counter.0.get() + 1));
+                        //挂起期间别的协程修改自己的值，互不影响
+                        Scheduler::wait(Some(Duration::from_millis(1)));
+                    }
+                    let value = COUNTER.with(|counter| counter.0.get());
+                    unsafe { (*pointer).push((NAME.with(Cell::get), value)) };
+                    param
+                },
+                None,
+            ));
+        }
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(vec![("a", 1), ("b", 10)], result);
+        //协程退出时析构
+        assert_eq!(11, DROPPED.load(Ordering::SeqCst));
+        assert_eq!("thread", NAME.with(Cell::get));
+    }
+
+    static DESTROYED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn destructor(value: *mut c_void) {
+        DESTROYED.fetch_add(value as usize, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_specific() {
+        let key = key_create(Some(destructor)).unwrap();
+        set_specific(key, 100 as *const c_void).unwrap();
+        let scheduler = Scheduler::current();
+        for i in 1..=2usize {
+            scheduler.execute(Coroutine::new(
+                64 * 1024,
+                move |param| {
+                    assert!(get_specific(key).is_null());
+                    set_specific(key, 7 as *const c_void).unwrap();
+                    //覆盖时不调用destructor
+                    set_specific(key, i as *const c_void).unwrap();
+                    Scheduler::wait(Some(Duration::from_millis(1)));
+                    assert_eq!(i, get_specific(key) as usize);
+                    param
+                },
+                None,
+            ));
+        }
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(1 + 2, DESTROYED.load(Ordering::SeqCst));
+        assert_eq!(100, get_specific(key) as usize);
+        set_specific(key, std::ptr::null()).unwrap();
+        key_delete(key).unwrap();
+        assert!(set_specific(key, 5 as *const c_void).is_err());
+        assert!(key_delete(key).is_err());
+    }
+}
@@ -0,0 +1,704 @@
+use crate::scheduler::Scheduler;
+use std::io::{Error, ErrorKind, IoSlice, IoSliceMut, Read, Write};
+use std::net::{Shutdown, SocketAddr, ToSocketAddrs};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/*
+和std::net对应的网络类型，不依赖libhook，
+fd总是非阻塞的，阻塞操作在协程中挂起当前协程，不在协程中时阻塞当前线程，
+超时时间同std保存在SO_RCVTIMEO/SO_SNDTIMEO中
+ */
+
+/// TCP监听socket，同std::net::TcpListener
+#[derive(Debug)]
+pub struct TcpListener {
+    inner: std::net::TcpListener,
+    //用户是否设置了非阻塞
+    nonblocking: AtomicBool,
+}
+
+impl TcpListener {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> std::io::Result<TcpListener> {
+        //bind和listen不会阻塞
+        TcpListener::from_std(std::net::TcpListener::bind(addr)?)
+    }
+
+    pub fn from_std(listener: std::net::TcpListener) -> std::io::Result<TcpListener> {
+        listener.set_nonblocking(true)?;
+        Ok(TcpListener {
+            inner: listener,
+            nonblocking: AtomicBool::new(false),
+        })
+    }
+
+    pub fn accept(&self) -> std::io::Result<(TcpStream, SocketAddr)> {
+        let (stream, addr) = blocking(
+            self.as_raw_fd(),
+            libc::POLLIN,
+            self.nonblocking.load(Ordering::Acquire),
+            || Ok(None),
+            || self.inner.accept(),
+        )?;
+        Ok((TcpStream::from_std(stream)?, addr))
+    }
+
+    pub fn incoming(&self) -> Incoming<'_> {
+        Incoming { listener: self }
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn try_clone(&self) -> std::io::Result<TcpListener> {
+        Ok(TcpListener {
+            inner: self.inner.try_clone()?,
+            nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
+        })
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn take_error(&self) -> std::io::Result<Option<Error>> {
+        self.inner.take_error()
+    }
+}
+
+/// TcpListener::incoming返回的迭代器，永远不会返回None
+#[derive(Debug)]
+pub struct Incoming<'a> {
+    listener: &'a TcpListener,
+}
+
+impl Iterator for Incoming<'_> {
+    type Item = std::io::Result<TcpStream>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.listener.accept().map(|(stream, _)| stream))
+    }
+}
+
+/// TCP连接，同std::net::TcpStream
+#[derive(Debug)]
+pub struct TcpStream {
+    inner: std::net::TcpStream,
+    nonblocking: AtomicBool,
+}
+
+impl TcpStream {
+    /// 依次尝试解析出的每个地址，返回第一个连接成功的
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> std::io::Result<TcpStream> {
+        each_addr(addr, |addr| TcpStream::connect_timeout_inner(addr, None))
+    }
+
+    pub fn connect_timeout(addr: &SocketAddr, timeout: Duration) -> std::io::Result<TcpStream> {
+        if timeout.is_zero() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "cannot set a 0 duration timeout",
+            ));
+        }
+        TcpStream::connect_timeout_inner(addr, Some(timeout))
+    }
+
+    fn connect_timeout_inner(
+        addr: &SocketAddr,
+        timeout: Option<Duration>,
+    ) -> std::io::Result<TcpStream> {
+        let family = match addr {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        };
+        let fd = unsafe { libc::socket(family, libc::SOCK_STREAM, 0) };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+        //先交给TcpStream管理，出错时自动关闭fd
+        let stream = TcpStream::from_std(unsafe { std::net::TcpStream::from_raw_fd(fd) })?;
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+        let (storage, len) = sockaddr(addr);
+        if unsafe { libc::connect(fd, &storage as *const _ as *const libc::sockaddr, len) } == 0 {
+            return Ok(stream);
+        }
+        let error = Error::last_os_error();
+        if !matches!(
+            error.raw_os_error(),
+            Some(libc::EINPROGRESS) | Some(libc::EINTR)
+        ) {
+            return Err(error);
+        }
+        let deadline = timeout.map(timer::get_timeout_time);
+        loop {
+            let mut pollfd = libc::pollfd {
+                fd,
+                events: libc::POLLOUT,
+                revents: 0,
+            };
+            if unsafe { libc::poll(&mut pollfd, 1, 0) } > 0 {
+                break;
+            }
+            let left = match deadline {
+                Some(deadline) => match deadline.checked_sub(timer::now()) {
+                    Some(left) if left > 0 => Some(Duration::from_nanos(left)),
+                    _ => return Err(Error::new(ErrorKind::TimedOut, "connection timed out")),
+                },
+                None => None,
+            };
+            wait(fd, libc::POLLOUT, left)?;
+        }
+        match stream.take_error()? {
+            Some(error) => Err(error),
+            None => Ok(stream),
+        }
+    }
+
+    pub fn from_std(stream: std::net::TcpStream) -> std::io::Result<TcpStream> {
+        stream.set_nonblocking(true)?;
+        Ok(TcpStream {
+            inner: stream,
+            nonblocking: AtomicBool::new(false),
+        })
+    }
+
+    pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> std::io::Result<()> {
+        self.inner.shutdown(how)
+    }
+
+    pub fn try_clone(&self) -> std::io::Result<TcpStream> {
+        Ok(TcpStream {
+            inner: self.inner.try_clone()?,
+            nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
+        })
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+
+    pub fn read_timeout(&self) -> std::io::Result<Option<Duration>> {
+        self.inner.read_timeout()
+    }
+
+    pub fn write_timeout(&self) -> std::io::Result<Option<Duration>> {
+        self.inner.write_timeout()
+    }
+
+    pub fn peek(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.recv(|| self.inner.peek(buf))
+    }
+
+    pub fn set_nodelay(&self, nodelay: bool) -> std::io::Result<()> {
+        self.inner.set_nodelay(nodelay)
+    }
+
+    pub fn nodelay(&self) -> std::io::Result<bool> {
+        self.inner.nodelay()
+    }
+
+    pub fn set_ttl(&self, ttl: u32) -> std::io::Result<()> {
+        self.inner.set_ttl(ttl)
+    }
+
+    pub fn ttl(&self) -> std::io::Result<u32> {
+        self.inner.ttl()
+    }
+
+    pub fn take_error(&self) -> std::io::Result<Option<Error>> {
+        self.inner.take_error()
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+
+    fn recv<R>(&self, f: impl FnMut() -> std::io::Result<R>) -> std::io::Result<R> {
+        blocking(
+            self.as_raw_fd(),
+            libc::POLLIN,
+            self.nonblocking.load(Ordering::Acquire),
+            || self.inner.read_timeout(),
+            f,
+        )
+    }
+
+    fn send<R>(&self, f: impl FnMut() -> std::io::Result<R>) -> std::io::Result<R> {
+        blocking(
+            self.as_raw_fd(),
+            libc::POLLOUT,
+            self.nonblocking.load(Ordering::Acquire),
+            || self.inner.write_timeout(),
+            f,
+        )
+    }
+}
+
+impl Read for TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        (&*self).read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        (&*self).read_vectored(bufs)
+    }
+}
+
+impl Read for &TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.recv(|| (&self.inner).read(buf))
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        self.recv(|| (&self.inner).read_vectored(bufs))
+    }
+}
+
+impl Write for TcpStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        (&*self).write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Write for &TcpStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.send(|| (&self.inner).write(buf))
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        self.send(|| (&self.inner).write_vectored(bufs))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// UDP socket，同std::net::UdpSocket
+#[derive(Debug)]
+pub struct UdpSocket {
+    inner: std::net::UdpSocket,
+    nonblocking: AtomicBool,
+}
+
+impl UdpSocket {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> std::io::Result<UdpSocket> {
+        UdpSocket::from_std(std::net::UdpSocket::bind(addr)?)
+    }
+
+    pub fn from_std(socket: std::net::UdpSocket) -> std::io::Result<UdpSocket> {
+        socket.set_nonblocking(true)?;
+        Ok(UdpSocket {
+            inner: socket,
+            nonblocking: AtomicBool::new(false),
+        })
+    }
+
+    /// UDP的connect只是设置默认地址，不会阻塞
+    pub fn connect<A: ToSocketAddrs>(&self, addr: A) -> std::io::Result<()> {
+        self.inner.connect(addr)
+    }
+
+    pub fn send(&self, buf: &[u8]) -> std::io::Result<usize> {
+        self.send_with(|| self.inner.send(buf))
+    }
+
+    pub fn recv(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.recv_with(|| self.inner.recv(buf))
+    }
+
+    pub fn peek(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.recv_with(|| self.inner.peek(buf))
+    }
+
+    /// 只发送到解析出的第一个地址，同std
+    pub fn send_to<A: ToSocketAddrs>(&self, buf: &[u8], addr: A) -> std::io::Result<usize> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "no addresses to send data to"))?;
+        self.send_with(|| self.inner.send_to(buf, addr))
+    }
+
+    pub fn recv_from(&self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
+        self.recv_with(|| self.inner.recv_from(buf))
+    }
+
+    pub fn peek_from(&self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
+        self.recv_with(|| self.inner.peek_from(buf))
+    }
+
+    pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn try_clone(&self) -> std::io::Result<UdpSocket> {
+        Ok(UdpSocket {
+            inner: self.inner.try_clone()?,
+            nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
+        })
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+
+    pub fn read_timeout(&self) -> std::io::Result<Option<Duration>> {
+        self.inner.read_timeout()
+    }
+
+    pub fn write_timeout(&self) -> std::io::Result<Option<Duration>> {
+        self.inner.write_timeout()
+    }
+
+    pub fn set_broadcast(&self, broadcast: bool) -> std::io::Result<()> {
+        self.inner.set_broadcast(broadcast)
+    }
+
+    pub fn broadcast(&self) -> std::io::Result<bool> {
+        self.inner.broadcast()
+    }
+
+    pub fn set_ttl(&self, ttl: u32) -> std::io::Result<()> {
+        self.inner.set_ttl(ttl)
+    }
+
+    pub fn ttl(&self) -> std::io::Result<u32> {
+        self.inner.ttl()
+    }
+
+    pub fn take_error(&self) -> std::io::Result<Option<Error>> {
+        self.inner.take_error()
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+
+    fn recv_with<R>(&self, f: impl FnMut() -> std::io::Result<R>) -> std::io::Result<R> {
+        blocking(
+            self.as_raw_fd(),
+            libc::POLLIN,
+            self.nonblocking.load(Ordering::Acquire),
+            || self.inner.read_timeout(),
+            f,
+        )
+    }
+
+    fn send_with<R>(&self, f: impl FnMut() -> std::io::Result<R>) -> std::io::Result<R> {
+        blocking(
+            self.as_raw_fd(),
+            libc::POLLOUT,
+            self.nonblocking.load(Ordering::Acquire),
+            || self.inner.write_timeout(),
+            f,
+        )
+    }
+}
+
+//$std是std中对应的类型
+macro_rules! impl_fd {
+    ($($ty:ident => $std:ty),*) => {
+        $(
+            impl std::os::unix::io::AsRawFd for $ty {
+                fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+                    std::os::unix::io::AsRawFd::as_raw_fd(&self.inner)
+                }
+            }
+
+            impl std::os::unix::io::IntoRawFd for $ty {
+                fn into_raw_fd(self) -> std::os::unix::io::RawFd {
+                    std::os::unix::io::IntoRawFd::into_raw_fd(self.inner)
+                }
+            }
+
+            impl std::os::unix::io::FromRawFd for $ty {
+                /// fd会被设置为非阻塞
+                unsafe fn from_raw_fd(fd: std::os::unix::io::RawFd) -> Self {
+                    $ty::from_std(<$std>::from_raw_fd(fd)).expect("set nonblocking failed")
+                }
+            }
+        )*
+    };
+}
+
+pub(crate) use impl_fd;
+
+impl_fd!(
+    TcpListener => std::net::TcpListener,
+    TcpStream => std::net::TcpStream,
+    UdpSocket => std::net::UdpSocket
+);
+
+//非阻塞fd上的阻塞操作，f返回WouldBlock时等待fd就绪后重试，
+//timeout在第一次需要等待时才读取
+pub(crate) fn blocking<R>(
+    fd: RawFd,
+    events: libc::c_short,
+    nonblocking: bool,
+    timeout: impl FnOnce() -> std::io::Result<Option<Duration>>,
+    mut f: impl FnMut() -> std::io::Result<R>,
+) -> std::io::Result<R> {
+    let r = f();
+    if nonblocking || !matches!(&r, Err(error) if error.kind() == ErrorKind::WouldBlock) {
+        return r;
+    }
+    let deadline = timeout()?.map(timer::get_timeout_time);
+    loop {
+        let left = match deadline {
+            Some(deadline) => match deadline.checked_sub(timer::now()) {
+                Some(left) if left > 0 => Some(Duration::from_nanos(left)),
+                //超时，同阻塞socket上SO_RCVTIMEO/SO_SNDTIMEO的行为
+                _ => return Err(Error::from_raw_os_error(libc::EAGAIN)),
+            },
+            None => None,
+        };
+        wait(fd, events, left)?;
+        match f() {
+            Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+            r => return r,
+        }
+    }
+}
+
+//等待fd就绪或者超时，在协程中挂起协程，否则阻塞线程
+pub(crate) fn wait(
+    fd: RawFd,
+    events: libc::c_short,
+    timeout: Option<Duration>,
+) -> std::io::Result<()> {
+    if Scheduler::wait_event(&[(fd, events)], timeout)? {
+        return Ok(());
+    }
+    let mut pollfd = libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+    let timeout = timeout.map_or(-1, |timeout| {
+        timeout
+            .as_nanos()
+            .div_ceil(1_000_000)
+            .min(libc::c_int::MAX as u128) as libc::c_int
+    });
+    if unsafe { libc::poll(&mut pollfd, 1, timeout) } < 0 {
+        let error = Error::last_os_error();
+        if error.kind() != ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+fn each_addr<A: ToSocketAddrs, R>(
+    addr: A,
+    mut f: impl FnMut(&SocketAddr) -> std::io::Result<R>,
+) -> std::io::Result<R> {
+    let mut last = None;
+    for addr in addr.to_socket_addrs()? {
+        match f(&addr) {
+            Ok(r) => return Ok(r),
+            Err(error) => last = Some(error),
+        }
+    }
+    Err(last.unwrap_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "could not resolve to any addresses",
+        )
+    }))
+}
+
+fn sockaddr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let len = match addr {
+        SocketAddr::V4(addr) => {
+            let sin = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = addr.port().to_be();
+            sin.sin_addr.s_addr = u32::from_ne_bytes(addr.ip().octets());
+            std::mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(addr) => {
+            let sin6 = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = addr.port().to_be();
+            sin6.sin6_flowinfo = addr.flowinfo();
+            sin6.sin6_addr.s6_addr = addr.ip().octets();
+            sin6.sin6_scope_id = addr.scope_id();
+            std::mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+    (storage, len as libc::socklen_t)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coroutine::Coroutine;
+    use crate::net::{TcpListener, TcpStream, UdpSocket};
+    use crate::scheduler::Scheduler;
+    use std::io::{ErrorKind, Read, Write};
+    use std::time::Duration;
+
+    #[test]
+    fn test_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<String>;
+        let scheduler = Scheduler::current();
+        //服务端和客户端在同一个线程上，阻塞操作只会挂起协程
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                let (mut stream, _) = listener.accept().unwrap();
+                unsafe { (*pointer).push("accept".to_string()) };
+                let mut buf = [0u8; 5];
+                stream.read_exact(&mut buf).unwrap();
+                stream.write_all(&buf).unwrap();
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                let mut stream = TcpStream::connect(addr).unwrap();
+                unsafe { (*pointer).push("connect".to_string()) };
+                stream.write_all(b"hello").unwrap();
+                let mut buf = String::new();
+                stream.read_to_string(&mut buf).unwrap();
+                unsafe { (*pointer).push(buf) };
+                param
+            },
+            None,
+        ));
+        //没有阻塞线程，两个协程都能执行完
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(3, result.len());
+        assert_eq!("hello", result[2]);
+    }
+
+    #[test]
+    fn test_tcp_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                let mut stream = TcpStream::connect(addr).unwrap();
+                stream
+                    .set_read_timeout(Some(Duration::from_millis(50)))
+                    .unwrap();
+                let error = stream.read(&mut [0u8; 1]).unwrap_err();
+                assert_eq!(ErrorKind::WouldBlock, error.kind());
+                unsafe { (*pointer).push("timeout") };
+                stream.set_nonblocking(true).unwrap();
+                let error = stream.read(&mut [0u8; 1]).unwrap_err();
+                assert_eq!(ErrorKind::WouldBlock, error.kind());
+                drop(listener);
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                unsafe { (*pointer).push("other") };
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(vec!["other", "timeout"], result);
+    }
+
+    #[test]
+    fn test_tcp_thread() {
+        //不在协程中时阻塞线程，行为同std
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            stream.write_all(b"hi").unwrap();
+        });
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut buf = String::new();
+        stream.read_to_string(&mut buf).unwrap();
+        assert_eq!("hi", buf);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_udp() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                let mut buf = [0u8; 16];
+                let (n, from) = server.recv_from(&mut buf).unwrap();
+                assert_eq!(b"ping", &buf[..n]);
+                unsafe { (*pointer).push("recv") };
+                server.send_to(b"pong", from).unwrap();
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                client.connect(addr).unwrap();
+                client.send(b"ping").unwrap();
+                unsafe { (*pointer).push("send") };
+                let mut buf = [0u8; 16];
+                let n = client.recv(&mut buf).unwrap();
+                assert_eq!(b"pong", &buf[..n]);
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(vec!["send", "recv"], result);
+    }
+}
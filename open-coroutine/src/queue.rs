@@ -0,0 +1,126 @@
+use crate::coroutine::{Coroutine, UserFunction};
+use crate::task::Task;
+use object_list::intrusive::{Link, Linked, LinkedList};
+use std::ptr;
+
+/*
+调度器队列的节点头，嵌在协程和无栈任务的第一个字段，
+协程和任务可以排在同一个队列里，入队出队只修改指针，不分配内存；
+一个节点同一时间只能在一个队列里
+ */
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum Kind {
+    Coroutine,
+    Task,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct Node {
+    link: Link<Node>,
+    kind: Kind,
+    //是否在某个队列里
+    linked: bool,
+}
+
+unsafe impl Linked for Node {
+    fn link(node: *mut Self) -> *mut Link<Self> {
+        unsafe { ptr::addr_of_mut!((*node).link) }
+    }
+}
+
+impl Node {
+    pub fn new(kind: Kind) -> Self {
+        Node {
+            link: Link::new(),
+            kind,
+            linked: false,
+        }
+    }
+
+    /// 标记为已入队，已经在某个队列里时panic
+    /// # Safety
+    /// node必须有效
+    pub unsafe fn enqueue(node: *mut Node) -> *mut Node {
+        assert!(!(*node).linked, "coroutine already queued !");
+        (*node).linked = true;
+        node
+    }
+
+    /// 标记为已出队
+    /// # Safety
+    /// node必须有效
+    pub unsafe fn dequeue(node: *mut Node) -> *mut Node {
+        (*node).linked = false;
+        node
+    }
+}
+
+//就绪队列里的元素，有栈协程和无栈任务共用一个队列
+#[derive(Debug)]
+pub(crate) enum Runnable {
+    Coroutine(Coroutine<UserFunction>),
+    Task(Box<Task>),
+}
+
+impl Runnable {
+    //队列持有入队的协程或者任务，出队时交还
+    fn into_node(self) -> *mut Node {
+        unsafe {
+            Node::enqueue(match self {
+                Runnable::Coroutine(coroutine) => coroutine.into_node(),
+                Runnable::Task(task) => task.into_node(),
+            })
+        }
+    }
+
+    unsafe fn from_node(node: *mut Node) -> Self {
+        match (*Node::dequeue(node)).kind {
+            Kind::Coroutine => Runnable::Coroutine(Coroutine::from_node(node)),
+            Kind::Task => Runnable::Task(Task::from_node(node)),
+        }
+    }
+}
+
+/// 协程和任务共用的就绪队列，保持两者的先后顺序
+#[derive(Debug, Default)]
+pub(crate) struct ReadyQueue {
+    inner: LinkedList<Node>,
+}
+
+impl ReadyQueue {
+    pub fn new() -> Self {
+        ReadyQueue {
+            inner: LinkedList::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn push_back(&mut self, runnable: Runnable) {
+        unsafe { self.inner.push_back(runnable.into_node()) };
+    }
+
+    pub fn push_front(&mut self, runnable: Runnable) {
+        unsafe { self.inner.push_front(runnable.into_node()) };
+    }
+
+    pub fn pop_front(&mut self) -> Option<Runnable> {
+        self.inner
+            .pop_front()
+            .map(|node| unsafe { Runnable::from_node(node) })
+    }
+}
+
+impl Drop for ReadyQueue {
+    fn drop(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+}
@@ -0,0 +1,466 @@
+use id_generator::CoroutineId;
+use object_list::intrusive::{Link, Linked, MpscList};
+use once_cell::sync::Lazy;
+use std::cell::UnsafeCell;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Debug, Formatter};
+use std::io::Error;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/*
+全局的fd索引，记录每个fd上等待的协程和它所在reactor的Waker；
+fd是进程级别的，可能在任意线程被关闭，关闭时通过索引唤醒所有线程上的等待者；
+按fd分片加锁，不同fd的注册和关闭互不影响
+ */
+
+const SHARDS: usize = 64;
+
+//fd -> 等待者的唤醒节点和它所在reactor的Waker
+type FdWaiters = HashMap<libc::c_int, Vec<(Arc<WakeNode>, Arc<Waker>)>>;
+
+type Shard = Mutex<FdWaiters>;
+
+static INDEX: Lazy<Box<[Shard]>> = Lazy::new(|| (0..SHARDS).map(|_| Mutex::default()).collect());
+
+//因等待的fd被关闭而唤醒、还没有检查的协程id
+static CLOSED: Mutex<BTreeSet<CoroutineId>> = Mutex::new(BTreeSet::new());
+
+fn shard(fd: libc::c_int) -> MutexGuard<'static, FdWaiters> {
+    INDEX[fd as usize % SHARDS]
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// fd即将被关闭或者被shutdown，唤醒所有线程上等待该fd的协程，
+/// closed为true时标记这些协程，通过[`take_closed`]检查
+pub fn wake_fd(fd: libc::c_int, closed: bool) {
+    let mut shard = shard(fd);
+    for (node, waker) in shard.remove(&fd).unwrap_or_default() {
+        //持有分片锁时标记，等待者注销后再检查，不会漏掉也不会残留
+        if closed {
+            CLOSED
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(node.id);
+        }
+        waker.wake(&node);
+    }
+}
+
+/// 协程是否因为等待的fd被关闭而被唤醒，需要先通过[`Reactor::remove_event`]注销，检查后清除标记
+pub fn take_closed(id: CoroutineId) -> bool {
+    CLOSED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&id)
+}
+
+/// 协程或者无栈任务的唤醒节点，协程和任务创建时分配，之后可以在任意线程反复唤醒，
+/// 每次唤醒不再分配内存；节点还没有被reactor取走时，再次唤醒会被合并
+pub struct WakeNode {
+    id: CoroutineId,
+    //Waker队列的链接，节点被多个线程共享，只有入队的线程和reactor会修改
+    link: UnsafeCell<Link<WakeNode>>,
+    //是否在某个Waker的队列里
+    queued: AtomicBool,
+    //被调度器挂起的协程，只在调度器所在线程读写
+    parked: AtomicPtr<c_void>,
+}
+
+unsafe impl Send for WakeNode {}
+
+unsafe impl Sync for WakeNode {}
+
+unsafe impl Linked for WakeNode {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn link(node: *mut Self) -> *mut Link<Self> {
+        unsafe { UnsafeCell::raw_get(ptr::addr_of!((*node).link)) }
+    }
+}
+
+impl Debug for WakeNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WakeNode")
+            .field("id", &self.id)
+            .field("queued", &self.queued.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+impl WakeNode {
+    pub fn new(id: CoroutineId) -> Self {
+        WakeNode {
+            id,
+            link: UnsafeCell::new(Link::new()),
+            queued: AtomicBool::new(false),
+            parked: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    pub fn id(&self) -> CoroutineId {
+        self.id
+    }
+
+    /// 调度器挂起协程时记录协程的地址
+    pub(crate) fn set_parked(&self, pointer: *mut c_void) {
+        self.parked.store(pointer, Ordering::Relaxed);
+    }
+
+    pub(crate) fn get_parked(&self) -> Option<*mut c_void> {
+        let pointer = self.parked.load(Ordering::Relaxed);
+        (!pointer.is_null()).then_some(pointer)
+    }
+
+    /// 协程被唤醒，不再挂起
+    pub(crate) fn take_parked(&self) -> Option<*mut c_void> {
+        let pointer = self.parked.swap(ptr::null_mut(), Ordering::Relaxed);
+        (!pointer.is_null()).then_some(pointer)
+    }
+}
+
+/// 别的线程通过Waker唤醒reactor所在线程上的协程，
+/// 被唤醒的协程的唤醒节点先放到无锁队列里，队列从空变为非空时往管道写一个字节打断poll
+#[derive(Debug)]
+pub struct Waker {
+    //[读端, 写端]，都是非阻塞的
+    fds: [libc::c_int; 2],
+    //队列里的每个节点持有一个Arc引用，取走时释放
+    woken: MpscList<WakeNode>,
+}
+
+impl Waker {
+    fn new() -> Self {
+        let mut fds = [-1; 2];
+        unsafe {
+            if libc::pipe(fds.as_mut_ptr()) == 0 {
+                for fd in fds {
+                    let flags = libc::fcntl(fd, libc::F_GETFL);
+                    libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+                    libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+                }
+            }
+        }
+        Waker {
+            fds,
+            woken: MpscList::new(),
+        }
+    }
+
+    /// 唤醒节点对应的协程或者任务，可以在任意线程调用
+    pub fn wake(&self, node: &Arc<WakeNode>) {
+        //已经在队列里，还没有被reactor取走
+        if node.queued.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let node = Arc::into_raw(node.clone()) as *mut WakeNode;
+        //队列原来不为空时，之前的唤醒已经写过管道
+        if unsafe { self.woken.push(node) } {
+            //管道满了说明已经有未处理的通知，忽略错误
+            unsafe { libc::write(self.fds[1], [1u8].as_ptr() as *const libc::c_void, 1) };
+        }
+    }
+
+    fn has_woken(&self) -> bool {
+        !self.woken.is_empty()
+    }
+
+    fn take_woken(&self) -> Vec<Arc<WakeNode>> {
+        let mut buf = [0u8; 64];
+        while unsafe {
+            libc::read(
+                self.fds[0],
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+            )
+        } > 0
+        {}
+        let mut woken = self.woken.take();
+        let mut nodes = Vec::with_capacity(woken.len());
+        while let Some(node) = woken.pop_front() {
+            let node = unsafe { Arc::from_raw(node) };
+            //出队之后的唤醒重新入队，swap和入队时的swap同步，不会丢失唤醒
+            node.queued.swap(false, Ordering::AcqRel);
+            nodes.push(node);
+        }
+        nodes
+    }
+}
+
+impl Drop for Waker {
+    fn drop(&mut self) {
+        let mut woken = self.woken.take();
+        while let Some(node) = woken.pop_front() {
+            let node = unsafe { Arc::from_raw(node) };
+            node.queued.store(false, Ordering::Release);
+        }
+        for fd in self.fds {
+            if fd >= 0 {
+                unsafe { libc::close(fd) };
+            }
+        }
+    }
+}
+
+/// 基于poll实现，兼容linux和mac
+#[derive(Debug)]
+pub struct Reactor {
+    //fd -> 等待该fd的协程的唤醒节点及关注的事件
+    waiters: HashMap<libc::c_int, Vec<(Arc<WakeNode>, libc::c_short)>>,
+    //协程id -> 协程注册的fd
+    registered: HashMap<CoroutineId, Vec<libc::c_int>>,
+    waker: Arc<Waker>,
+}
+
+impl Default for Reactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//从全局索引里删除还没有触发的事件
+impl Drop for Reactor {
+    fn drop(&mut self) {
+        let ids: Vec<CoroutineId> = self.registered.keys().copied().collect();
+        for id in ids {
+            self.remove_event(id);
+        }
+    }
+}
+
+impl Reactor {
+    pub fn new() -> Self {
+        Reactor {
+            waiters: HashMap::new(),
+            registered: HashMap::new(),
+            waker: Arc::new(Waker::new()),
+        }
+    }
+
+    pub fn waker(&self) -> Arc<Waker> {
+        self.waker.clone()
+    }
+
+    /// 是否有被别的线程唤醒、但还没处理的协程
+    pub fn has_woken(&self) -> bool {
+        self.waker.has_woken()
+    }
+
+    /// 注册的fd数量
+    pub fn len(&self) -> usize {
+        self.waiters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+
+    /// 唤醒节点对应的协程关注fd上的events事件，events同poll
+    pub fn add_event(&mut self, fd: libc::c_int, events: libc::c_short, node: &Arc<WakeNode>) {
+        self.waiters
+            .entry(fd)
+            .or_default()
+            .push((node.clone(), events));
+        self.registered.entry(node.id).or_default().push(fd);
+        shard(fd)
+            .entry(fd)
+            .or_default()
+            .push((node.clone(), self.waker.clone()));
+    }
+
+    /// 删除协程注册的所有事件
+    pub fn remove_event(&mut self, id: CoroutineId) {
+        if let Some(fds) = self.registered.remove(&id) {
+            for fd in fds {
+                if let Some(waiters) = self.waiters.get_mut(&fd) {
+                    waiters.retain(|(waiter, _)| waiter.id != id);
+                    if waiters.is_empty() {
+                        self.waiters.remove(&fd);
+                    }
+                }
+                //fd被关闭时已经从索引里删除，fd可能已被复用，只删除自己的
+                let mut shard = shard(fd);
+                if let Some(waiters) = shard.get_mut(&fd) {
+                    waiters.retain(|(waiter, _)| waiter.id != id);
+                    if waiters.is_empty() {
+                        shard.remove(&fd);
+                    }
+                }
+            }
+        }
+    }
+
+    /// fd被关闭，删除fd上的所有事件，返回等待该fd的协程id，
+    /// 这些协程注册的所有事件都会被删除
+    pub fn remove_fd(&mut self, fd: libc::c_int) -> Vec<CoroutineId> {
+        let ids: Vec<CoroutineId> = self
+            .waiters
+            .remove(&fd)
+            .map(|waiters| waiters.into_iter().map(|(node, _)| node.id).collect())
+            .unwrap_or_default();
+        for id in &ids {
+            self.remove_event(*id);
+        }
+        ids
+    }
+
+    /// 等待事件，timeout为None时一直等到有事件为止，返回就绪的协程的唤醒节点，
+    /// 包括被Waker唤醒的协程，就绪的协程注册的所有事件都会被删除，
+    /// 等待期间线程收到信号时返回Interrupted
+    pub fn wait(&mut self, timeout: Option<Duration>) -> std::io::Result<Vec<Arc<WakeNode>>> {
+        let mut fds: Vec<libc::pollfd> = self
+            .waiters
+            .iter()
+            .map(|(fd, waiters)| libc::pollfd {
+                fd: *fd,
+                events: waiters.iter().fold(0, |events, (_, e)| events | *e),
+                revents: 0,
+            })
+            .collect();
+        fds.push(libc::pollfd {
+            fd: self.waker.fds[0],
+            events: libc::POLLIN,
+            revents: 0,
+        });
+        //已经有别的线程唤醒的协程，不需要等待
+        let timeout = if self.waker.has_woken() {
+            Some(Duration::ZERO)
+        } else {
+            timeout
+        };
+        let timeout = match timeout {
+            //向上取整，避免提前醒来空转
+            Some(timeout) => timeout
+                .as_nanos()
+                .div_ceil(1_000_000)
+                .min(libc::c_int::MAX as u128) as libc::c_int,
+            None => -1,
+        };
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) } < 0 {
+            return Err(Error::last_os_error());
+        }
+        let mut ready = self.waker.take_woken();
+        for pollfd in fds.iter().filter(|pollfd| pollfd.revents != 0) {
+            if let Some(waiters) = self.waiters.get(&pollfd.fd) {
+                for (node, events) in waiters {
+                    //出错时唤醒所有等待者
+                    let interest = *events | libc::POLLERR | libc::POLLHUP | libc::POLLNVAL;
+                    if pollfd.revents & interest != 0 && !ready.iter().any(|n| n.id == node.id) {
+                        ready.push(node.clone());
+                    }
+                }
+            }
+        }
+        for node in &ready {
+            self.remove_event(node.id);
+        }
+        Ok(ready)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reactor::{Reactor, WakeNode};
+    use id_generator::CoroutineId;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn node(id: u64) -> Arc<WakeNode> {
+        Arc::new(WakeNode::new(CoroutineId::from_u64(id)))
+    }
+
+    fn ids(nodes: Vec<Arc<WakeNode>>) -> Vec<u64> {
+        nodes.iter().map(|node| node.id().as_u64()).collect()
+    }
+
+    #[test]
+    fn test() {
+        let mut fds = [0; 2];
+        assert_eq!(0, unsafe { libc::pipe(fds.as_mut_ptr()) });
+        let mut reactor = Reactor::new();
+        reactor.add_event(fds[0], libc::POLLIN, &node(1));
+        reactor.add_event(fds[1], libc::POLLOUT, &node(2));
+        assert_eq!(2, reactor.len());
+        assert_eq!(
+            vec![2],
+            ids(reactor.wait(Some(Duration::from_millis(10))).unwrap())
+        );
+        assert_eq!(1, reactor.len());
+        assert!(reactor
+            .wait(Some(Duration::from_millis(10)))
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(1, unsafe { libc::write(fds[1], [1u8].as_ptr() as _, 1) });
+        assert_eq!(vec![1], ids(reactor.wait(None).unwrap()));
+        assert!(reactor.is_empty());
+
+        let three = node(3);
+        reactor.add_event(fds[0], libc::POLLIN, &three);
+        reactor.add_event(fds[1], libc::POLLOUT, &three);
+        reactor.add_event(fds[0], libc::POLLIN, &node(4));
+        assert_eq!(
+            vec![CoroutineId::from_u64(3), CoroutineId::from_u64(4)],
+            reactor.remove_fd(fds[0])
+        );
+        assert!(reactor.is_empty());
+
+        //别的线程唤醒
+        let waker = reactor.waker();
+        let handle = std::thread::spawn(move || waker.wake(&node(5)));
+        assert_eq!(vec![5], ids(reactor.wait(None).unwrap()));
+        handle.join().unwrap();
+        assert!(!reactor.has_woken());
+        unsafe {
+            libc::close(fds[0]);
+            libc::close(fds[1]);
+        }
+    }
+
+    #[test]
+    fn wake_twice() {
+        let mut reactor = Reactor::new();
+        let waker = reactor.waker();
+        let node = node(1);
+        //还没有被取走的唤醒会被合并
+        waker.wake(&node);
+        waker.wake(&node);
+        assert_eq!(2, Arc::strong_count(&node));
+        assert_eq!(vec![1], ids(reactor.wait(None).unwrap()));
+        assert_eq!(1, Arc::strong_count(&node));
+        //取走之后节点可以再次唤醒
+        waker.wake(&node);
+        assert_eq!(vec![1], ids(reactor.wait(None).unwrap()));
+        //Waker释放时归还队列里的引用
+        waker.wake(&node);
+        drop(waker);
+        drop(reactor);
+        assert_eq!(1, Arc::strong_count(&node));
+    }
+
+    #[test]
+    fn waker_threads() {
+        let mut reactor = Reactor::new();
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let waker = reactor.waker();
+                std::thread::spawn(move || {
+                    for id in t * 100..(t + 1) * 100 {
+                        waker.wake(&node(id));
+                    }
+                })
+            })
+            .collect();
+        let mut woken = Vec::new();
+        while woken.len() < 400 {
+            woken.extend(ids(reactor.wait(None).unwrap()));
+        }
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        woken.sort();
+        assert!((0..400).eq(woken));
+        assert!(!reactor.has_woken());
+    }
+}
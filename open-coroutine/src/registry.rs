@@ -0,0 +1,240 @@
+use crate::coroutine::{Coroutine, Status, UserFunction};
+use crate::reactor::{WakeNode, Waker};
+use crate::scheduler::Scheduler;
+use crate::sync::CancelToken;
+use id_generator::{CoroutineId, SchedulerId};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+
+/*
+全局的协程注册表，协程提交给调度器时注册，退出时注销；
+按协程id分片加读写锁，不同分片上的注册和查询互不影响，
+可以在任意线程查询协程的状态、唤醒或者取消协程
+ */
+
+const SHARDS: usize = 64;
+
+type Shard = RwLock<HashMap<CoroutineId, Arc<CoroutineHandle>>>;
+
+static REGISTRY: Lazy<Box<[Shard]>> =
+    Lazy::new(|| (0..SHARDS).map(|_| RwLock::default()).collect());
+
+fn shard(id: CoroutineId) -> &'static Shard {
+    &REGISTRY[(id.sequence() % SHARDS as u64) as usize]
+}
+
+//按Status的声明顺序排列，用于从原子变量还原
+const STATUSES: [Status; 8] = [
+    Status::Created,
+    Status::Ready,
+    Status::Running,
+    Status::Suspend,
+    Status::SystemCall,
+    Status::CopyStack,
+    Status::Finished,
+    Status::Exited,
+];
+
+/// 注册表里的协程，协程退出后仍然可以读取退出前的信息
+pub struct CoroutineHandle {
+    id: CoroutineId,
+    name: Option<String>,
+    //创建时间，单位纳秒
+    created: u64,
+    //提交到的调度器
+    scheduler: SchedulerId,
+    status: AtomicU8,
+    //调度器的Waker和协程的唤醒节点，可以在任意线程唤醒协程
+    waker: Arc<Waker>,
+    node: Arc<WakeNode>,
+    token: CancelToken,
+}
+
+impl CoroutineHandle {
+    pub fn id(&self) -> CoroutineId {
+        self.id
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn created(&self) -> u64 {
+        self.created
+    }
+
+    pub fn scheduler(&self) -> SchedulerId {
+        self.scheduler
+    }
+
+    pub fn status(&self) -> Status {
+        STATUSES[self.status.load(Ordering::Acquire) as usize]
+    }
+
+    pub(crate) fn set_status(&self, status: Status) {
+        self.status.store(status as u8, Ordering::Release);
+    }
+
+    /// 协程通过令牌检查自己是否被取消，也可以作为select的一个分支
+    pub fn cancel_token(&self) -> &CancelToken {
+        &self.token
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// 唤醒在Scheduler::wait上挂起的协程，协程不在等待时没有影响
+    pub fn wake(&self) {
+        self.waker.wake(&self.node);
+    }
+
+    /// 请求取消并唤醒协程，取消是协作式的，由协程自己决定何时退出，
+    /// 返回是否由本次调用取消
+    pub fn cancel(&self) -> bool {
+        let cancelled = self.token.cancel();
+        if cancelled {
+            self.wake();
+        }
+        cancelled
+    }
+}
+
+impl Debug for CoroutineHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CoroutineHandle")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("created", &self.created)
+            .field("scheduler", &self.scheduler)
+            .field("status", &self.status())
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// 把协程注册到scheduler下，已经注册过的协程不会重复注册
+pub(crate) fn register(coroutine: &mut Coroutine<UserFunction>, scheduler: &Scheduler) {
+    if coroutine.get_handle().is_some() {
+        return;
+    }
+    let handle = Arc::new(CoroutineHandle {
+        id: coroutine.get_id(),
+        name: coroutine.get_name().map(str::to_string),
+        created: coroutine.get_created(),
+        scheduler: scheduler.get_id(),
+        status: AtomicU8::new(coroutine.get_status() as u8),
+        waker: scheduler.reactor_waker(),
+        node: coroutine.wake_node().clone(),
+        token: CancelToken::new(),
+    });
+    shard(handle.id)
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(handle.id, handle.clone());
+    coroutine.set_handle(Some(handle));
+}
+
+pub(crate) fn unregister(id: CoroutineId) {
+    shard(id)
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&id);
+}
+
+/// 当前正在运行的协程，不在协程中或者协程没有提交给调度器时返回None
+pub fn current() -> Option<Arc<CoroutineHandle>> {
+    Coroutine::<UserFunction>::current()?.get_handle()
+}
+
+pub fn get(id: CoroutineId) -> Option<Arc<CoroutineHandle>> {
+    shard(id)
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&id)
+        .cloned()
+}
+
+/// 所有已注册的协程，按id排序
+pub fn list_all() -> Vec<Arc<CoroutineHandle>> {
+    let mut handles: Vec<Arc<CoroutineHandle>> = REGISTRY
+        .iter()
+        .flat_map(|shard| {
+            shard
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .values()
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    handles.sort_by_key(|handle| handle.id);
+    handles
+}
+
+/// 唤醒协程，协程不存在时返回false
+pub fn wake(id: CoroutineId) -> bool {
+    get(id).map(|handle| handle.wake()).is_some()
+}
+
+/// 取消协程，协程不存在或者已经被取消时返回false
+pub fn cancel(id: CoroutineId) -> bool {
+    get(id).is_some_and(|handle| handle.cancel())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coroutine::{Coroutine, Status};
+    use crate::registry;
+    use crate::scheduler::Scheduler;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        let mut scheduler = Scheduler::new();
+        let mut coroutine = Coroutine::new(
+            16 * 1024,
+            |param| {
+                let handle = registry::current().unwrap();
+                assert_eq!(Some("worker"), handle.name());
+                assert_eq!(Status::Running, handle.status());
+                //等待被取消
+                while !handle.is_cancelled() {
+                    Scheduler::wait(Some(Duration::from_secs(10)));
+                }
+                param
+            },
+            None,
+        );
+        coroutine.set_name("worker");
+        let id = coroutine.get_id();
+        assert!(registry::get(id).is_none());
+        scheduler.execute(coroutine);
+        let handle = registry::get(id).unwrap();
+        assert_eq!(scheduler.get_id(), handle.scheduler());
+        assert_eq!(Status::Ready, handle.status());
+        assert!(registry::list_all().iter().any(|handle| handle.id() == id));
+
+        assert!(scheduler.try_schedule().is_empty());
+        assert_eq!(Status::SystemCall, handle.status());
+        assert!(registry::wake(id));
+        assert!(scheduler.try_schedule().is_empty());
+        assert!(registry::cancel(id));
+        assert!(!registry::cancel(id));
+        assert_eq!(1, scheduler.try_schedule().len());
+        assert_eq!(Status::Exited, handle.status());
+        assert!(registry::get(id).is_none());
+        assert!(!registry::wake(id));
+        assert!(registry::current().is_none());
+    }
+
+    #[test]
+    fn not_submitted() {
+        let coroutine = Coroutine::new(2048, |param| param, None);
+        assert_eq!(Status::Finished, coroutine.resume().get_status());
+        assert!(registry::get(coroutine.get_id()).is_none());
+    }
+}
@@ -0,0 +1,156 @@
+use crate::coroutine::{Coroutine, UserFunction};
+use std::panic;
+use std::sync::Once;
+
+/*
+协程panic和栈溢出时输出协程的名字和id：
+panic时先于默认的panic hook输出，默认的hook只能输出线程名；
+栈溢出时访问到协程栈底的保护页触发SIGSEGV(macOS上是SIGBUS)，在信号处理函数里输出后abort，
+不是协程栈溢出的信号交给之前的处理函数。
+信号处理函数运行在线程的备用信号栈上，只有std创建的线程才有
+ */
+
+static INSTALL: Once = Once::new();
+
+/// 安装panic hook和信号处理函数，只会安装一次
+pub(crate) fn install() {
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Some(coroutine) = Coroutine::<UserFunction>::current() {
+                eprintln!("coroutine {} panicked", coroutine);
+            }
+            previous(info);
+        }));
+        #[cfg(unix)]
+        unsafe {
+            overflow::install()
+        };
+    });
+}
+
+#[cfg(unix)]
+mod overflow {
+    use crate::coroutine::{Coroutine, UserFunction};
+    use std::io::Write;
+    use std::os::raw::c_void;
+    use std::ptr;
+
+    //之前的处理函数，只在install时写入
+    static mut PREVIOUS: [libc::sigaction; 2] = unsafe { std::mem::zeroed() };
+
+    const SIGNALS: [libc::c_int; 2] = [libc::SIGSEGV, libc::SIGBUS];
+
+    pub(super) unsafe fn install() {
+        for (i, signum) in SIGNALS.into_iter().enumerate() {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler as *const () as usize;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(
+                signum,
+                &action,
+                ptr::addr_of_mut!(PREVIOUS).cast::<libc::sigaction>().add(i),
+            );
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe fn fault_address(info: *mut libc::siginfo_t) -> *const c_void {
+        (*info).si_addr()
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    unsafe fn fault_address(info: *mut libc::siginfo_t) -> *const c_void {
+        (*info).si_addr
+    }
+
+    unsafe extern "C" fn handler(signum: libc::c_int, info: *mut libc::siginfo_t, _: *mut c_void) {
+        if let Some(coroutine) = Coroutine::<UserFunction>::overflowed(fault_address(info)) {
+            //不能分配内存，写到栈上的缓冲区里
+            let mut buffer = [0u8; 512];
+            let mut cursor = &mut buffer[..];
+            _ = writeln!(cursor, "\ncoroutine {} has overflowed its stack", coroutine);
+            let len = 512 - cursor.len();
+            libc::write(libc::STDERR_FILENO, buffer.as_ptr().cast(), len);
+            libc::abort();
+        }
+        //恢复之前的处理函数，返回后重新执行出错的指令，由之前的处理函数处理
+        let i = SIGNALS.iter().position(|s| *s == signum).unwrap_or(0);
+        libc::sigaction(
+            signum,
+            ptr::addr_of!(PREVIOUS).cast::<libc::sigaction>().add(i),
+            ptr::null_mut(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coroutine::Coroutine;
+    use crate::scheduler::Scheduler;
+    use std::hint::black_box;
+    use std::process::Command;
+
+    const CHILD: &str = "OPEN_COROUTINE_REPORT_CHILD";
+
+    fn recurse(depth: usize) -> usize {
+        let buffer = black_box([depth as u8; 1024]);
+        match depth {
+            usize::MAX => 0,
+            _ => recurse(depth + 1) + buffer[0] as usize,
+        }
+    }
+
+    //在子进程里执行这个测试本身，返回子进程的stderr
+    fn run_child(test: &str, kind: &str) -> (bool, String) {
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args([test, "--exact", "--nocapture", "--test-threads=1"])
+            .env(CHILD, kind)
+            .output()
+            .unwrap();
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    }
+
+    #[test]
+    fn report() {
+        match std::env::var(CHILD).as_deref() {
+            Ok("panic") => {
+                let mut scheduler = Scheduler::new();
+                let handle = Coroutine::builder()
+                    .name("conn-42")
+                    .scheduler(&mut scheduler)
+                    .spawn(|| panic!("oops"))
+                    .unwrap();
+                scheduler.schedule();
+                assert!(handle.join().is_err());
+            }
+            Ok("overflow") => {
+                let mut scheduler = Scheduler::new();
+                _ = Coroutine::builder()
+                    .name("deep")
+                    .stack_size(16 * 1024)
+                    .scheduler(&mut scheduler)
+                    .spawn(|| recurse(0))
+                    .unwrap();
+                scheduler.schedule();
+            }
+            _ => {
+                let (success, stderr) = run_child("report::tests::report", "panic");
+                assert!(success, "{}", stderr);
+                assert!(stderr.contains("coroutine 'conn-42' ("), "{}", stderr);
+                let (success, stderr) = run_child("report::tests::report", "overflow");
+                assert!(!success);
+                assert!(
+                    stderr.contains("coroutine 'deep' (")
+                        && stderr.contains("has overflowed its stack"),
+                    "{}",
+                    stderr
+                );
+            }
+        }
+    }
+}
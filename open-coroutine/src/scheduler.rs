@@ -1,10 +1,18 @@
-use crate::coroutine::{Coroutine, Status};
-use id_generator::IdGenerator;
-use object_list::ObjectList;
+use crate::coroutine::{Coroutine, CoroutineList, Priority, Status, UserFunction};
+use crate::future::JoinHandle;
+use crate::queue::{ReadyQueue, Runnable};
+use crate::reactor::{Reactor, WakeNode, Waker};
+use crate::task::Task;
+use id_generator::{CoroutineId, SchedulerId};
 use once_cell::sync::Lazy;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{Error, ErrorKind};
 use std::mem::ManuallyDrop;
 use std::os::raw::c_void;
 use std::ptr;
+use std::sync::Arc;
 use std::time::Duration;
 use timer::TimerList;
 
@@ -12,24 +20,45 @@ static mut GLOBAL: Lazy<ManuallyDrop<Scheduler>> =
     Lazy::new(|| ManuallyDrop::new(Scheduler::new()));
 
 thread_local! {
-    static SCHEDULER: Box<Scheduler> = Box::new(Scheduler::new());
+    static SCHEDULER: Box<Scheduler> = {
+        CREATED.with(|created| created.set(true));
+        Box::new(Scheduler::new())
+    };
+    //当前线程是否已经创建了调度器
+    static CREATED: Cell<bool> = const { Cell::new(false) };
+    //当前线程处理过的信号数量，const初始化且没有析构，信号处理函数中访问是安全的
+    static SIGNALS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn signals() -> usize {
+    SIGNALS.try_with(Cell::get).unwrap_or(0)
 }
 
 #[repr(C)]
 #[derive(Debug)]
 pub struct Scheduler {
-    id: usize,
-    ready: ObjectList,
-    //正在执行的协程id
-    running: Option<usize>,
-    suspend: TimerList,
-    //not support for now
-    #[allow(unused)]
-    system_call: ObjectList,
+    id: SchedulerId,
+    //协程和无栈任务共用，通过它们内嵌的链接排队，保持两者的先后顺序，不分配内存
+    ready: ReadyQueue,
+    //低优先级的就绪协程，就绪队列为空时才执行
+    idle: CoroutineList,
+    //正在执行的协程或者任务id
+    running: Option<CoroutineId>,
+    //有执行时间的挂起协程，按执行时间排序，等待到时或者被reactor唤醒
+    suspend: CoroutineList,
+    //没有执行时间的挂起协程，只能被reactor唤醒
+    waiting: CoroutineList,
+    //任务注册的定时器，一个任务可能注册多个，所以按id保存
+    timers: TimerList<CoroutineId>,
+    //返回Pending的任务，等待被Waker、定时器或者reactor唤醒
+    tasks: HashMap<CoroutineId, Box<Task>>,
+    reactor: Reactor,
+    //正在sleep的协程id -> 开始sleep时线程处理过的信号数量和协程的唤醒节点，
+    //之后线程处理了信号就提前唤醒
+    sleeping: HashMap<CoroutineId, (usize, Arc<WakeNode>)>,
     //not support for now
     #[allow(unused)]
-    copy_stack: ObjectList,
-    finished: ObjectList,
+    copy_stack: CoroutineList,
 }
 
 impl PartialEq for Scheduler {
@@ -38,65 +67,106 @@ impl PartialEq for Scheduler {
     }
 }
 
+//调度器id是可回收的
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        //挂起的协程随调度器释放，之后的唤醒找不到它们
+        for coroutine in self.suspend.iter().chain(self.waiting.iter()) {
+            coroutine.wake_node().take_parked();
+        }
+        self.id.release();
+    }
+}
+
 unsafe impl Send for Scheduler {}
 
 unsafe impl Sync for Scheduler {}
 
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Scheduler {
-    fn new() -> Self {
+    /// 创建独立的调度器，通常使用global或者current
+    pub fn new() -> Self {
         //构造
         Scheduler {
-            id: IdGenerator::next_id("scheduler"),
-            ready: ObjectList::new(),
+            id: SchedulerId::next(),
+            ready: ReadyQueue::new(),
+            idle: CoroutineList::new(),
             running: None,
-            suspend: TimerList::new(),
-            system_call: ObjectList::new(),
-            copy_stack: ObjectList::new(),
-            finished: ObjectList::new(),
+            suspend: CoroutineList::new(),
+            waiting: CoroutineList::new(),
+            timers: TimerList::new(),
+            tasks: HashMap::new(),
+            reactor: Reactor::new(),
+            sleeping: HashMap::new(),
+            copy_stack: CoroutineList::new(),
         }
     }
 
+    pub fn get_id(&self) -> SchedulerId {
+        self.id
+    }
+
+    pub(crate) fn reactor_waker(&self) -> Arc<Waker> {
+        self.reactor.waker()
+    }
+
     pub fn global() -> &'static mut ManuallyDrop<Scheduler> {
-        unsafe { &mut GLOBAL }
+        unsafe { &mut *ptr::addr_of_mut!(GLOBAL) }
     }
 
     pub fn current<'a>() -> &'a mut Scheduler {
         SCHEDULER.with(|boxed| Box::leak(unsafe { ptr::read_unaligned(boxed) }))
     }
 
-    pub fn submit(
-        &mut self,
-        mut coroutine: Coroutine<dyn FnOnce(Option<*mut c_void>) -> Option<*mut c_void>>,
-    ) {
+    /// 当前线程已经创建的调度器，不会创建新的调度器
+    pub fn try_current<'a>() -> Option<&'a mut Scheduler> {
+        if CREATED.try_with(Cell::get).unwrap_or(false) {
+            return SCHEDULER
+                .try_with(|boxed| Box::leak(unsafe { ptr::read_unaligned(boxed) }))
+                .ok();
+        }
+        None
+    }
+
+    pub fn submit(&mut self, mut coroutine: Coroutine<UserFunction>) {
         let time = coroutine.get_execute_time();
         coroutine.set_scheduler(self);
+        crate::registry::register(&mut coroutine, self);
         if timer::now() < time {
-            coroutine.set_execute_time(time).set_status(Status::Suspend);
-            self.suspend.insert(time, coroutine);
+            coroutine.set_status(Status::Suspend);
+            self.park(coroutine);
             return;
         }
         coroutine.set_status(Status::Ready);
-        self.ready.push_back(coroutine);
+        self.push_coroutine(coroutine, false);
     }
 
+    /// 调度器可能在调用者返回后才执行协程，所以闭包不能借用局部变量：
+    /// ```compile_fail
+    /// use open_coroutine::coroutine::Coroutine;
+    /// use open_coroutine::scheduler::Scheduler;
+    /// let x = 1;
+    /// Scheduler::current().execute(Coroutine::new(2048, |param| {
+    ///     println!("{}", x);
+    ///     param
+    /// }, None));
+    /// ```
     pub fn execute(
         &mut self,
-        mut coroutine: Coroutine<impl FnOnce(Option<*mut c_void>) -> Option<*mut c_void>>,
+        coroutine: Coroutine<impl FnOnce(Option<*mut c_void>) -> Option<*mut c_void> + 'static>,
     ) {
-        let time = coroutine.get_execute_time();
-        if timer::now() < time {
-            self.execute_at(time, coroutine);
-            return;
-        }
-        coroutine.set_status(Status::Ready);
-        coroutine.set_scheduler(self);
-        self.ready.push_back(coroutine);
+        self.submit(coroutine.into_dyn())
     }
 
     pub fn delay(
         &mut self,
         delay: Duration,
-        coroutine: Coroutine<impl FnOnce(Option<*mut c_void>) -> Option<*mut c_void>>,
+        coroutine: Coroutine<impl FnOnce(Option<*mut c_void>) -> Option<*mut c_void> + 'static>,
     ) {
         let time = timer::get_timeout_time(delay);
         self.execute_at(time, coroutine)
@@ -105,150 +175,453 @@ impl Scheduler {
     pub fn execute_at(
         &mut self,
         time: u64,
-        mut coroutine: Coroutine<impl FnOnce(Option<*mut c_void>) -> Option<*mut c_void>>,
+        mut coroutine: Coroutine<impl FnOnce(Option<*mut c_void>) -> Option<*mut c_void> + 'static>,
     ) {
-        coroutine.set_execute_time(time).set_status(Status::Suspend);
-        coroutine.set_scheduler(self);
-        self.suspend.insert(time, coroutine);
+        coroutine.set_execute_time(time);
+        self.submit(coroutine.into_dyn())
+    }
+
+    /// 创建协程执行f，通过返回的JoinHandle等待f的返回值，
+    /// JoinHandle实现了Future，也可以在async代码里.await
+    pub fn spawn<F, T>(&mut self, size: usize, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + 'static,
+        T: 'static,
+    {
+        Coroutine::builder()
+            .stack_size(size)
+            .scheduler(self)
+            .spawn(f)
+            .expect("allocate stack failed !")
+    }
+
+    /// 在调度器上直接执行future，不创建协程，也不需要分配栈，
+    /// future在poll时不能阻塞，需要等待时使用crate::future里的sleep和event，
+    /// 通过返回的JoinHandle等待future的结果
+    pub fn spawn_future<F>(&mut self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        crate::future::spawn_future(self, future)
+    }
+
+    /// 提交无栈任务，返回任务id
+    pub(crate) fn submit_future(
+        &mut self,
+        future: impl Future<Output = ()> + 'static,
+    ) -> CoroutineId {
+        let task = Task::new(self.id, future, self.reactor.waker());
+        let id = task.get_id();
+        self.push_ready(Runnable::Task(task), false);
+        id
+    }
+
+    /// 任务id在time时被唤醒
+    pub(crate) fn add_timer(&mut self, time: u64, id: CoroutineId) {
+        self.timers.insert(time, id);
+    }
+
+    /// 唤醒节点对应的任务关注fd上的events事件
+    pub(crate) fn add_event(
+        &mut self,
+        fd: libc::c_int,
+        events: libc::c_short,
+        node: &Arc<WakeNode>,
+    ) {
+        self.reactor.add_event(fd, events, node);
+    }
+
+    /// 挂起当前协程，直到events中任意一个fd就绪或者超时，events同poll，
+    /// timeout为None时一直等待，不在协程中时返回false，
+    /// 等待期间fd被关闭时返回EBADF
+    pub fn wait_event(
+        events: &[(libc::c_int, libc::c_short)],
+        timeout: Option<Duration>,
+    ) -> std::io::Result<bool> {
+        let mut coroutine = match Coroutine::<UserFunction>::current() {
+            Some(coroutine) => coroutine,
+            None => return Ok(false),
+        };
+        let scheduler = match coroutine.get_scheduler() {
+            Some(scheduler) => scheduler,
+            None => return Ok(false),
+        };
+        let id = coroutine.get_id();
+        unsafe {
+            for (fd, events) in events {
+                (*scheduler)
+                    .reactor
+                    .add_event(*fd, *events, coroutine.wake_node());
+            }
+        }
+        let time = timeout.map_or(u64::MAX, timer::get_timeout_time);
+        coroutine
+            .set_execute_time(time)
+            .set_status(Status::SystemCall);
+        Coroutine::suspend();
+        //超时唤醒时，清理未触发的事件
+        unsafe { (*scheduler).reactor.remove_event(id) };
+        if crate::reactor::take_closed(id) {
+            return Err(Error::from_raw_os_error(libc::EBADF));
+        }
+        Ok(true)
+    }
+
+    /// 当前协程的Waker和唤醒节点，别的线程可以通过它们唤醒当前协程，
+    /// 协程会回到原来的调度器上执行，不在协程中时返回None
+    pub fn waker() -> Option<(Arc<Waker>, Arc<WakeNode>)> {
+        let coroutine = Coroutine::<UserFunction>::current()?;
+        let scheduler = coroutine.get_scheduler()?;
+        Some((
+            unsafe { (*scheduler).reactor.waker() },
+            coroutine.wake_node().clone(),
+        ))
+    }
+
+    /// 挂起当前协程，直到被Waker唤醒或者超时，timeout为None时一直等待，
+    /// 可能被提前唤醒，调用者需要自己检查条件，不在协程中时返回false
+    pub fn wait(timeout: Option<Duration>) -> bool {
+        //没有注册事件，不会因为fd关闭而出错
+        Scheduler::wait_event(&[], timeout).unwrap_or(true)
+    }
+
+    /// 让出当前协程，排到就绪队列末尾，不在协程中时返回false
+    pub fn yield_now() -> bool {
+        let mut coroutine = match Coroutine::<UserFunction>::current() {
+            Some(coroutine) => coroutine,
+            None => return false,
+        };
+        if coroutine.get_scheduler().is_none() {
+            return false;
+        }
+        coroutine.set_status(Status::Ready);
+        Coroutine::suspend();
+        true
     }
 
-    pub fn try_timed_schedule(&mut self, timeout: Duration) -> ObjectList {
+    /// 挂起当前协程timeout时间，等待期间线程收到信号时提前唤醒并返回EINTR，
+    /// 不在协程中时返回false
+    pub fn sleep(timeout: Duration) -> std::io::Result<bool> {
+        let mut coroutine = match Coroutine::<UserFunction>::current() {
+            Some(coroutine) => coroutine,
+            None => return Ok(false),
+        };
+        let scheduler = match coroutine.get_scheduler() {
+            Some(scheduler) => scheduler,
+            None => return Ok(false),
+        };
+        let id = coroutine.get_id();
+        let started = signals();
         let timeout_time = timer::get_timeout_time(timeout);
-        let mut scheduled = ObjectList::new();
-        while !self.suspend.is_empty() || !self.ready.is_empty() {
-            if timeout_time <= timer::now() {
+        //被其他原因提前唤醒时继续sleep，直到时间到了或者被信号打断
+        while timer::now() < timeout_time {
+            let node = coroutine.wake_node().clone();
+            unsafe { (*scheduler).sleeping.insert(id, (started, node)) };
+            coroutine
+                .set_execute_time(timeout_time)
+                .set_status(Status::SystemCall);
+            Coroutine::suspend();
+            unsafe { (*scheduler).sleeping.remove(&id) };
+            //不管是被提前唤醒还是到时，sleep期间线程处理过信号都算被打断
+            if signals() != started {
+                return Err(Error::from_raw_os_error(libc::EINTR));
+            }
+        }
+        Ok(true)
+    }
+
+    /// 记录当前线程处理了一个信号，正在sleep的协程会被提前唤醒并返回EINTR，
+    /// 只访问线程局部的计数器，可以在信号处理函数中调用
+    pub fn signaled() {
+        _ = SIGNALS.try_with(|signals| signals.set(signals.get().wrapping_add(1)));
+    }
+
+    /// fd即将被关闭，唤醒所有调度器上等待该fd的协程，它们的wait_event返回EBADF
+    pub fn close_event(fd: libc::c_int) {
+        crate::reactor::wake_fd(fd, true)
+    }
+
+    /// fd被shutdown，唤醒所有调度器上等待该fd的协程，让它们重新检查fd的状态
+    pub fn shutdown_event(fd: libc::c_int) {
+        crate::reactor::wake_fd(fd, false)
+    }
+
+    pub fn try_timed_schedule(&mut self, timeout: Duration) -> CoroutineList {
+        let timeout_time = timer::get_timeout_time(timeout);
+        let mut scheduled = CoroutineList::new();
+        while !self.is_empty() {
+            let now = timer::now();
+            if timeout_time <= now {
                 break;
             }
-            let mut temp = self.try_schedule();
-            while !temp.is_empty() {
-                scheduled.push_back_raw(temp.pop_front_raw().unwrap());
+            scheduled.append(&mut self.try_schedule());
+            //执行协程期间线程处理过的信号，在阻塞等待之前唤醒被打断的sleep
+            self.check_signals();
+            if !self.has_ready() && !self.is_empty() {
+                //没有可执行的协程，等待事件或者最近的定时器
+                let time = self.next_time().min(timeout_time);
+                self.check_events(Some(Duration::from_nanos(
+                    time.saturating_sub(timer::now()),
+                )));
             }
         }
         scheduled
     }
 
-    pub fn try_schedule(&mut self) -> ObjectList {
+    pub fn try_schedule(&mut self) -> CoroutineList {
         self.check_ready();
-        self.mark_entrance();
+        self.check_signals();
+        if !self.reactor.is_empty() || self.reactor.has_woken() {
+            self.check_events(Some(Duration::ZERO));
+        }
         self.do_schedule()
     }
 
-    fn mark_entrance(&mut self) {
-        if let Some(front_pointer) = self.ready.front_mut_raw() {
-            let front = unsafe {
-                ptr::read_unaligned(
-                    front_pointer
-                        as *mut Coroutine<dyn FnOnce(Option<*mut c_void>) -> Option<*mut c_void>>,
-                )
-            };
-            if let Some(back_pointer) = self.ready.back_mut_raw() {
-                if front_pointer != back_pointer {
-                    let mut back = unsafe {
-                        ptr::read_unaligned(
-                            back_pointer
-                                as *mut Coroutine<
-                                    dyn FnOnce(Option<*mut c_void>) -> Option<*mut c_void>,
-                                >,
-                        )
-                    };
-                    back.set_entrance(&front);
-                    std::mem::forget(back);
-                }
+    fn do_schedule(&mut self) -> CoroutineList {
+        let mut scheduled = CoroutineList::new();
+        //没有其他可执行的协程时，执行一个低优先级的协程
+        if self.ready.is_empty() {
+            if let Some(coroutine) = self.idle.pop_front() {
+                self.push_ready(Runnable::Coroutine(coroutine), false);
             }
-            std::mem::forget(front);
         }
-    }
-
-    fn do_schedule(&mut self) -> ObjectList {
-        let mut scheduled = ObjectList::new();
         for _ in 0..self.ready.len() {
-            if let Some(pointer) = self.ready.pop_front_raw() {
-                let mut coroutine = unsafe {
-                    ptr::read_unaligned(
-                        pointer
-                            as *mut Coroutine<
-                                dyn FnOnce(Option<*mut c_void>) -> Option<*mut c_void>,
-                            >,
-                    )
-                };
-                //fixme 这里拿到的时间不对
-                let exec_time = coroutine.get_execute_time();
-                //过滤未到执行时间的协程
-                if timer::now() < exec_time {
-                    //设置协程状态
-                    coroutine.set_status(Status::Suspend);
-                    //移动至"挂起"队列
-                    self.suspend.insert(exec_time, coroutine);
-                    continue;
+            match self.ready.pop_front() {
+                Some(Runnable::Coroutine(mut coroutine)) => {
+                    let exec_time = coroutine.get_execute_time();
+                    //过滤未到执行时间的协程
+                    if timer::now() < exec_time {
+                        //设置协程状态
+                        coroutine.set_status(Status::Suspend);
+                        //移动至"挂起"队列
+                        self.park(coroutine);
+                        continue;
+                    }
+                    self.running = Some(coroutine.get_id());
+                    coroutine.resume();
+                    self.running = None;
+                    match coroutine.get_status() {
+                        Status::Finished => {
+                            coroutine.exit();
+                            //调度器不再持有已完成的协程，调用者释放返回的句柄后回收
+                            scheduled.push_back(coroutine);
+                        }
+                        Status::Suspend | Status::SystemCall => self.park(coroutine),
+                        _ => {
+                            //主动让出，重新排到末尾，高优先级的协程也不例外，避免饿死其他协程
+                            coroutine.set_status(Status::Ready);
+                            match coroutine.get_priority() {
+                                Priority::Low => self.push_idle(coroutine),
+                                _ => self.push_ready(Runnable::Coroutine(coroutine), false),
+                            }
+                        }
+                    }
+                }
+                Some(Runnable::Task(mut task)) => {
+                    let id = task.get_id();
+                    //清理上次poll时注册、但没有触发的事件，任务自己重新检查fd，不需要关闭标记
+                    self.reactor.remove_event(id);
+                    crate::reactor::take_closed(id);
+                    self.running = Some(id);
+                    let finished = task.poll(self);
+                    self.running = None;
+                    if !finished {
+                        self.tasks.insert(id, task);
+                    }
                 }
-                self.running = Some(coroutine.get_id());
-                let result = coroutine.resume();
-                self.running = None;
-                //移动至"已完成"队列
-                scheduled.push_back(unsafe { ptr::read_unaligned(&result) });
-                self.finished.push_back(result);
-                coroutine.exit();
-                std::mem::forget(coroutine);
+                None => {}
             }
         }
         scheduled
     }
 
+    //在唤醒节点上记录协程的地址，被唤醒时不需要按id查找
+    fn park(&mut self, coroutine: Coroutine<UserFunction>) {
+        coroutine.wake_node().set_parked(coroutine.as_ptr());
+        if coroutine.get_execute_time() != u64::MAX {
+            self.suspend.insert(coroutine);
+        } else {
+            self.waiting.push_back(coroutine);
+        }
+    }
+
+    //唤醒挂起在这个调度器上的协程，节点对应的不是挂起的协程时返回None
+    fn wake(&mut self, node: &WakeNode) -> Option<Coroutine<UserFunction>> {
+        let parked = Coroutine::<UserFunction>::from_raw(node.get_parked()?);
+        if parked.get_scheduler() != Some(self as *mut Scheduler) {
+            return None;
+        }
+        node.take_parked();
+        //挂起期间执行时间不会改变，和挂起时放入的队列一致
+        let mut coroutine = unsafe {
+            if parked.get_execute_time() != u64::MAX {
+                self.suspend.remove(&parked)
+            } else {
+                self.waiting.remove(&parked)
+            }
+        };
+        coroutine.set_status(Status::Ready);
+        Some(coroutine)
+    }
+
+    fn push_ready(&mut self, runnable: Runnable, front: bool) {
+        if front {
+            self.ready.push_front(runnable)
+        } else {
+            self.ready.push_back(runnable)
+        }
+    }
+
+    //按优先级放入就绪队列
+    fn push_coroutine(&mut self, coroutine: Coroutine<UserFunction>, front: bool) {
+        match coroutine.get_priority() {
+            Priority::Low => self.push_idle(coroutine),
+            Priority::Normal => self.push_ready(Runnable::Coroutine(coroutine), front),
+            Priority::High => self.push_ready(Runnable::Coroutine(coroutine), true),
+        }
+    }
+
+    fn push_idle(&mut self, coroutine: Coroutine<UserFunction>) {
+        self.idle.push_back(coroutine);
+    }
+
+    fn has_ready(&self) -> bool {
+        !self.ready.is_empty() || !self.idle.is_empty()
+    }
+
+    //唤醒返回Pending的任务，已经被唤醒过的忽略
+    fn wake_task(&mut self, id: CoroutineId, front: bool) {
+        if let Some(task) = self.tasks.remove(&id) {
+            self.push_ready(Runnable::Task(task), front);
+        }
+    }
+
     fn check_ready(&mut self) {
-        for _ in 0..self.suspend.len() {
-            if let Some(entry) = self.suspend.front() {
-                let exec_time = entry.get_time();
-                if timer::now() < exec_time {
+        let now = timer::now();
+        while let Some(coroutine) = self.suspend.front() {
+            if now < coroutine.get_execute_time() {
+                break;
+            }
+            //移动至"就绪"队列
+            if let Some(mut coroutine) = self.suspend.pop_front() {
+                coroutine.wake_node().take_parked();
+                coroutine.set_status(Status::Ready);
+                //优先执行到时间的协程
+                self.push_coroutine(coroutine, true)
+            }
+        }
+        for _ in 0..self.timers.len() {
+            if let Some(entry) = self.timers.front() {
+                if timer::now() < entry.get_time() {
                     break;
                 }
-                //移动至"就绪"队列
-                if let Some(mut entry) = self.suspend.pop_front() {
-                    for _ in 0..entry.len() {
-                        if let Some(pointer) = entry.pop_front_raw() {
-                            unsafe {
-                                let mut coroutine = ptr::read_unaligned(
-                                    pointer
-                                        as *mut Coroutine<
-                                            dyn FnOnce(Option<*mut c_void>) -> Option<*mut c_void>,
-                                        >,
-                                );
-                                coroutine.set_status(Status::Ready);
-                                //优先执行到时间的协程
-                                self.ready.push_front(coroutine)
-                            }
-                        }
+                if let Some(mut entry) = self.timers.pop_front() {
+                    while let Some(id) = entry.pop_front() {
+                        self.wake_task(id, true);
                     }
                 }
             }
         }
     }
 
-    //todo 提供一个block版，如果suspend和ready没有，则把自己挂起
-    pub fn schedule(&mut self) -> ObjectList {
-        let mut scheduled = ObjectList::new();
-        while !self.suspend.is_empty() || !self.ready.is_empty() {
-            let mut temp = self.try_schedule();
-            while !temp.is_empty() {
-                scheduled.push_back_raw(temp.pop_front_raw().unwrap());
+    fn check_events(&mut self, timeout: Option<Duration>) {
+        match self.reactor.wait(timeout) {
+            Ok(nodes) => {
+                for node in nodes {
+                    if let Some(mut coroutine) = self.wake(&node) {
+                        coroutine.set_execute_time(0);
+                        self.push_coroutine(coroutine, false);
+                    } else {
+                        self.wake_task(node.id(), false);
+                    }
+                }
+            }
+            //信号打断了正在sleep的协程，处理函数不是通过hook注册的时候计数器不会增加
+            Err(error) if error.kind() == ErrorKind::Interrupted => {
+                Self::signaled();
+                self.check_signals();
             }
+            Err(_) => {}
         }
-        scheduled
     }
 
-    pub fn get_ready(&self) -> &ObjectList {
-        &self.ready
+    //sleep开始后线程处理过信号的协程提前唤醒，由它们自己返回EINTR
+    fn check_signals(&mut self) {
+        let signals = signals();
+        let interrupted: Vec<CoroutineId> = self
+            .sleeping
+            .iter()
+            .filter(|(_, (started, _))| *started != signals)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in interrupted {
+            let (_, node) = self
+                .sleeping
+                .remove(&id)
+                .expect("sleeping coroutine not found !");
+            if let Some(mut coroutine) = self.wake(&node) {
+                coroutine.set_execute_time(0);
+                self.push_coroutine(coroutine, false);
+            }
+        }
+    }
+
+    fn next_time(&self) -> u64 {
+        let coroutine = self
+            .suspend
+            .front()
+            .map_or(u64::MAX, |coroutine| coroutine.get_execute_time());
+        let task = self
+            .timers
+            .front()
+            .map_or(u64::MAX, |entry| entry.get_time());
+        coroutine.min(task)
+    }
+
+    /// 没有就绪或者被挂起的协程和任务
+    pub fn is_empty(&self) -> bool {
+        !self.has_ready()
+            && self.suspend.is_empty()
+            && self.waiting.is_empty()
+            && self.tasks.is_empty()
+    }
+
+    //todo 提供一个block版，如果suspend和ready没有，则把自己挂起
+    pub fn schedule(&mut self) -> CoroutineList {
+        let mut scheduled = CoroutineList::new();
+        while !self.is_empty() {
+            scheduled.append(&mut self.try_schedule());
+            //执行协程期间线程处理过的信号，在阻塞等待之前唤醒被打断的sleep
+            self.check_signals();
+            if !self.has_ready() && !self.is_empty() {
+                //没有可执行的协程，等待事件或者最近的定时器
+                let timeout = match self.next_time() {
+                    u64::MAX => None,
+                    time => Some(Duration::from_nanos(time.saturating_sub(timer::now()))),
+                };
+                self.check_events(timeout);
+            }
+        }
+        scheduled
     }
 
-    pub fn get_finished(&self) -> &ObjectList {
-        &self.finished
+    /// 就绪队列中协程和任务的数量
+    pub fn ready_len(&self) -> usize {
+        self.ready.len() + self.idle.len()
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::manual_dangling_ptr)]
 mod tests {
-    use crate::coroutine::Coroutine;
+    use crate::coroutine::{Coroutine, Priority, Status};
+    use crate::reactor::{WakeNode, Waker};
     use crate::scheduler::Scheduler;
+    use id_generator::{CoroutineId, SchedulerId};
     use std::os::raw::c_void;
+    use std::rc::Rc;
+    use std::sync::Arc;
     use std::thread;
     use std::time::Duration;
 
@@ -259,7 +632,7 @@ mod tests {
         let mut scheduler = Scheduler::new();
         scheduler.execute(Coroutine::new(
             2048,
-            |param| {
+            move |param| {
                 print!("env {} ", x);
                 match param {
                     Some(param) => {
@@ -275,7 +648,7 @@ mod tests {
         ));
         scheduler.execute(Coroutine::new(
             2048,
-            |param| {
+            move |param| {
                 print!("env {} ", y);
                 match param {
                     Some(param) => {
@@ -314,8 +687,8 @@ mod tests {
         assert_eq!(0, scheduler.try_schedule().len());
         assert_eq!(0, scheduler.ready.len());
         assert_eq!(1, scheduler.suspend.len());
-        let entry = scheduler.suspend.front().unwrap();
-        assert_eq!(1, entry.len());
+        let coroutine = scheduler.suspend.front().unwrap();
+        assert_eq!(Status::Suspend, coroutine.get_status());
 
         scheduler.execute(Coroutine::new(
             2048,
@@ -414,6 +787,63 @@ mod tests {
         );
     }
 
+    #[test]
+    fn release() {
+        let counter = Rc::new(());
+        let mut scheduler = Scheduler::new();
+        for delay in [0, 500] {
+            let captured = counter.clone();
+            scheduler.delay(
+                Duration::from_millis(delay),
+                Coroutine::new(
+                    2048,
+                    move |param| {
+                        drop(captured);
+                        param
+                    },
+                    None,
+                ),
+            );
+        }
+        assert_eq!(3, Rc::strong_count(&counter));
+        //调度器不再持有已完成的协程
+        let finished = scheduler.try_schedule();
+        assert_eq!(1, finished.len());
+        assert_eq!(Status::Exited, finished.front().unwrap().get_status());
+        drop(finished);
+        assert_eq!(2, Rc::strong_count(&counter));
+        //调度器释放时回收还没有执行的协程
+        drop(scheduler);
+        assert_eq!(1, Rc::strong_count(&counter));
+    }
+
+    #[test]
+    fn close_event() {
+        let mut fds = [0; 2];
+        assert_eq!(0, unsafe { libc::pipe(fds.as_mut_ptr()) });
+        let fd = fds[0];
+        //等待者在别的线程的调度器上
+        let handle = thread::spawn(move || {
+            let mut scheduler = Scheduler::new();
+            let join = scheduler.spawn(16 * 1024, move || {
+                Scheduler::wait_event(&[(fd, libc::POLLIN)], None)
+                    .unwrap_err()
+                    .raw_os_error()
+            });
+            scheduler.schedule();
+            join.join().unwrap()
+        });
+        while !handle.is_finished() {
+            Scheduler::close_event(fd);
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(Some(libc::EBADF), handle.join().unwrap());
+        unsafe {
+            libc::close(fds[0]);
+            libc::close(fds[1]);
+        }
+    }
+
     #[test]
     fn global() {
         let scheduler1 = Scheduler::global();
@@ -437,4 +867,137 @@ mod tests {
         let scheduler2 = Scheduler::current();
         assert_eq!(scheduler1, scheduler2);
     }
+
+    #[test]
+    fn yield_now() {
+        assert!(!Scheduler::yield_now());
+        let mut result: Vec<usize> = Vec::new();
+        let pointer = &mut result as *mut Vec<usize>;
+        let mut scheduler = Scheduler::new();
+        for i in 0..2 {
+            scheduler.execute(Coroutine::new(
+                16 * 1024,
+                move |param| {
+                    for j in 0..2 {
+                        unsafe { (*pointer).push(i * 10 + j) };
+                        assert!(Scheduler::yield_now());
+                    }
+                    param
+                },
+                None,
+            ));
+        }
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(vec![0, 10, 1, 11], result);
+    }
+
+    #[test]
+    fn delay_order() {
+        let mut result: Vec<u64> = Vec::new();
+        let pointer = &mut result as *mut Vec<u64>;
+        let mut scheduler = Scheduler::new();
+        for delay in [30, 10, 20] {
+            scheduler.delay(
+                Duration::from_millis(delay),
+                Coroutine::new(
+                    2048,
+                    move |param| {
+                        unsafe { (*pointer).push(delay) };
+                        param
+                    },
+                    None,
+                ),
+            );
+        }
+        assert_eq!(3, scheduler.suspend.len());
+        assert_eq!(3, scheduler.schedule().len());
+        assert_eq!(vec![10, 20, 30], result);
+    }
+
+    #[test]
+    fn sleep() {
+        assert!(!Scheduler::sleep(Duration::from_millis(1)).unwrap());
+        let mut result = (None, 0u64);
+        let pointer = &mut result as *mut (Option<(Arc<Waker>, Arc<WakeNode>)>, u64);
+        let mut scheduler = Scheduler::new();
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                unsafe { (*pointer).0 = Scheduler::waker() };
+                let start = timer::now();
+                assert!(Scheduler::sleep(Duration::from_millis(100)).unwrap());
+                unsafe { (*pointer).1 = timer::now() - start };
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                //提前唤醒sleep的协程
+                let (waker, node) = unsafe { (*pointer).0.take().unwrap() };
+                waker.wake(&node);
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        //被提前唤醒后继续sleep到时间
+        assert!(result.1 >= 100_000_000);
+    }
+
+    #[test]
+    fn coroutine_id() {
+        let mut scheduler = Scheduler::new();
+        let id = scheduler.get_id();
+        assert_ne!(SchedulerId::NONE, id);
+        assert_ne!(id, Scheduler::new().get_id());
+        //在协程里创建的协程属于该协程的调度器
+        let mut inner: Option<CoroutineId> = None;
+        let pointer = &mut inner as *mut Option<CoroutineId>;
+        scheduler.execute(Coroutine::new(
+            16 * 1024,
+            move |param| {
+                let coroutine = Coroutine::new(2048, |param| param, None);
+                unsafe { *pointer = Some(coroutine.get_id()) };
+                param
+            },
+            None,
+        ));
+        assert_eq!(1, scheduler.schedule().len());
+        assert_eq!(id, inner.unwrap().scheduler());
+    }
+
+    #[test]
+    fn priority() {
+        let mut result: Vec<&str> = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let mut scheduler = Scheduler::new();
+        for (name, priority) in [
+            ("low", Priority::Low),
+            ("normal", Priority::Normal),
+            ("high", Priority::High),
+        ] {
+            let mut coroutine = Coroutine::new(
+                16 * 1024,
+                move |param| {
+                    for _ in 0..2 {
+                        unsafe { (*pointer).push(name) };
+                        Scheduler::yield_now();
+                    }
+                    param
+                },
+                None,
+            );
+            coroutine.set_priority(priority);
+            scheduler.execute(coroutine);
+        }
+        assert_eq!(3, scheduler.ready_len());
+        assert_eq!(3, scheduler.schedule().len());
+        //低优先级的协程在其他协程都结束后才执行
+        assert_eq!(
+            vec!["high", "normal", "high", "normal", "low", "low"],
+            result
+        );
+    }
 }
@@ -0,0 +1,610 @@
+use crate::channel::{Receiver, RecvError, SendError, Sender, TryRecvError, TrySendError};
+use crate::scheduler::Scheduler;
+use crate::sync::CancelToken;
+use crate::waiter::Waiter;
+use std::io::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/*
+同时等待多个事件，第一个完成的分支生效，其余分支都会被注销；
+channel和CancelToken通过Waiter唤醒，fd通过reactor唤醒，超时通过调度器的定时器唤醒，
+不在协程中时挂起线程
+ */
+
+/// 同时等待多个分支，每个分支完成时把结果写到自己的slot里，
+/// 同一时刻有多个分支可以完成时随机选择一个，一般通过select!使用
+pub struct Select<'a> {
+    cases: Vec<Box<dyn Case + 'a>>,
+    default: Option<(usize, &'a mut Option<()>)>,
+}
+
+trait Case {
+    /// 尝试完成分支，成功时把结果写到slot里
+    fn try_complete(&mut self) -> bool;
+
+    fn add_waiter(&mut self, _waiter: &Arc<Waiter>) {}
+
+    fn remove_waiter(&mut self, _waiter: &Arc<Waiter>) {}
+
+    /// 需要reactor关注的fd事件
+    fn event(&self) -> Option<(libc::c_int, libc::c_short)> {
+        None
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        None
+    }
+}
+
+impl<'a> Select<'a> {
+    pub fn new() -> Self {
+        Select {
+            cases: Vec::new(),
+            default: None,
+        }
+    }
+
+    /// 从channel接收数据，channel断开时slot为Err
+    pub fn recv<T>(
+        &mut self,
+        receiver: &'a Receiver<T>,
+        slot: &'a mut Option<Result<T, RecvError>>,
+    ) -> usize {
+        self.add(RecvCase { receiver, slot })
+    }
+
+    /// 往channel发送value，没有接收者时slot为Err，value随错误返回
+    pub fn send<T>(
+        &mut self,
+        sender: &'a Sender<T>,
+        value: T,
+        slot: &'a mut Option<Result<(), SendError<T>>>,
+    ) -> usize {
+        self.add(SendCase {
+            sender,
+            value: Some(value),
+            slot,
+        })
+    }
+
+    /// fd可读，fd被关闭时slot为EBADF
+    pub fn readable(
+        &mut self,
+        fd: libc::c_int,
+        slot: &'a mut Option<std::io::Result<()>>,
+    ) -> usize {
+        self.add(EventCase {
+            fd,
+            events: libc::POLLIN,
+            slot,
+        })
+    }
+
+    /// fd可写，fd被关闭时slot为EBADF
+    pub fn writable(
+        &mut self,
+        fd: libc::c_int,
+        slot: &'a mut Option<std::io::Result<()>>,
+    ) -> usize {
+        self.add(EventCase {
+            fd,
+            events: libc::POLLOUT,
+            slot,
+        })
+    }
+
+    pub fn cancelled(&mut self, token: &'a CancelToken, slot: &'a mut Option<()>) -> usize {
+        self.add(CancelCase { token, slot })
+    }
+
+    pub fn timeout(&mut self, timeout: Duration, slot: &'a mut Option<()>) -> usize {
+        self.deadline(Instant::now() + timeout, slot)
+    }
+
+    pub fn deadline(&mut self, deadline: Instant, slot: &'a mut Option<()>) -> usize {
+        self.add(DeadlineCase { deadline, slot })
+    }
+
+    /// 其他分支都不能立即完成时执行，有default分支时wait不会挂起
+    pub fn default(&mut self, slot: &'a mut Option<()>) -> usize {
+        assert!(
+            self.default.is_none(),
+            "select has more than one default case"
+        );
+        let index = self.cases.len();
+        self.default = Some((index, slot));
+        //占位，保证分支的序号和注册顺序一致
+        self.add(NeverCase)
+    }
+
+    fn add(&mut self, case: impl Case + 'a) -> usize {
+        self.cases.push(Box::new(case));
+        self.cases.len() - 1
+    }
+
+    /// 等到有分支完成，返回该分支的序号
+    pub fn wait(mut self) -> usize {
+        loop {
+            if let Some(index) = self.try_complete() {
+                return index;
+            }
+            if let Some((index, slot)) = self.default.take() {
+                *slot = Some(());
+                return index;
+            }
+            let waiter = Waiter::current();
+            for case in self.cases.iter_mut() {
+                case.add_waiter(&waiter);
+            }
+            //注册之后再检查一次，避免丢失注册之前的通知
+            let completed = self.try_complete();
+            if completed.is_none() {
+                self.park(&waiter);
+            }
+            for case in self.cases.iter_mut() {
+                case.remove_waiter(&waiter);
+            }
+            if let Some(index) = completed {
+                return index;
+            }
+        }
+    }
+
+    fn try_complete(&mut self) -> Option<usize> {
+        let len = self.cases.len();
+        if len == 0 {
+            return None;
+        }
+        //从随机位置开始检查，避免排在前面的分支总是优先
+        let start = timer::now() as usize % len;
+        (0..len)
+            .map(|i| (start + i) % len)
+            .find(|index| self.cases[*index].try_complete())
+    }
+
+    //挂起直到可能有分支可以完成，可能被提前唤醒
+    fn park(&self, waiter: &Arc<Waiter>) {
+        if waiter.is_notified() {
+            return;
+        }
+        let deadline = self.cases.iter().filter_map(|case| case.deadline()).min();
+        let timeout = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(left) if !left.is_zero() => Some(left),
+                _ => return,
+            },
+            None => None,
+        };
+        let events: Vec<(libc::c_int, libc::c_short)> =
+            self.cases.iter().filter_map(|case| case.event()).collect();
+        if !matches!(Scheduler::wait_event(&events, timeout), Ok(false)) {
+            //在协程中，不管被什么唤醒都重新检查所有分支
+            return;
+        }
+        if events.is_empty() {
+            _ = waiter.wait(deadline);
+            return;
+        }
+        //线程不能同时等待fd和unpark，分段poll
+        let mut fds: Vec<libc::pollfd> = events
+            .iter()
+            .map(|(fd, events)| libc::pollfd {
+                fd: *fd,
+                events: *events,
+                revents: 0,
+            })
+            .collect();
+        while !waiter.is_notified() {
+            let slice = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(left) if !left.is_zero() => left.min(POLL_SLICE),
+                    _ => return,
+                },
+                None => POLL_SLICE,
+            };
+            let ms = slice.as_millis().max(1) as libc::c_int;
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, ms) } != 0 {
+                return;
+            }
+        }
+    }
+}
+
+impl Default for Select<'_> {
+    fn default() -> Self {
+        Select::new()
+    }
+}
+
+const POLL_SLICE: Duration = Duration::from_millis(10);
+
+struct RecvCase<'a, T> {
+    receiver: &'a Receiver<T>,
+    slot: &'a mut Option<Result<T, RecvError>>,
+}
+
+impl<T> Case for RecvCase<'_, T> {
+    fn try_complete(&mut self) -> bool {
+        *self.slot = match self.receiver.try_recv() {
+            Ok(value) => Some(Ok(value)),
+            Err(TryRecvError::Disconnected) => Some(Err(RecvError)),
+            Err(TryRecvError::Empty) => return false,
+        };
+        true
+    }
+
+    fn add_waiter(&mut self, waiter: &Arc<Waiter>) {
+        self.receiver.add_waiter(waiter);
+    }
+
+    fn remove_waiter(&mut self, waiter: &Arc<Waiter>) {
+        self.receiver.remove_waiter(waiter);
+    }
+}
+
+struct SendCase<'a, T> {
+    sender: &'a Sender<T>,
+    value: Option<T>,
+    slot: &'a mut Option<Result<(), SendError<T>>>,
+}
+
+impl<T> Case for SendCase<'_, T> {
+    fn try_complete(&mut self) -> bool {
+        let value = match self.value.take() {
+            Some(value) => value,
+            None => return false,
+        };
+        *self.slot = match self.sender.try_send(value) {
+            Ok(()) => Some(Ok(())),
+            Err(TrySendError::Disconnected(value)) => Some(Err(SendError(value))),
+            Err(TrySendError::Full(value)) => {
+                self.value = Some(value);
+                return false;
+            }
+        };
+        true
+    }
+
+    fn add_waiter(&mut self, waiter: &Arc<Waiter>) {
+        self.sender.add_waiter(waiter);
+    }
+
+    fn remove_waiter(&mut self, waiter: &Arc<Waiter>) {
+        self.sender.remove_waiter(waiter);
+    }
+}
+
+struct EventCase<'a> {
+    fd: libc::c_int,
+    events: libc::c_short,
+    slot: &'a mut Option<std::io::Result<()>>,
+}
+
+impl Case for EventCase<'_> {
+    fn try_complete(&mut self) -> bool {
+        let mut fd = libc::pollfd {
+            fd: self.fd,
+            events: self.events,
+            revents: 0,
+        };
+        *self.slot = match unsafe { libc::poll(&mut fd, 1, 0) } {
+            0 => return false,
+            -1 => {
+                let error = Error::last_os_error();
+                if error.raw_os_error() == Some(libc::EINTR) {
+                    return false;
+                }
+                Some(Err(error))
+            }
+            _ if fd.revents & libc::POLLNVAL != 0 => {
+                Some(Err(Error::from_raw_os_error(libc::EBADF)))
+            }
+            //POLLERR和POLLHUP也算就绪，由后续的读写返回具体的错误
+            _ => Some(Ok(())),
+        };
+        true
+    }
+
+    fn event(&self) -> Option<(libc::c_int, libc::c_short)> {
+        Some((self.fd, self.events))
+    }
+}
+
+struct CancelCase<'a> {
+    token: &'a CancelToken,
+    slot: &'a mut Option<()>,
+}
+
+impl Case for CancelCase<'_> {
+    fn try_complete(&mut self) -> bool {
+        if self.token.is_cancelled() {
+            *self.slot = Some(());
+            return true;
+        }
+        false
+    }
+
+    fn add_waiter(&mut self, waiter: &Arc<Waiter>) {
+        self.token.add_waiter(waiter);
+    }
+
+    fn remove_waiter(&mut self, waiter: &Arc<Waiter>) {
+        self.token.remove_waiter(waiter);
+    }
+}
+
+struct DeadlineCase<'a> {
+    deadline: Instant,
+    slot: &'a mut Option<()>,
+}
+
+impl Case for DeadlineCase<'_> {
+    fn try_complete(&mut self) -> bool {
+        if Instant::now() >= self.deadline {
+            *self.slot = Some(());
+            return true;
+        }
+        false
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        Some(self.deadline)
+    }
+}
+
+struct NeverCase;
+
+impl Case for NeverCase {
+    fn try_complete(&mut self) -> bool {
+        false
+    }
+}
+
+/// 同时等待多个分支，执行第一个完成的分支，整个select!的值为该分支的值，
+/// 分支之间用逗号分隔，分支体在select!所在的作用域里执行，可以使用return、break和?：
+/// ```ignore
+/// select! {
+///     recv(rx) -> msg => println!("{:?}", msg),
+///     send(tx, 1) -> res => res.unwrap(),
+///     readable(fd) -> res => res.unwrap(),
+///     writable(fd) -> res => res.unwrap(),
+///     cancelled(token) => println!("cancelled"),
+///     timeout(Duration::from_millis(50)) => println!("timeout"),
+///     deadline(instant) => println!("deadline"),
+///     default => println!("nothing ready"),
+/// }
+/// ```
+#[macro_export]
+macro_rules! select {
+    //slot要比Select活得久，所以先声明所有slot，最后再创建Select并注册分支
+    (@case $select:ident [$($register:tt)*] [$($dispatch:tt)*]) => {{
+        let mut $select = $crate::select::Select::new();
+        $($register)*
+        $select.wait();
+        $($dispatch)* { unreachable!("no select case completed") }
+    }};
+    (@case $select:ident [$($register:tt)*] [$($dispatch:tt)*]
+        recv($receiver:expr) -> $pattern:pat => $body:expr $(, $($rest:tt)*)?) => {{
+        let mut slot = None;
+        $crate::select!(@case $select
+            [$($register)* $select.recv(&$receiver, &mut slot);]
+            [$($dispatch)* if let Some($pattern) = slot { $body } else] $($($rest)*)?)
+    }};
+    (@case $select:ident [$($register:tt)*] [$($dispatch:tt)*]
+        send($sender:expr, $value:expr) -> $pattern:pat => $body:expr $(, $($rest:tt)*)?) => {{
+        let mut slot = None;
+        $crate::select!(@case $select
+            [$($register)* $select.send(&$sender, $value, &mut slot);]
+            [$($dispatch)* if let Some($pattern) = slot { $body } else] $($($rest)*)?)
+    }};
+    (@case $select:ident [$($register:tt)*] [$($dispatch:tt)*]
+        readable($fd:expr) -> $pattern:pat => $body:expr $(, $($rest:tt)*)?) => {{
+        let mut slot = None;
+        $crate::select!(@case $select
+            [$($register)* $select.readable($fd, &mut slot);]
+            [$($dispatch)* if let Some($pattern) = slot { $body } else] $($($rest)*)?)
+    }};
+    (@case $select:ident [$($register:tt)*] [$($dispatch:tt)*]
+        writable($fd:expr) -> $pattern:pat => $body:expr $(, $($rest:tt)*)?) => {{
+        let mut slot = None;
+        $crate::select!(@case $select
+            [$($register)* $select.writable($fd, &mut slot);]
+            [$($dispatch)* if let Some($pattern) = slot { $body } else] $($($rest)*)?)
+    }};
+    (@case $select:ident [$($register:tt)*] [$($dispatch:tt)*]
+        cancelled($token:expr) => $body:expr $(, $($rest:tt)*)?) => {{
+        let mut slot = None;
+        $crate::select!(@case $select
+            [$($register)* $select.cancelled(&$token, &mut slot);]
+            [$($dispatch)* if let Some(()) = slot { $body } else] $($($rest)*)?)
+    }};
+    (@case $select:ident [$($register:tt)*] [$($dispatch:tt)*]
+        timeout($timeout:expr) => $body:expr $(, $($rest:tt)*)?) => {{
+        let mut slot = None;
+        $crate::select!(@case $select
+            [$($register)* $select.timeout($timeout, &mut slot);]
+            [$($dispatch)* if let Some(()) = slot { $body } else] $($($rest)*)?)
+    }};
+    (@case $select:ident [$($register:tt)*] [$($dispatch:tt)*]
+        deadline($deadline:expr) => $body:expr $(, $($rest:tt)*)?) => {{
+        let mut slot = None;
+        $crate::select!(@case $select
+            [$($register)* $select.deadline($deadline, &mut slot);]
+            [$($dispatch)* if let Some(()) = slot { $body } else] $($($rest)*)?)
+    }};
+    (@case $select:ident [$($register:tt)*] [$($dispatch:tt)*]
+        default => $body:expr $(, $($rest:tt)*)?) => {{
+        let mut slot = None;
+        $crate::select!(@case $select
+            [$($register)* $select.default(&mut slot);]
+            [$($dispatch)* if let Some(()) = slot { $body } else] $($($rest)*)?)
+    }};
+    ($($cases:tt)+) => {
+        $crate::select!(@case select [] [] $($cases)+)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::channel::{bounded, TrySendError};
+    use crate::coroutine::Coroutine;
+    use crate::scheduler::Scheduler;
+    use crate::sync::CancelToken;
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+    use std::time::Duration;
+
+    #[test]
+    fn test_recv() {
+        let (tx_a, rx_a) = bounded::<i32>(1);
+        let (tx_b, rx_b) = bounded::<&str>(1);
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<String>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                for _ in 0..2 {
+                    let received = select! {
+                        recv(rx_a) -> msg => format!("a {}", msg.unwrap()),
+                        recv(rx_b) -> msg => format!("b {}", msg.unwrap()),
+                        timeout(Duration::from_secs(10)) => "timeout".to_string(),
+                    };
+                    unsafe { (*pointer).push(received) };
+                }
+                param
+            },
+            None,
+        ));
+        //发送者drop后channel断开也算就绪，保证select结束前发送者都还在
+        let (sender_a, sender_b) = (tx_a.clone(), tx_b.clone());
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                Scheduler::wait(Some(Duration::from_millis(10)));
+                sender_b.send("hello").unwrap();
+                Scheduler::wait(Some(Duration::from_millis(10)));
+                sender_a.send(1).unwrap();
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(vec!["b hello", "a 1"], result);
+        drop((tx_a, tx_b));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let (tx, rx) = bounded::<i32>(0);
+        let timed_out = select! {
+            recv(rx) -> _ => false,
+            timeout(Duration::from_millis(20)) => true,
+        };
+        assert!(timed_out);
+        //超时后已经注销，发送者不会认为还有接收者在等待
+        assert!(matches!(tx.try_send(1), Err(TrySendError::Full(1))));
+        let value = select! {
+            send(tx, 2) -> _ => 0,
+            default => 1,
+        };
+        assert_eq!(1, value);
+        drop(rx);
+        let disconnected = select! {
+            send(tx, 3) -> result => result.unwrap_err().0,
+        };
+        assert_eq!(3, disconnected);
+    }
+
+    #[test]
+    fn test_rendezvous() {
+        let (tx, rx) = bounded::<i32>(0);
+        let handle = std::thread::spawn(move || {
+            let received = select! {
+                recv(rx) -> msg => msg.ok(),
+                timeout(Duration::from_millis(100)) => None,
+            };
+            (received, rx)
+        });
+        std::thread::sleep(Duration::from_millis(20));
+        //select注册的接收者可能选择超时分支，不能让try_send成功后数据没人取走
+        assert!(matches!(tx.try_send(1), Err(TrySendError::Full(1))));
+        let (received, rx) = handle.join().unwrap();
+        assert_eq!(None, received);
+        assert!(rx.is_empty());
+        //阻塞的发送者会等到select取走数据
+        let handle = std::thread::spawn(move || {
+            select! {
+                recv(rx) -> msg => msg.ok(),
+                timeout(Duration::from_secs(10)) => None,
+            }
+        });
+        tx.send(2).unwrap();
+        assert_eq!(Some(2), handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_event() {
+        let (reader, mut writer) = std::os::unix::net::UnixStream::pair().unwrap();
+        let fd = reader.as_raw_fd();
+        let token = CancelToken::new();
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let scheduler = Scheduler::current();
+        let select_token = token.clone();
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                for _ in 0..2 {
+                    let event = select! {
+                        readable(fd) -> result => {
+                            result.unwrap();
+                            "readable"
+                        },
+                        cancelled(select_token) => "cancelled",
+                    };
+                    unsafe { (*pointer).push(event) };
+                    if event == "readable" {
+                        //读掉数据，下一轮只能等到被取消
+                        let mut buf = [0u8; 16];
+                        assert_eq!(5, unsafe {
+                            libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+                        });
+                    }
+                }
+                param
+            },
+            None,
+        ));
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            writer.write_all(b"hello").unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+            token.cancel();
+            //对端关闭也算可读，select结束前不能关闭
+            writer
+        });
+        assert_eq!(1, scheduler.schedule().len());
+        drop(handle.join().unwrap());
+        assert_eq!(vec!["readable", "cancelled"], result);
+    }
+
+    #[test]
+    fn test_thread() {
+        //不在协程中同时等待fd和取消
+        let (reader, _writer) = std::os::unix::net::UnixStream::pair().unwrap();
+        let token = CancelToken::new();
+        let canceller = token.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            canceller.cancel();
+        });
+        let cancelled = select! {
+            readable(reader.as_raw_fd()) -> _ => false,
+            cancelled(token) => true,
+        };
+        assert!(cancelled);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,178 @@
+use crate::sync::{lock, wait, WaitQueue};
+use std::sync::{Arc, Mutex};
+
+/// 屏障，n个协程或线程都调用wait后才一起继续执行，可以重复使用
+#[derive(Debug)]
+pub struct CoBarrier {
+    n: usize,
+    state: Mutex<BarrierState>,
+}
+
+#[derive(Debug)]
+struct BarrierState {
+    count: usize,
+    //每凑齐一轮加一
+    generation: usize,
+    waiters: WaitQueue,
+}
+
+/// CoBarrier::wait的结果，每轮只有一个等待者是leader
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct CoBarrierWaitResult(bool);
+
+impl CoBarrierWaitResult {
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl CoBarrier {
+    pub fn new(n: usize) -> Self {
+        CoBarrier {
+            n,
+            state: Mutex::new(BarrierState {
+                count: 0,
+                generation: 0,
+                waiters: WaitQueue::new(),
+            }),
+        }
+    }
+
+    pub fn wait(&self) -> CoBarrierWaitResult {
+        let mut state = lock(&self.state);
+        state.count += 1;
+        if state.count >= self.n {
+            state.count = 0;
+            state.generation = state.generation.wrapping_add(1);
+            state.waiters.notify_all();
+            return CoBarrierWaitResult(true);
+        }
+        let generation = state.generation;
+        while generation == state.generation {
+            state = wait(&self.state, state, |s| &mut s.waiters, None).0;
+        }
+        CoBarrierWaitResult(false)
+    }
+}
+
+/// 等待一组任务完成，同go的sync.WaitGroup，clone出的WaitGroup共享计数
+#[derive(Debug, Clone, Default)]
+pub struct WaitGroup {
+    inner: Arc<Mutex<GroupState>>,
+}
+
+#[derive(Debug, Default)]
+struct GroupState {
+    count: usize,
+    waiters: WaitQueue,
+}
+
+impl WaitGroup {
+    pub fn new() -> Self {
+        WaitGroup::default()
+    }
+
+    /// 增加n个任务
+    pub fn add(&self, n: usize) {
+        lock(&self.inner).count += n;
+    }
+
+    /// 一个任务完成，计数为0时唤醒所有等待者
+    pub fn done(&self) {
+        let mut state = lock(&self.inner);
+        state.count = state
+            .count
+            .checked_sub(1)
+            .expect("WaitGroup::done called more times than add");
+        if state.count == 0 {
+            state.waiters.notify_all();
+        }
+    }
+
+    /// 等到计数为0
+    pub fn wait(&self) {
+        let mut state = lock(&self.inner);
+        while state.count > 0 {
+            state = wait(&self.inner, state, |s| &mut s.waiters, None).0;
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        lock(&self.inner).count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coroutine::Coroutine;
+    use crate::scheduler::Scheduler;
+    use crate::sync::{CoBarrier, WaitGroup};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_barrier() {
+        let barrier = Arc::new(CoBarrier::new(4));
+        let leaders = Arc::new(AtomicUsize::new(0));
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let scheduler = Scheduler::current();
+        for _ in 0..3 {
+            let (barrier, leaders) = (barrier.clone(), leaders.clone());
+            scheduler.execute(Coroutine::new(
+                64 * 1024,
+                move |param| {
+                    unsafe { (*pointer).push("before") };
+                    if barrier.wait().is_leader() {
+                        leaders.fetch_add(1, Ordering::SeqCst);
+                    }
+                    unsafe { (*pointer).push("after") };
+                    param
+                },
+                None,
+            ));
+        }
+        //第四个等待者在别的线程
+        let (thread_barrier, thread_leaders) = (barrier.clone(), leaders.clone());
+        let handle = std::thread::spawn(move || {
+            if thread_barrier.wait().is_leader() {
+                thread_leaders.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        assert_eq!(3, scheduler.schedule().len());
+        handle.join().unwrap();
+        assert_eq!(1, leaders.load(Ordering::SeqCst));
+        assert_eq!(
+            vec!["before", "before", "before", "after", "after", "after"],
+            result
+        );
+    }
+
+    #[test]
+    fn test_wait_group() {
+        let group = WaitGroup::new();
+        let counter = Arc::new(AtomicUsize::new(0));
+        let scheduler = Scheduler::current();
+        group.add(3);
+        for _ in 0..3 {
+            let (group, counter) = (group.clone(), counter.clone());
+            std::thread::spawn(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                group.done();
+            });
+        }
+        let waiter = group.clone();
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                waiter.wait();
+                assert_eq!(3, counter.load(Ordering::SeqCst));
+                param
+            },
+            None,
+        ));
+        assert_eq!(1, scheduler.schedule().len());
+        assert_eq!(0, group.count());
+        group.wait();
+    }
+}
@@ -0,0 +1,109 @@
+use crate::sync::{lock, wait, WaitQueue};
+use crate::waiter::Waiter;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 取消令牌，clone出的令牌共享状态，任意一个cancel后所有等待者都会被唤醒，
+/// 可以作为select的一个分支
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    inner: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    cancelled: bool,
+    waiters: WaitQueue,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    /// 取消，返回是否由本次调用取消
+    pub fn cancel(&self) -> bool {
+        let mut state = lock(&self.inner);
+        if state.cancelled {
+            return false;
+        }
+        state.cancelled = true;
+        state.waiters.notify_all();
+        true
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        lock(&self.inner).cancelled
+    }
+
+    /// 等到被取消
+    pub fn cancelled(&self) {
+        self.cancelled_until(None);
+    }
+
+    /// 在timeout内被取消返回true
+    pub fn cancelled_timeout(&self, timeout: Duration) -> bool {
+        self.cancelled_until(Some(Instant::now() + timeout))
+    }
+
+    fn cancelled_until(&self, deadline: Option<Instant>) -> bool {
+        let mut state = lock(&self.inner);
+        while !state.cancelled {
+            let (guard, notified) = wait(&self.inner, state, |s| &mut s.waiters, deadline);
+            state = guard;
+            if !notified {
+                break;
+            }
+        }
+        state.cancelled
+    }
+
+    pub(crate) fn add_waiter(&self, waiter: &Arc<Waiter>) {
+        let mut state = lock(&self.inner);
+        if state.cancelled {
+            waiter.wake();
+        } else {
+            state.waiters.push(waiter.clone());
+        }
+    }
+
+    //cancel会唤醒所有等待者，不需要转交唤醒
+    pub(crate) fn remove_waiter(&self, waiter: &Arc<Waiter>) {
+        lock(&self.inner).waiters.remove(waiter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coroutine::Coroutine;
+    use crate::scheduler::Scheduler;
+    use crate::sync::CancelToken;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        let token = CancelToken::new();
+        assert!(!token.cancelled_timeout(Duration::from_millis(10)));
+        let scheduler = Scheduler::current();
+        for _ in 0..2 {
+            let token = token.clone();
+            scheduler.execute(Coroutine::new(
+                64 * 1024,
+                move |param| {
+                    token.cancelled();
+                    assert!(token.is_cancelled());
+                    param
+                },
+                None,
+            ));
+        }
+        let canceller = token.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            assert!(canceller.cancel());
+        });
+        assert_eq!(2, scheduler.schedule().len());
+        handle.join().unwrap();
+        assert!(!token.cancel());
+    }
+}
@@ -0,0 +1,169 @@
+use crate::sync::{lock, wait, CoMutexGuard, WaitQueue};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 条件变量，配合CoMutex使用，可能被虚假唤醒，调用者需要重新检查条件
+#[derive(Debug, Default)]
+pub struct CoCondvar {
+    waiters: Mutex<WaitQueue>,
+}
+
+/// wait_timeout的结果
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct WaitTimeoutResult(bool);
+
+impl WaitTimeoutResult {
+    pub fn timed_out(&self) -> bool {
+        self.0
+    }
+}
+
+impl CoCondvar {
+    pub fn new() -> Self {
+        CoCondvar {
+            waiters: Mutex::new(WaitQueue::new()),
+        }
+    }
+
+    /// 释放锁并等待通知，被唤醒后重新获取锁
+    pub fn wait<'a, T: ?Sized>(&self, guard: CoMutexGuard<'a, T>) -> CoMutexGuard<'a, T> {
+        self.wait_until(guard, None).0
+    }
+
+    pub fn wait_while<'a, T: ?Sized>(
+        &self,
+        mut guard: CoMutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> CoMutexGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// 超时时间由调度器的定时器管理，不在协程中时挂起线程
+    pub fn wait_timeout<'a, T: ?Sized>(
+        &self,
+        guard: CoMutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> (CoMutexGuard<'a, T>, WaitTimeoutResult) {
+        self.wait_until(guard, Some(Instant::now() + timeout))
+    }
+
+    pub fn wait_timeout_while<'a, T: ?Sized>(
+        &self,
+        mut guard: CoMutexGuard<'a, T>,
+        timeout: Duration,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> (CoMutexGuard<'a, T>, WaitTimeoutResult) {
+        let deadline = Instant::now() + timeout;
+        while condition(&mut *guard) {
+            let (g, result) = self.wait_until(guard, Some(deadline));
+            guard = g;
+            if result.timed_out() {
+                let timed_out = condition(&mut *guard);
+                return (guard, WaitTimeoutResult(timed_out));
+            }
+        }
+        (guard, WaitTimeoutResult(false))
+    }
+
+    fn wait_until<'a, T: ?Sized>(
+        &self,
+        guard: CoMutexGuard<'a, T>,
+        deadline: Option<Instant>,
+    ) -> (CoMutexGuard<'a, T>, WaitTimeoutResult) {
+        let mutex = guard.mutex();
+        let waiters = lock(&self.waiters);
+        //先排队再释放锁，避免丢失释放锁和等待之间的通知
+        drop(guard);
+        let (waiters, notified) = wait(&self.waiters, waiters, |queue| queue, deadline);
+        drop(waiters);
+        (mutex.lock(), WaitTimeoutResult(!notified))
+    }
+
+    pub fn notify_one(&self) {
+        lock(&self.waiters).notify_one();
+    }
+
+    pub fn notify_all(&self) {
+        lock(&self.waiters).notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coroutine::Coroutine;
+    use crate::scheduler::Scheduler;
+    use crate::sync::{CoCondvar, CoMutex};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        let pair = Arc::new((CoMutex::new(false), CoCondvar::new()));
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let scheduler = Scheduler::current();
+        let waiter = pair.clone();
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                let (mutex, condvar) = &*waiter;
+                let guard = condvar.wait_while(mutex.lock(), |ready| !*ready);
+                assert!(*guard);
+                unsafe { (*pointer).push("wait") };
+                param
+            },
+            None,
+        ));
+        let notifier = pair.clone();
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                let (mutex, condvar) = &*notifier;
+                *mutex.lock() = true;
+                unsafe { (*pointer).push("notify") };
+                condvar.notify_one();
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(vec!["notify", "wait"], result);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let pair = Arc::new((CoMutex::new(0), CoCondvar::new()));
+        let scheduler = Scheduler::current();
+        let waiter = pair.clone();
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                let (mutex, condvar) = &*waiter;
+                let (guard, result) = condvar.wait_timeout(mutex.lock(), Duration::from_millis(20));
+                assert!(result.timed_out());
+                drop(guard);
+                //被别的线程唤醒
+                let (guard, result) =
+                    condvar.wait_timeout_while(mutex.lock(), Duration::from_secs(10), |value| {
+                        *value == 0
+                    });
+                assert!(!result.timed_out());
+                assert_eq!(1, *guard);
+                param
+            },
+            None,
+        ));
+        let notifier = pair.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            let (mutex, condvar) = &*notifier;
+            *mutex.lock() = 1;
+            condvar.notify_all();
+        });
+        assert_eq!(1, scheduler.schedule().len());
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+use crate::waiter::Waiter;
+use object_list::ObjectList;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+/*
+协程同步原语，等待时挂起当前协程，不在协程中时挂起线程，
+协程和线程可以混用，也可以跨调度器使用；
+内部短暂持有std::sync::Mutex保护状态，不会在持有期间等待
+ */
+
+mod mutex;
+
+mod rwlock;
+
+mod condvar;
+
+mod semaphore;
+
+mod barrier;
+
+mod cancel;
+
+pub use barrier::{CoBarrier, CoBarrierWaitResult, WaitGroup};
+pub use cancel::CancelToken;
+pub use condvar::{CoCondvar, WaitTimeoutResult};
+pub use mutex::{CoMutex, CoMutexGuard};
+pub use rwlock::{CoRwLock, CoRwLockReadGuard, CoRwLockWriteGuard};
+pub use semaphore::{CoSemaphore, CoSemaphorePermit};
+
+/// 等待队列，按等待顺序保存Arc<Waiter>
+#[derive(Debug, Default)]
+pub(crate) struct WaitQueue {
+    waiters: ObjectList<Arc<Waiter>>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        WaitQueue {
+            waiters: ObjectList::new(),
+        }
+    }
+
+    pub fn push(&mut self, waiter: Arc<Waiter>) {
+        self.waiters.push_back(waiter);
+    }
+
+    /// 唤醒最早的等待者，返回是否有等待者
+    pub fn notify_one(&mut self) -> bool {
+        match self.waiters.pop_front() {
+            Some(waiter) => {
+                waiter.wake();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 唤醒所有等待者，返回唤醒的数量
+    pub fn notify_all(&mut self) -> usize {
+        let mut count = 0;
+        while self.notify_one() {
+            count += 1;
+        }
+        count
+    }
+
+    /// 删除等待者，返回是否删除成功，失败说明已经被唤醒了
+    pub fn remove(&mut self, waiter: &Arc<Waiter>) -> bool {
+        match self.waiters.iter().position(|w| Arc::ptr_eq(w, waiter)) {
+            Some(index) => self.waiters.remove(index).is_some(),
+            None => false,
+        }
+    }
+}
+
+pub(crate) fn lock<S>(state: &Mutex<S>) -> MutexGuard<'_, S> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// 把当前协程或线程放到queue选出的等待队列上，释放state的锁并等待，
+/// 返回重新加锁后的state和是否在deadline之前被唤醒；
+/// 超时时如果恰好被唤醒了，把唤醒转交给下一个等待者，避免唤醒丢失
+pub(crate) fn wait<'a, S>(
+    state: &'a Mutex<S>,
+    mut guard: MutexGuard<'a, S>,
+    queue: impl Fn(&mut S) -> &mut WaitQueue,
+    deadline: Option<Instant>,
+) -> (MutexGuard<'a, S>, bool) {
+    let waiter = Waiter::current();
+    queue(&mut guard).push(waiter.clone());
+    drop(guard);
+    let notified = waiter.wait(deadline);
+    let mut guard = lock(state);
+    if !notified && !queue(&mut guard).remove(&waiter) {
+        queue(&mut guard).notify_one();
+    }
+    (guard, notified)
+}
@@ -0,0 +1,221 @@
+use crate::sync::{lock, wait, WaitQueue};
+use std::cell::UnsafeCell;
+use std::fmt::{Debug, Formatter};
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 互斥锁，lock时锁被占用则挂起当前协程；
+/// 持有锁的协程可以被挂起，同一线程上别的协程仍能执行；不支持poison
+pub struct CoMutex<T: ?Sized> {
+    state: Mutex<State>,
+    data: UnsafeCell<T>,
+}
+
+#[derive(Debug)]
+struct State {
+    locked: bool,
+    waiters: WaitQueue,
+}
+
+unsafe impl<T: ?Sized + Send> Send for CoMutex<T> {}
+
+unsafe impl<T: ?Sized + Send> Sync for CoMutex<T> {}
+
+impl<T> CoMutex<T> {
+    pub fn new(data: T) -> Self {
+        CoMutex {
+            state: Mutex::new(State {
+                locked: false,
+                waiters: WaitQueue::new(),
+            }),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> CoMutex<T> {
+    pub fn lock(&self) -> CoMutexGuard<'_, T> {
+        self.lock_until(None)
+            .expect("lock without deadline never times out")
+    }
+
+    /// 在timeout内获取锁，超时返回None
+    pub fn try_lock_for(&self, timeout: Duration) -> Option<CoMutexGuard<'_, T>> {
+        self.lock_until(Some(Instant::now() + timeout))
+    }
+
+    pub fn try_lock(&self) -> Option<CoMutexGuard<'_, T>> {
+        let mut state = lock(&self.state);
+        if state.locked {
+            return None;
+        }
+        state.locked = true;
+        Some(CoMutexGuard { mutex: self })
+    }
+
+    fn lock_until(&self, deadline: Option<Instant>) -> Option<CoMutexGuard<'_, T>> {
+        let mut state = lock(&self.state);
+        loop {
+            if !state.locked {
+                state.locked = true;
+                return Some(CoMutexGuard { mutex: self });
+            }
+            let (guard, notified) = wait(&self.state, state, |s| &mut s.waiters, deadline);
+            state = guard;
+            if !notified && state.locked {
+                return None;
+            }
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        lock(&self.state).locked
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    pub(crate) fn unlock(&self) {
+        let mut state = lock(&self.state);
+        state.locked = false;
+        state.waiters.notify_one();
+    }
+}
+
+impl<T: Default> Default for CoMutex<T> {
+    fn default() -> Self {
+        CoMutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized + Debug> Debug for CoMutex<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut d = f.debug_struct("CoMutex");
+        match self.try_lock() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+/// CoMutex的锁，drop时释放
+pub struct CoMutexGuard<'a, T: ?Sized> {
+    mutex: &'a CoMutex<T>,
+}
+
+impl<'a, T: ?Sized> CoMutexGuard<'a, T> {
+    pub(crate) fn mutex(&self) -> &'a CoMutex<T> {
+        self.mutex
+    }
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for CoMutexGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for CoMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for CoMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for CoMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+impl<T: ?Sized + Debug> Debug for CoMutexGuard<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coroutine::Coroutine;
+    use crate::scheduler::Scheduler;
+    use crate::sync::CoMutex;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_lock() {
+        let mutex = Arc::new(CoMutex::new(Vec::new()));
+        let scheduler = Scheduler::current();
+        for i in 0..3 {
+            let mutex = mutex.clone();
+            scheduler.execute(Coroutine::new(
+                64 * 1024,
+                move |param| {
+                    let mut guard = mutex.lock();
+                    guard.push(i);
+                    //持有锁时挂起，别的协程lock时也会被挂起，而不是阻塞线程
+                    Scheduler::wait(Some(Duration::from_millis(10)));
+                    guard.push(i);
+                    param
+                },
+                None,
+            ));
+        }
+        assert_eq!(3, scheduler.schedule().len());
+        assert_eq!(vec![0, 0, 1, 1, 2, 2], *mutex.lock());
+    }
+
+    #[test]
+    fn test_threads() {
+        let mutex = Arc::new(CoMutex::new(0));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let mutex = mutex.clone();
+                std::thread::spawn(move || {
+                    let scheduler = Scheduler::current();
+                    for _ in 0..4 {
+                        let mutex = mutex.clone();
+                        scheduler.execute(Coroutine::new(
+                            64 * 1024,
+                            move |param| {
+                                for _ in 0..100 {
+                                    *mutex.lock() += 1;
+                                }
+                                param
+                            },
+                            None,
+                        ));
+                    }
+                    scheduler.schedule();
+                    //不在协程中也可以使用
+                    *mutex.lock() += 1;
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(4 * 4 * 100 + 4, *mutex.lock());
+    }
+
+    #[test]
+    fn test_try_lock() {
+        let mutex = CoMutex::new(1);
+        let guard = mutex.lock();
+        assert!(mutex.try_lock().is_none());
+        assert!(mutex.try_lock_for(Duration::from_millis(10)).is_none());
+        drop(guard);
+        assert_eq!(1, *mutex.try_lock().unwrap());
+        assert_eq!(1, mutex.into_inner());
+    }
+}
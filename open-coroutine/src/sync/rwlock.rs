@@ -0,0 +1,243 @@
+use crate::sync::{lock, wait, WaitQueue};
+use std::cell::UnsafeCell;
+use std::fmt::{Debug, Formatter};
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+/// 读写锁，有写者在等待时新的读者也要等待，避免写者饿死；不支持poison
+pub struct CoRwLock<T: ?Sized> {
+    state: Mutex<State>,
+    data: UnsafeCell<T>,
+}
+
+#[derive(Debug)]
+struct State {
+    readers: usize,
+    writer: bool,
+    waiting_writers: usize,
+    read_waiters: WaitQueue,
+    write_waiters: WaitQueue,
+}
+
+unsafe impl<T: ?Sized + Send> Send for CoRwLock<T> {}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for CoRwLock<T> {}
+
+impl<T> CoRwLock<T> {
+    pub fn new(data: T) -> Self {
+        CoRwLock {
+            state: Mutex::new(State {
+                readers: 0,
+                writer: false,
+                waiting_writers: 0,
+                read_waiters: WaitQueue::new(),
+                write_waiters: WaitQueue::new(),
+            }),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> CoRwLock<T> {
+    pub fn read(&self) -> CoRwLockReadGuard<'_, T> {
+        let mut state = lock(&self.state);
+        while state.writer || state.waiting_writers > 0 {
+            state = wait(&self.state, state, |s| &mut s.read_waiters, None).0;
+        }
+        state.readers += 1;
+        CoRwLockReadGuard { lock: self }
+    }
+
+    pub fn try_read(&self) -> Option<CoRwLockReadGuard<'_, T>> {
+        let mut state = lock(&self.state);
+        if state.writer || state.waiting_writers > 0 {
+            return None;
+        }
+        state.readers += 1;
+        Some(CoRwLockReadGuard { lock: self })
+    }
+
+    pub fn write(&self) -> CoRwLockWriteGuard<'_, T> {
+        let mut state = lock(&self.state);
+        state.waiting_writers += 1;
+        while state.writer || state.readers > 0 {
+            state = wait(&self.state, state, |s| &mut s.write_waiters, None).0;
+        }
+        state.waiting_writers -= 1;
+        state.writer = true;
+        CoRwLockWriteGuard { lock: self }
+    }
+
+    pub fn try_write(&self) -> Option<CoRwLockWriteGuard<'_, T>> {
+        let mut state = lock(&self.state);
+        if state.writer || state.readers > 0 {
+            return None;
+        }
+        state.writer = true;
+        Some(CoRwLockWriteGuard { lock: self })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn unlock_read(&self) {
+        let mut state = lock(&self.state);
+        state.readers -= 1;
+        if state.readers == 0 {
+            state.write_waiters.notify_one();
+        }
+    }
+
+    fn unlock_write(&self) {
+        let mut state = lock(&self.state);
+        state.writer = false;
+        //优先唤醒写者，没有写者时唤醒所有读者
+        if state.waiting_writers == 0 || !state.write_waiters.notify_one() {
+            state.read_waiters.notify_all();
+        }
+    }
+}
+
+impl<T: Default> Default for CoRwLock<T> {
+    fn default() -> Self {
+        CoRwLock::new(T::default())
+    }
+}
+
+impl<T: ?Sized + Debug> Debug for CoRwLock<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut d = f.debug_struct("CoRwLock");
+        match self.try_read() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+/// CoRwLock的读锁，drop时释放
+pub struct CoRwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a CoRwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for CoRwLockReadGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for CoRwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for CoRwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.unlock_read();
+    }
+}
+
+impl<T: ?Sized + Debug> Debug for CoRwLockReadGuard<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+/// CoRwLock的写锁，drop时释放
+pub struct CoRwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a CoRwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for CoRwLockWriteGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for CoRwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for CoRwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for CoRwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.unlock_write();
+    }
+}
+
+impl<T: ?Sized + Debug> Debug for CoRwLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coroutine::Coroutine;
+    use crate::scheduler::Scheduler;
+    use crate::sync::CoRwLock;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        let lock = Arc::new(CoRwLock::new(Vec::new()));
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<String>;
+        let scheduler = Scheduler::current();
+        for i in 0..2 {
+            let lock = lock.clone();
+            scheduler.execute(Coroutine::new(
+                64 * 1024,
+                move |param| {
+                    let guard = lock.read();
+                    unsafe { (*pointer).push(format!("read {}", i)) };
+                    //读锁可以同时持有
+                    Scheduler::wait(Some(Duration::from_millis(10 + 10 * i)));
+                    unsafe { (*pointer).push(format!("read {} {:?}", i, *guard)) };
+                    param
+                },
+                None,
+            ));
+        }
+        let writer = lock.clone();
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                writer.write().push(1);
+                unsafe { (*pointer).push("write".to_string()) };
+                param
+            },
+            None,
+        ));
+        assert_eq!(3, scheduler.schedule().len());
+        assert_eq!(
+            vec!["read 0", "read 1", "read 0 []", "read 1 []", "write"],
+            result
+        );
+        assert_eq!(vec![1], *lock.read());
+    }
+
+    #[test]
+    fn test_try() {
+        let lock = CoRwLock::new(1);
+        let read = lock.read();
+        assert!(lock.try_read().is_some());
+        assert!(lock.try_write().is_none());
+        drop(read);
+        let mut write = lock.try_write().unwrap();
+        *write += 1;
+        assert!(lock.try_read().is_none());
+        drop(write);
+        assert_eq!(2, lock.into_inner());
+    }
+}
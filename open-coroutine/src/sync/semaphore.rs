@@ -0,0 +1,146 @@
+use crate::sync::{lock, wait, WaitQueue};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 计数信号量，没有许可时挂起当前协程
+#[derive(Debug)]
+pub struct CoSemaphore {
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    permits: usize,
+    waiters: WaitQueue,
+}
+
+impl CoSemaphore {
+    pub fn new(permits: usize) -> Self {
+        CoSemaphore {
+            state: Mutex::new(State {
+                permits,
+                waiters: WaitQueue::new(),
+            }),
+        }
+    }
+
+    /// 获取一个许可，返回的CoSemaphorePermit被drop时归还
+    pub fn acquire(&self) -> CoSemaphorePermit<'_> {
+        self.acquire_until(None)
+            .expect("acquire without deadline never times out")
+    }
+
+    pub fn try_acquire(&self) -> Option<CoSemaphorePermit<'_>> {
+        let mut state = lock(&self.state);
+        if state.permits == 0 {
+            return None;
+        }
+        state.permits -= 1;
+        Some(CoSemaphorePermit { semaphore: self })
+    }
+
+    /// 在timeout内获取许可，超时返回None
+    pub fn acquire_timeout(&self, timeout: Duration) -> Option<CoSemaphorePermit<'_>> {
+        self.acquire_until(Some(Instant::now() + timeout))
+    }
+
+    fn acquire_until(&self, deadline: Option<Instant>) -> Option<CoSemaphorePermit<'_>> {
+        let mut state = lock(&self.state);
+        loop {
+            if state.permits > 0 {
+                state.permits -= 1;
+                return Some(CoSemaphorePermit { semaphore: self });
+            }
+            let (guard, notified) = wait(&self.state, state, |s| &mut s.waiters, deadline);
+            state = guard;
+            if !notified && state.permits == 0 {
+                return None;
+            }
+        }
+    }
+
+    /// 增加n个许可
+    pub fn release(&self, n: usize) {
+        let mut state = lock(&self.state);
+        state.permits += n;
+        for _ in 0..n {
+            if !state.waiters.notify_one() {
+                break;
+            }
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        lock(&self.state).permits
+    }
+}
+
+/// 信号量的许可，drop时归还
+#[derive(Debug)]
+pub struct CoSemaphorePermit<'a> {
+    semaphore: &'a CoSemaphore,
+}
+
+impl CoSemaphorePermit<'_> {
+    /// 不归还许可
+    pub fn forget(self) {
+        std::mem::forget(self)
+    }
+}
+
+impl Drop for CoSemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.release(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coroutine::Coroutine;
+    use crate::scheduler::Scheduler;
+    use crate::sync::CoSemaphore;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        let semaphore = Arc::new(CoSemaphore::new(2));
+        let running = Arc::new(AtomicUsize::new(0));
+        let max = Arc::new(AtomicUsize::new(0));
+        let scheduler = Scheduler::current();
+        for _ in 0..5 {
+            let (semaphore, running, max) = (semaphore.clone(), running.clone(), max.clone());
+            scheduler.execute(Coroutine::new(
+                64 * 1024,
+                move |param| {
+                    let _permit = semaphore.acquire();
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max.fetch_max(now, Ordering::SeqCst);
+                    Scheduler::wait(Some(Duration::from_millis(10)));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    param
+                },
+                None,
+            ));
+        }
+        assert_eq!(5, scheduler.schedule().len());
+        //最多同时有两个协程持有许可
+        assert_eq!(2, max.load(Ordering::SeqCst));
+        assert_eq!(2, semaphore.available_permits());
+    }
+
+    #[test]
+    fn test_try() {
+        let semaphore = CoSemaphore::new(1);
+        let permit = semaphore.try_acquire().unwrap();
+        assert!(semaphore.try_acquire().is_none());
+        assert!(semaphore
+            .acquire_timeout(Duration::from_millis(10))
+            .is_none());
+        permit.forget();
+        assert_eq!(0, semaphore.available_permits());
+        semaphore.release(1);
+        assert!(semaphore.try_acquire().is_some());
+    }
+}
@@ -0,0 +1,101 @@
+use crate::queue::{Kind, Node};
+use crate::reactor::{WakeNode, Waker as ReactorWaker};
+use crate::scheduler::Scheduler;
+use id_generator::{CoroutineId, SchedulerId};
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Wake, Waker};
+
+/*
+无栈任务，由调度器直接poll，不需要从memory_pool分配栈；
+和协程共用就绪队列、定时器和reactor，id也和协程从同一个生成器分配
+ */
+
+thread_local! {
+    //正在poll的任务所在的调度器和任务的唤醒节点
+    static CURRENT: RefCell<Option<(*mut Scheduler, Arc<WakeNode>)>> = const { RefCell::new(None) };
+}
+
+/// 正在poll的任务所在的调度器和任务的唤醒节点，不在任务中时返回None
+pub(crate) fn current() -> Option<(*mut Scheduler, Arc<WakeNode>)> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+//唤醒时通过reactor的Waker把任务放回调度器的就绪队列，可以在任意线程唤醒
+struct TaskWaker {
+    reactor: Arc<ReactorWaker>,
+    node: Arc<WakeNode>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.reactor.wake(&self.node)
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.reactor.wake(&self.node)
+    }
+}
+
+#[repr(C)]
+pub(crate) struct Task {
+    //就绪队列的链接，必须是第一个字段
+    node: Node,
+    wake: Arc<WakeNode>,
+    future: Pin<Box<dyn Future<Output = ()>>>,
+    waker: Waker,
+}
+
+impl Task {
+    pub fn new(
+        scheduler: SchedulerId,
+        future: impl Future<Output = ()> + 'static,
+        reactor: Arc<ReactorWaker>,
+    ) -> Box<Self> {
+        let wake = Arc::new(WakeNode::new(CoroutineId::next(scheduler)));
+        Box::new(Task {
+            node: Node::new(Kind::Task),
+            wake: wake.clone(),
+            future: Box::pin(future),
+            waker: Waker::from(Arc::new(TaskWaker {
+                reactor,
+                node: wake,
+            })),
+        })
+    }
+
+    pub fn get_id(&self) -> CoroutineId {
+        self.wake.id()
+    }
+
+    pub fn into_node(self: Box<Self>) -> *mut Node {
+        Box::into_raw(self) as *mut Node
+    }
+
+    /// # Safety
+    /// node必须来自[`Task::into_node`]，并且只能转换一次
+    pub unsafe fn from_node(node: *mut Node) -> Box<Self> {
+        Box::from_raw(node as *mut Task)
+    }
+
+    /// poll一次，完成时返回true
+    pub fn poll(&mut self, scheduler: *mut Scheduler) -> bool {
+        let previous =
+            CURRENT.with(|current| current.borrow_mut().replace((scheduler, self.wake.clone())));
+        let poll = self
+            .future
+            .as_mut()
+            .poll(&mut Context::from_waker(&self.waker));
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+        poll.is_ready()
+    }
+}
+
+impl Debug for Task {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Task").field("id", &self.get_id()).finish()
+    }
+}
@@ -0,0 +1,848 @@
+use crate::net::{blocking, impl_fd, wait};
+use crate::scheduler::Scheduler;
+use std::io::{Error, ErrorKind, IoSlice, IoSliceMut, Read, Write};
+use std::net::Shutdown;
+use std::os::raw::c_void;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::unix::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/*
+unix socket和管道，同net模块，fd总是非阻塞的，
+阻塞操作在协程中挂起当前协程，不在协程中时阻塞当前线程
+ */
+
+/// unix socket监听，同std::os::unix::net::UnixListener
+#[derive(Debug)]
+pub struct UnixListener {
+    inner: std::os::unix::net::UnixListener,
+    nonblocking: AtomicBool,
+}
+
+impl UnixListener {
+    pub fn bind<P: AsRef<Path>>(path: P) -> std::io::Result<UnixListener> {
+        UnixListener::from_std(std::os::unix::net::UnixListener::bind(path)?)
+    }
+
+    pub fn from_std(listener: std::os::unix::net::UnixListener) -> std::io::Result<UnixListener> {
+        listener.set_nonblocking(true)?;
+        Ok(UnixListener {
+            inner: listener,
+            nonblocking: AtomicBool::new(false),
+        })
+    }
+
+    pub fn accept(&self) -> std::io::Result<(UnixStream, SocketAddr)> {
+        let (stream, addr) = blocking(
+            self.as_raw_fd(),
+            libc::POLLIN,
+            self.nonblocking.load(Ordering::Acquire),
+            || Ok(None),
+            || self.inner.accept(),
+        )?;
+        Ok((UnixStream::from_std(stream)?, addr))
+    }
+
+    pub fn incoming(&self) -> Incoming<'_> {
+        Incoming { listener: self }
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn try_clone(&self) -> std::io::Result<UnixListener> {
+        Ok(UnixListener {
+            inner: self.inner.try_clone()?,
+            nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
+        })
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn take_error(&self) -> std::io::Result<Option<Error>> {
+        self.inner.take_error()
+    }
+}
+
+/// UnixListener::incoming返回的迭代器，永远不会返回None
+#[derive(Debug)]
+pub struct Incoming<'a> {
+    listener: &'a UnixListener,
+}
+
+impl Iterator for Incoming<'_> {
+    type Item = std::io::Result<UnixStream>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.listener.accept().map(|(stream, _)| stream))
+    }
+}
+
+/// unix socket连接，同std::os::unix::net::UnixStream，支持通过SCM_RIGHTS传递fd
+#[derive(Debug)]
+pub struct UnixStream {
+    inner: std::os::unix::net::UnixStream,
+    nonblocking: AtomicBool,
+}
+
+impl UnixStream {
+    pub fn connect<P: AsRef<Path>>(path: P) -> std::io::Result<UnixStream> {
+        let (addr, len) = sockaddr_un(path.as_ref())?;
+        let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_STREAM, 0) };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+        //先交给UnixStream管理，出错时自动关闭fd
+        let stream =
+            UnixStream::from_std(unsafe { std::os::unix::net::UnixStream::from_raw_fd(fd) })?;
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+        loop {
+            if unsafe { libc::connect(fd, &addr as *const _ as *const libc::sockaddr, len) } == 0 {
+                return Ok(stream);
+            }
+            let error = Error::last_os_error();
+            match error.raw_os_error() {
+                Some(libc::EINPROGRESS) => {
+                    wait(fd, libc::POLLOUT, None)?;
+                    return match stream.take_error()? {
+                        Some(error) => Err(error),
+                        None => Ok(stream),
+                    };
+                }
+                //linux上监听队列满时返回EAGAIN，稍后重试
+                Some(libc::EAGAIN) => {
+                    if !Scheduler::wait(Some(Duration::from_millis(1))) {
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                }
+                Some(libc::EINTR) => {}
+                _ => return Err(error),
+            }
+        }
+    }
+
+    pub fn pair() -> std::io::Result<(UnixStream, UnixStream)> {
+        let (first, second) = std::os::unix::net::UnixStream::pair()?;
+        Ok((UnixStream::from_std(first)?, UnixStream::from_std(second)?))
+    }
+
+    pub fn from_std(stream: std::os::unix::net::UnixStream) -> std::io::Result<UnixStream> {
+        stream.set_nonblocking(true)?;
+        Ok(UnixStream {
+            inner: stream,
+            nonblocking: AtomicBool::new(false),
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> std::io::Result<()> {
+        self.inner.shutdown(how)
+    }
+
+    pub fn try_clone(&self) -> std::io::Result<UnixStream> {
+        Ok(UnixStream {
+            inner: self.inner.try_clone()?,
+            nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
+        })
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+
+    pub fn read_timeout(&self) -> std::io::Result<Option<Duration>> {
+        self.inner.read_timeout()
+    }
+
+    pub fn write_timeout(&self) -> std::io::Result<Option<Duration>> {
+        self.inner.write_timeout()
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn take_error(&self) -> std::io::Result<Option<Error>> {
+        self.inner.take_error()
+    }
+
+    /// 发送数据的同时通过SCM_RIGHTS发送fds，返回发送的字节数
+    pub fn send_with_fds(&self, buf: &[u8], fds: &[RawFd]) -> std::io::Result<usize> {
+        self.send(|| send_with_fds(self.as_raw_fd(), buf, fds))
+    }
+
+    /// 接收数据和对方发送的fd，返回接收的字节数和fd数量，
+    /// 放不下的fd会被关闭
+    pub fn recv_with_fds(
+        &self,
+        buf: &mut [u8],
+        fds: &mut [RawFd],
+    ) -> std::io::Result<(usize, usize)> {
+        self.recv(|| recv_with_fds(self.as_raw_fd(), buf, fds))
+    }
+
+    fn recv<R>(&self, f: impl FnMut() -> std::io::Result<R>) -> std::io::Result<R> {
+        blocking(
+            self.as_raw_fd(),
+            libc::POLLIN,
+            self.nonblocking.load(Ordering::Acquire),
+            || self.inner.read_timeout(),
+            f,
+        )
+    }
+
+    fn send<R>(&self, f: impl FnMut() -> std::io::Result<R>) -> std::io::Result<R> {
+        blocking(
+            self.as_raw_fd(),
+            libc::POLLOUT,
+            self.nonblocking.load(Ordering::Acquire),
+            || self.inner.write_timeout(),
+            f,
+        )
+    }
+}
+
+impl Read for UnixStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        (&*self).read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        (&*self).read_vectored(bufs)
+    }
+}
+
+impl Read for &UnixStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.recv(|| (&self.inner).read(buf))
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        self.recv(|| (&self.inner).read_vectored(bufs))
+    }
+}
+
+impl Write for UnixStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        (&*self).write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Write for &UnixStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.send(|| (&self.inner).write(buf))
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        self.send(|| (&self.inner).write_vectored(bufs))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// unix数据报socket，同std::os::unix::net::UnixDatagram
+#[derive(Debug)]
+pub struct UnixDatagram {
+    inner: std::os::unix::net::UnixDatagram,
+    nonblocking: AtomicBool,
+}
+
+impl UnixDatagram {
+    pub fn bind<P: AsRef<Path>>(path: P) -> std::io::Result<UnixDatagram> {
+        UnixDatagram::from_std(std::os::unix::net::UnixDatagram::bind(path)?)
+    }
+
+    pub fn unbound() -> std::io::Result<UnixDatagram> {
+        UnixDatagram::from_std(std::os::unix::net::UnixDatagram::unbound()?)
+    }
+
+    pub fn pair() -> std::io::Result<(UnixDatagram, UnixDatagram)> {
+        let (first, second) = std::os::unix::net::UnixDatagram::pair()?;
+        Ok((
+            UnixDatagram::from_std(first)?,
+            UnixDatagram::from_std(second)?,
+        ))
+    }
+
+    pub fn from_std(socket: std::os::unix::net::UnixDatagram) -> std::io::Result<UnixDatagram> {
+        socket.set_nonblocking(true)?;
+        Ok(UnixDatagram {
+            inner: socket,
+            nonblocking: AtomicBool::new(false),
+        })
+    }
+
+    /// 数据报socket的connect只是设置默认地址，不会阻塞
+    pub fn connect<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        self.inner.connect(path)
+    }
+
+    pub fn send(&self, buf: &[u8]) -> std::io::Result<usize> {
+        self.send_with(|| self.inner.send(buf))
+    }
+
+    pub fn recv(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.recv_with(|| self.inner.recv(buf))
+    }
+
+    pub fn send_to<P: AsRef<Path>>(&self, buf: &[u8], path: P) -> std::io::Result<usize> {
+        let path = path.as_ref();
+        self.send_with(|| self.inner.send_to(buf, path))
+    }
+
+    pub fn recv_from(&self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
+        self.recv_with(|| self.inner.recv_from(buf))
+    }
+
+    /// 同UnixStream::send_with_fds
+    pub fn send_with_fds(&self, buf: &[u8], fds: &[RawFd]) -> std::io::Result<usize> {
+        self.send_with(|| send_with_fds(self.as_raw_fd(), buf, fds))
+    }
+
+    /// 同UnixStream::recv_with_fds
+    pub fn recv_with_fds(
+        &self,
+        buf: &mut [u8],
+        fds: &mut [RawFd],
+    ) -> std::io::Result<(usize, usize)> {
+        self.recv_with(|| recv_with_fds(self.as_raw_fd(), buf, fds))
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> std::io::Result<()> {
+        self.inner.shutdown(how)
+    }
+
+    pub fn try_clone(&self) -> std::io::Result<UnixDatagram> {
+        Ok(UnixDatagram {
+            inner: self.inner.try_clone()?,
+            nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
+        })
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+
+    pub fn read_timeout(&self) -> std::io::Result<Option<Duration>> {
+        self.inner.read_timeout()
+    }
+
+    pub fn write_timeout(&self) -> std::io::Result<Option<Duration>> {
+        self.inner.write_timeout()
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn take_error(&self) -> std::io::Result<Option<Error>> {
+        self.inner.take_error()
+    }
+
+    fn recv_with<R>(&self, f: impl FnMut() -> std::io::Result<R>) -> std::io::Result<R> {
+        blocking(
+            self.as_raw_fd(),
+            libc::POLLIN,
+            self.nonblocking.load(Ordering::Acquire),
+            || self.inner.read_timeout(),
+            f,
+        )
+    }
+
+    fn send_with<R>(&self, f: impl FnMut() -> std::io::Result<R>) -> std::io::Result<R> {
+        blocking(
+            self.as_raw_fd(),
+            libc::POLLOUT,
+            self.nonblocking.load(Ordering::Acquire),
+            || self.inner.write_timeout(),
+            f,
+        )
+    }
+}
+
+impl_fd!(
+    UnixListener => std::os::unix::net::UnixListener,
+    UnixStream => std::os::unix::net::UnixStream,
+    UnixDatagram => std::os::unix::net::UnixDatagram
+);
+
+/// 创建管道，返回读端和写端，两端都设置了FD_CLOEXEC
+pub fn pipe() -> std::io::Result<(PipeReader, PipeWriter)> {
+    let mut fds = [-1; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    let (reader, writer) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    for fd in fds {
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+    Ok((PipeReader::new(reader)?, PipeWriter::new(writer)?))
+}
+
+/// 管道读端，也可以由子进程的stdout、stderr转换而来
+#[derive(Debug)]
+pub struct PipeReader {
+    fd: OwnedFd,
+    nonblocking: AtomicBool,
+}
+
+/// 管道写端，也可以由子进程的stdin转换而来
+#[derive(Debug)]
+pub struct PipeWriter {
+    fd: OwnedFd,
+    nonblocking: AtomicBool,
+}
+
+macro_rules! impl_pipe {
+    ($($ty:ident),*) => {
+        $(
+            impl $ty {
+                /// fd会被设置为非阻塞，fd不能和别的进程共享，
+                /// 否则别的进程也会看到非阻塞的fd
+                pub fn new(fd: OwnedFd) -> std::io::Result<$ty> {
+                    set_nonblocking(fd.as_raw_fd())?;
+                    Ok($ty {
+                        fd,
+                        nonblocking: AtomicBool::new(false),
+                    })
+                }
+
+                pub fn try_clone(&self) -> std::io::Result<$ty> {
+                    Ok($ty {
+                        fd: self.fd.try_clone()?,
+                        nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
+                    })
+                }
+
+                pub fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+                    self.nonblocking.store(nonblocking, Ordering::Release);
+                    Ok(())
+                }
+            }
+
+            impl AsRawFd for $ty {
+                fn as_raw_fd(&self) -> RawFd {
+                    self.fd.as_raw_fd()
+                }
+            }
+
+            impl IntoRawFd for $ty {
+                fn into_raw_fd(self) -> RawFd {
+                    self.fd.into_raw_fd()
+                }
+            }
+
+            impl FromRawFd for $ty {
+                /// fd会被设置为非阻塞
+                unsafe fn from_raw_fd(fd: RawFd) -> Self {
+                    $ty::new(OwnedFd::from_raw_fd(fd)).expect("set nonblocking failed")
+                }
+            }
+
+            impl From<$ty> for OwnedFd {
+                fn from(pipe: $ty) -> OwnedFd {
+                    pipe.fd
+                }
+            }
+        )*
+    };
+}
+
+impl_pipe!(PipeReader, PipeWriter);
+
+impl PipeReader {
+    fn read_with<R>(&self, f: impl FnMut() -> std::io::Result<R>) -> std::io::Result<R> {
+        blocking(
+            self.as_raw_fd(),
+            libc::POLLIN,
+            self.nonblocking.load(Ordering::Acquire),
+            || Ok(None),
+            f,
+        )
+    }
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        (&*self).read(buf)
+    }
+}
+
+impl Read for &PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let fd = self.as_raw_fd();
+        self.read_with(|| {
+            let r = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut c_void, buf.len()) };
+            cvt(r).map(|n| n as usize)
+        })
+    }
+}
+
+impl TryFrom<std::process::ChildStdout> for PipeReader {
+    type Error = Error;
+
+    fn try_from(stdout: std::process::ChildStdout) -> std::io::Result<PipeReader> {
+        PipeReader::new(OwnedFd::from(stdout))
+    }
+}
+
+impl TryFrom<std::process::ChildStderr> for PipeReader {
+    type Error = Error;
+
+    fn try_from(stderr: std::process::ChildStderr) -> std::io::Result<PipeReader> {
+        PipeReader::new(OwnedFd::from(stderr))
+    }
+}
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Write for &PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let fd = self.as_raw_fd();
+        blocking(
+            fd,
+            libc::POLLOUT,
+            self.nonblocking.load(Ordering::Acquire),
+            || Ok(None),
+            || {
+                let r = unsafe { libc::write(fd, buf.as_ptr() as *const c_void, buf.len()) };
+                cvt(r).map(|n| n as usize)
+            },
+        )
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl TryFrom<std::process::ChildStdin> for PipeWriter {
+    type Error = Error;
+
+    fn try_from(stdin: std::process::ChildStdin) -> std::io::Result<PipeWriter> {
+        PipeWriter::new(OwnedFd::from(stdin))
+    }
+}
+
+fn cvt(r: libc::ssize_t) -> std::io::Result<libc::ssize_t> {
+    if r < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(r)
+}
+
+fn set_nonblocking(fd: RawFd) -> std::io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn sockaddr_un(path: &Path) -> std::io::Result<(libc::sockaddr_un, libc::socklen_t)> {
+    let mut addr: libc::sockaddr_un = unsafe { std::mem::zeroed() };
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    let bytes = path.as_os_str().as_bytes();
+    if bytes.contains(&0) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "paths must not contain interior null bytes",
+        ));
+    }
+    //留一个字节给\0
+    if bytes.len() >= addr.sun_path.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "path must be shorter than SUN_LEN",
+        ));
+    }
+    for (dst, src) in addr.sun_path.iter_mut().zip(bytes) {
+        *dst = *src as libc::c_char;
+    }
+    let len = std::mem::size_of::<libc::sockaddr_un>() - addr.sun_path.len() + bytes.len() + 1;
+    Ok((addr, len as libc::socklen_t))
+}
+
+//非阻塞地发送一次数据和fds
+fn send_with_fds(socket: RawFd, buf: &[u8], fds: &[RawFd]) -> std::io::Result<usize> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_ptr() as *mut c_void,
+        iov_len: buf.len(),
+    };
+    let size = std::mem::size_of_val(fds);
+    //u64保证cmsghdr对齐
+    let mut control = vec![0u64; (unsafe { libc::CMSG_SPACE(size as u32) } as usize).div_ceil(8)];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    if !fds.is_empty() {
+        msg.msg_control = control.as_mut_ptr() as *mut c_void;
+        msg.msg_controllen = unsafe { libc::CMSG_SPACE(size as u32) } as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(size as u32) as _;
+            std::ptr::copy_nonoverlapping(
+                fds.as_ptr(),
+                libc::CMSG_DATA(cmsg) as *mut RawFd,
+                fds.len(),
+            );
+        }
+    }
+    cvt(unsafe { libc::sendmsg(socket, &msg, 0) }).map(|n| n as usize)
+}
+
+//非阻塞地接收一次数据和fds
+fn recv_with_fds(
+    socket: RawFd,
+    buf: &mut [u8],
+    fds: &mut [RawFd],
+) -> std::io::Result<(usize, usize)> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut c_void,
+        iov_len: buf.len(),
+    };
+    let space = unsafe { libc::CMSG_SPACE(std::mem::size_of_val(fds) as u32) } as usize;
+    let mut control = vec![0u64; space.div_ceil(8)];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut c_void;
+    msg.msg_controllen = space as _;
+    #[cfg(target_os = "linux")]
+    let flags = libc::MSG_CMSG_CLOEXEC;
+    #[cfg(not(target_os = "linux"))]
+    let flags = 0;
+    let n = cvt(unsafe { libc::recvmsg(socket, &mut msg, flags) })? as usize;
+    let mut count = 0;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                let len = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize)
+                    / std::mem::size_of::<RawFd>();
+                for i in 0..len {
+                    let fd = std::ptr::read_unaligned(data.add(i));
+                    #[cfg(not(target_os = "linux"))]
+                    libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+                    if count < fds.len() {
+                        fds[count] = fd;
+                        count += 1;
+                    } else {
+                        libc::close(fd);
+                    }
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    Ok((n, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coroutine::Coroutine;
+    use crate::scheduler::Scheduler;
+    use crate::unix::{pipe, UnixDatagram, UnixListener, UnixStream};
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsRawFd;
+
+    #[test]
+    fn test_stream() {
+        let path = std::env::temp_dir().join(format!("open-coroutine-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let client_path = path.clone();
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<String>;
+        let scheduler = Scheduler::current();
+        //服务端和客户端在同一个线程上，阻塞操作只会挂起协程
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0u8; 4];
+                stream.read_exact(&mut buf).unwrap();
+                stream.write_all(&buf).unwrap();
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                let mut stream = UnixStream::connect(&client_path).unwrap();
+                stream.write_all(b"ping").unwrap();
+                let mut buf = String::new();
+                stream.read_to_string(&mut buf).unwrap();
+                unsafe { (*pointer).push(buf) };
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(vec!["ping"], result);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_fd_passing() {
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let (reader, mut writer) = pipe().unwrap();
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<u8>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                let mut buf = [0u8; 8];
+                let mut fds = [-1; 2];
+                let (n, count) = receiver.recv_with_fds(&mut buf, &mut fds).unwrap();
+                assert_eq!(b"fd", &buf[..n]);
+                assert_eq!(1, count);
+                //从收到的fd读取管道的数据
+                let mut reader = unsafe {
+                    <crate::unix::PipeReader as std::os::unix::io::FromRawFd>::from_raw_fd(fds[0])
+                };
+                let mut data = [0u8; 5];
+                reader.read_exact(&mut data).unwrap();
+                unsafe { (*pointer).extend_from_slice(&data) };
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                assert_eq!(
+                    2,
+                    sender.send_with_fds(b"fd", &[reader.as_raw_fd()]).unwrap()
+                );
+                drop(reader);
+                writer.write_all(b"hello").unwrap();
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(b"hello".to_vec(), result);
+    }
+
+    #[test]
+    fn test_datagram() {
+        let (first, second) = UnixDatagram::pair().unwrap();
+        let mut result = Vec::new();
+        let pointer = &mut result as *mut Vec<&str>;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                let mut buf = [0u8; 8];
+                let n = first.recv(&mut buf).unwrap();
+                assert_eq!(b"ping", &buf[..n]);
+                unsafe { (*pointer).push("recv") };
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                unsafe { (*pointer).push("send") };
+                second.send(b"ping").unwrap();
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert_eq!(vec!["send", "recv"], result);
+    }
+
+    #[test]
+    fn test_child_pipe() {
+        let mut child = std::process::Command::new("cat")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = crate::unix::PipeWriter::try_from(child.stdin.take().unwrap()).unwrap();
+        let mut stdout = crate::unix::PipeReader::try_from(child.stdout.take().unwrap()).unwrap();
+        let mut result = String::new();
+        let pointer = &mut result as *mut String;
+        let scheduler = Scheduler::current();
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                stdout.read_to_string(unsafe { &mut *pointer }).unwrap();
+                param
+            },
+            None,
+        ));
+        scheduler.execute(Coroutine::new(
+            64 * 1024,
+            move |param| {
+                stdin.write_all(b"child").unwrap();
+                drop(stdin);
+                param
+            },
+            None,
+        ));
+        assert_eq!(2, scheduler.schedule().len());
+        assert!(child.wait().unwrap().success());
+        assert_eq!("child", result);
+    }
+}
@@ -0,0 +1,69 @@
+use crate::reactor::{WakeNode, Waker};
+use crate::scheduler::Scheduler;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::Thread;
+use std::time::Instant;
+
+/// 等待者，在协程中等待时挂起协程，否则挂起线程，可以在任意线程唤醒
+#[derive(Debug)]
+pub(crate) struct Waiter {
+    kind: Kind,
+    notified: AtomicBool,
+}
+
+#[derive(Debug)]
+enum Kind {
+    Coroutine(Arc<Waker>, Arc<WakeNode>),
+    Thread(Thread),
+}
+
+impl Waiter {
+    /// 当前协程或者线程对应的等待者
+    pub fn current() -> Arc<Waiter> {
+        let kind = match Scheduler::waker() {
+            Some((waker, node)) => Kind::Coroutine(waker, node),
+            None => Kind::Thread(std::thread::current()),
+        };
+        Arc::new(Waiter {
+            kind,
+            notified: AtomicBool::new(false),
+        })
+    }
+
+    pub fn is_notified(&self) -> bool {
+        self.notified.load(Ordering::Acquire)
+    }
+
+    pub fn wake(&self) {
+        self.notified.store(true, Ordering::Release);
+        match &self.kind {
+            Kind::Coroutine(waker, node) => waker.wake(node),
+            Kind::Thread(thread) => thread.unpark(),
+        }
+    }
+
+    /// 等待被唤醒，deadline为None时一直等待，超时返回false
+    pub fn wait(&self, deadline: Option<Instant>) -> bool {
+        while !self.is_notified() {
+            let left = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(left) if !left.is_zero() => Some(left),
+                    _ => return self.is_notified(),
+                },
+                None => None,
+            };
+            match &self.kind {
+                //可能被提前唤醒，重新检查
+                Kind::Coroutine(..) => {
+                    Scheduler::wait(left);
+                }
+                Kind::Thread(_) => match left {
+                    Some(left) => std::thread::park_timeout(left),
+                    None => std::thread::park(),
+                },
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,183 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use object_list::ObjectList;
+use ring_buffer::mpmc::MpmcRingBuffer;
+use ring_buffer::spsc;
+use ring_buffer::RingBuffer;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const COUNT: usize = 10_000;
+
+//加锁的ObjectList
+#[derive(Default)]
+struct SharedList(Mutex<ObjectList<usize>>);
+
+impl SharedList {
+    fn push(&self, value: usize) {
+        self.0.lock().unwrap().push_back(value);
+    }
+
+    fn pop(&self) -> Option<usize> {
+        self.0.lock().unwrap().pop_front()
+    }
+}
+
+//调度器就绪队列的用法：不断从头部取出再放回尾部
+fn ready_queue(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ready_queue");
+    for size in [16, 1024] {
+        group.bench_with_input(BenchmarkId::new("ObjectList", size), &size, |b, &size| {
+            let mut list = ObjectList::new();
+            for i in 0..size {
+                list.push_back(i);
+            }
+            b.iter(|| {
+                for _ in 0..COUNT {
+                    let value = list.pop_front().unwrap();
+                    list.push_back(black_box(value));
+                }
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("RingBuffer", size), &size, |b, &size| {
+            let mut buffer = RingBuffer::growable(size);
+            for i in 0..size {
+                buffer.push_back(i).unwrap();
+            }
+            b.iter(|| {
+                for _ in 0..COUNT {
+                    let value = buffer.pop_front().unwrap();
+                    buffer.push_back(black_box(value)).unwrap();
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+//一个线程生产一个线程消费，对比加锁的ObjectList
+fn spsc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spsc");
+    group.bench_function("Mutex<ObjectList>", |b| {
+        b.iter(|| {
+            let list = Arc::new(SharedList::default());
+            let producer = list.clone();
+            let writer = thread::spawn(move || {
+                for i in 0..COUNT {
+                    producer.push(i);
+                }
+            });
+            let mut received = 0;
+            while received < COUNT {
+                if let Some(value) = list.pop() {
+                    black_box(value);
+                    received += 1;
+                }
+            }
+            writer.join().unwrap();
+        });
+    });
+    group.bench_function("spsc", |b| {
+        b.iter(|| {
+            let (mut producer, mut consumer) = spsc::ring_buffer(1024);
+            let writer = thread::spawn(move || {
+                let mut values = Vec::with_capacity(64);
+                let mut next = 0;
+                while next < COUNT {
+                    values.extend(next..COUNT.min(next + 64 - values.len()));
+                    next += values.len();
+                    while !values.is_empty() {
+                        if producer.push_batch(&mut values) == 0 {
+                            thread::yield_now();
+                        }
+                    }
+                }
+            });
+            let mut values = Vec::with_capacity(64);
+            let mut received = 0;
+            while received < COUNT {
+                match consumer.pop_batch(&mut values, 64) {
+                    0 => thread::yield_now(),
+                    count => received += count,
+                }
+                values.drain(..).for_each(|value| {
+                    black_box(value);
+                });
+            }
+            writer.join().unwrap();
+        });
+    });
+    group.finish();
+}
+
+//两个线程生产两个线程消费，对比加锁的ObjectList
+fn mpmc(c: &mut Criterion) {
+    const THREADS: usize = 2;
+    let mut group = c.benchmark_group("mpmc");
+    group.bench_function("Mutex<ObjectList>", |b| {
+        b.iter(|| {
+            let list = Arc::new(SharedList::default());
+            let threads: Vec<_> = (0..THREADS)
+                .flat_map(|_| {
+                    let producer = list.clone();
+                    let consumer = list.clone();
+                    [
+                        thread::spawn(move || {
+                            for i in 0..COUNT {
+                                producer.push(i);
+                            }
+                        }),
+                        thread::spawn(move || {
+                            let mut received = 0;
+                            while received < COUNT {
+                                if let Some(value) = consumer.pop() {
+                                    black_box(value);
+                                    received += 1;
+                                }
+                            }
+                        }),
+                    ]
+                })
+                .collect();
+            threads.into_iter().for_each(|t| t.join().unwrap());
+        });
+    });
+    group.bench_function("MpmcRingBuffer", |b| {
+        b.iter(|| {
+            let buffer = Arc::new(MpmcRingBuffer::new(1024));
+            let threads: Vec<_> = (0..THREADS)
+                .flat_map(|_| {
+                    let producer = buffer.clone();
+                    let consumer = buffer.clone();
+                    [
+                        thread::spawn(move || {
+                            for i in 0..COUNT {
+                                let mut value = i;
+                                while let Err(v) = producer.push(value) {
+                                    value = v;
+                                    thread::yield_now();
+                                }
+                            }
+                        }),
+                        thread::spawn(move || {
+                            let mut received = 0;
+                            while received < COUNT {
+                                match consumer.pop() {
+                                    Some(value) => {
+                                        black_box(value);
+                                        received += 1;
+                                    }
+                                    None => thread::yield_now(),
+                                }
+                            }
+                        }),
+                    ]
+                })
+                .collect();
+            threads.into_iter().for_each(|t| t.join().unwrap());
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, ready_queue, spsc, mpmc);
+criterion_main!(benches);
@@ -0,0 +1,323 @@
+use std::fmt::{Debug, Formatter};
+use std::mem::MaybeUninit;
+use std::ops::{Deref, DerefMut};
+
+pub mod mpmc;
+pub mod spsc;
+
+/*
+参考disruptor、gnet实现的环形缓冲区，容量都是2的幂，下标通过位与计算；
+RingBuffer单线程使用，可以选择写满时自动扩容，
+spsc和mpmc可以跨线程使用，容量固定，读写位置按缓存行对齐
+ */
+
+/// 按缓存行对齐，避免不同线程频繁修改的字段伪共享
+#[cfg_attr(any(target_arch = "x86_64", target_arch = "aarch64"), repr(align(128)))]
+#[cfg_attr(
+    not(any(target_arch = "x86_64", target_arch = "aarch64")),
+    repr(align(64))
+)]
+#[derive(Debug, Default)]
+pub struct CachePadded<T> {
+    value: T,
+}
+
+impl<T> CachePadded<T> {
+    pub fn new(value: T) -> Self {
+        CachePadded { value }
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for CachePadded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+//不小于capacity的2的幂，至少为1
+pub(crate) fn capacity_of(capacity: usize) -> usize {
+    capacity.max(1).next_power_of_two()
+}
+
+pub struct RingBuffer<T> {
+    buffer: Box<[MaybeUninit<T>]>,
+    //head和tail一直递增，与capacity-1相与得到下标
+    head: usize,
+    tail: usize,
+    growable: bool,
+}
+
+fn allocate<T>(capacity: usize) -> Box<[MaybeUninit<T>]> {
+    (0..capacity).map(|_| MaybeUninit::uninit()).collect()
+}
+
+impl<T> RingBuffer<T> {
+    /// 容量固定的环形缓冲区，容量向上取整为2的幂，写满后push返回Err
+    pub fn new(capacity: usize) -> Self {
+        RingBuffer {
+            buffer: allocate(capacity_of(capacity)),
+            head: 0,
+            tail: 0,
+            growable: false,
+        }
+    }
+
+    /// 写满时容量自动翻倍的环形缓冲区
+    pub fn growable(capacity: usize) -> Self {
+        let mut buffer = Self::new(capacity);
+        buffer.growable = true;
+        buffer
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tail.wrapping_sub(self.head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head == self.tail
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    fn mask(&self) -> usize {
+        self.capacity() - 1
+    }
+
+    //写满时扩容，返回是否还有空位
+    fn reserve(&mut self) -> bool {
+        if !self.is_full() {
+            return true;
+        }
+        if !self.growable {
+            return false;
+        }
+        let len = self.len();
+        let mut buffer = allocate(self.capacity() * 2);
+        for (i, slot) in buffer.iter_mut().take(len).enumerate() {
+            let index = self.head.wrapping_add(i) & self.mask();
+            *slot = MaybeUninit::new(unsafe { self.buffer[index].assume_init_read() });
+        }
+        self.buffer = buffer;
+        self.head = 0;
+        self.tail = len;
+        true
+    }
+
+    pub fn push_back(&mut self, value: T) -> Result<(), T> {
+        if !self.reserve() {
+            return Err(value);
+        }
+        let index = self.tail & self.mask();
+        self.buffer[index] = MaybeUninit::new(value);
+        self.tail = self.tail.wrapping_add(1);
+        Ok(())
+    }
+
+    pub fn push_front(&mut self, value: T) -> Result<(), T> {
+        if !self.reserve() {
+            return Err(value);
+        }
+        self.head = self.head.wrapping_sub(1);
+        let index = self.head & self.mask();
+        self.buffer[index] = MaybeUninit::new(value);
+        Ok(())
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let index = self.head & self.mask();
+        self.head = self.head.wrapping_add(1);
+        Some(unsafe { self.buffer[index].assume_init_read() })
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.tail = self.tail.wrapping_sub(1);
+        let index = self.tail & self.mask();
+        Some(unsafe { self.buffer[index].assume_init_read() })
+    }
+
+    /// 批量写入values，返回写入的数量，写入的元素从values头部移除
+    pub fn push_batch(&mut self, values: &mut Vec<T>) -> usize {
+        let count = if self.growable {
+            values.len()
+        } else {
+            values.len().min(self.capacity() - self.len())
+        };
+        for value in values.drain(..count) {
+            if self.push_back(value).is_err() {
+                unreachable!("ring buffer has enough space");
+            }
+        }
+        count
+    }
+
+    /// 批量取出最多max个元素追加到values，返回取出的数量
+    pub fn pop_batch(&mut self, values: &mut Vec<T>, max: usize) -> usize {
+        let count = self.len().min(max);
+        values.reserve(count);
+        for _ in 0..count {
+            values.extend(self.pop_front());
+        }
+        count
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len() {
+            return None;
+        }
+        let index = self.head.wrapping_add(index) & self.mask();
+        Some(unsafe { self.buffer[index].assume_init_ref() })
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len() {
+            return None;
+        }
+        let index = self.head.wrapping_add(index) & self.mask();
+        Some(unsafe { self.buffer[index].assume_init_mut() })
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.get(self.len().wrapping_sub(1))
+    }
+
+    /// 删除第index个元素，后面的元素依次前移
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        if index >= self.len() {
+            return None;
+        }
+        let (head, mask, len) = (self.head, self.mask(), self.len());
+        let position = |i: usize| head.wrapping_add(i) & mask;
+        let value = unsafe { self.buffer[position(index)].assume_init_read() };
+        for i in index..len - 1 {
+            let (to, from) = (position(i), position(i + 1));
+            self.buffer.swap(to, from);
+        }
+        self.tail = self.tail.wrapping_sub(1);
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        (0..self.len()).filter_map(|i| self.get(i))
+    }
+
+    pub fn clear(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+}
+
+impl<T> Default for RingBuffer<T> {
+    fn default() -> Self {
+        Self::growable(16)
+    }
+}
+
+impl<T> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T: Debug> Debug for RingBuffer<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CachePadded, RingBuffer};
+    use std::rc::Rc;
+
+    #[test]
+    fn test_fixed() {
+        let mut buffer = RingBuffer::new(3);
+        assert_eq!(4, buffer.capacity());
+        assert!(buffer.is_empty());
+        for i in 0..4 {
+            buffer.push_back(i).unwrap();
+        }
+        assert!(buffer.is_full());
+        assert_eq!(Err(4), buffer.push_back(4));
+        assert_eq!(Err(-1), buffer.push_front(-1));
+        assert_eq!(Some(0), buffer.pop_front());
+        buffer.push_front(-1).unwrap();
+        assert_eq!(Some(&-1), buffer.front());
+        assert_eq!(Some(&3), buffer.back());
+        assert_eq!(Some(3), buffer.pop_back());
+        assert_eq!(vec![-1, 1, 2], buffer.iter().copied().collect::<Vec<_>>());
+        assert_eq!(Some(1), buffer.remove(1));
+        assert_eq!(None, buffer.remove(2));
+        assert_eq!(vec![-1, 2], buffer.iter().copied().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_growable() {
+        let mut buffer = RingBuffer::growable(2);
+        //先让head绕一圈，扩容时要保持顺序
+        buffer.push_back(1).unwrap();
+        buffer.push_front(0).unwrap();
+        for i in 2..100 {
+            buffer.push_back(i).unwrap();
+        }
+        assert_eq!(128, buffer.capacity());
+        assert_eq!(100, buffer.len());
+        assert!((0..100).eq(buffer.iter().copied()));
+    }
+
+    #[test]
+    fn test_batch() {
+        let mut buffer = RingBuffer::new(4);
+        let mut values: Vec<i32> = (0..6).collect();
+        assert_eq!(4, buffer.push_batch(&mut values));
+        assert_eq!(vec![4, 5], values);
+        let mut popped = Vec::new();
+        assert_eq!(3, buffer.pop_batch(&mut popped, 3));
+        assert_eq!(2, buffer.push_batch(&mut values));
+        assert_eq!(3, buffer.pop_batch(&mut popped, 10));
+        assert_eq!((0..6).collect::<Vec<_>>(), popped);
+        assert!(values.is_empty());
+    }
+
+    #[test]
+    fn test_drop() {
+        let value = Rc::new(());
+        let mut buffer = RingBuffer::growable(1);
+        for _ in 0..10 {
+            buffer.push_back(value.clone()).unwrap();
+        }
+        buffer.remove(3);
+        assert_eq!(10, Rc::strong_count(&value));
+        drop(buffer);
+        assert_eq!(1, Rc::strong_count(&value));
+    }
+
+    #[test]
+    fn test_cache_padded() {
+        assert!(std::mem::align_of::<CachePadded<u8>>() >= 64);
+        assert_eq!(1, *CachePadded::new(1));
+    }
+}
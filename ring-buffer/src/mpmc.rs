@@ -0,0 +1,236 @@
+use crate::{capacity_of, CachePadded};
+use std::cell::UnsafeCell;
+use std::fmt::{Debug, Formatter};
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/*
+多生产者多消费者，每个槽位带一个序号：
+序号等于位置时槽位空闲，可以写入；序号等于位置+1时已经写入，可以读取；
+读取后序号设为位置+capacity，留给下一圈的写入。
+生产者和消费者分别CAS tail和head抢占位置，批量操作一次抢占多个连续的位置
+ */
+
+struct Slot<T> {
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+pub struct MpmcRingBuffer<T> {
+    buffer: Box<[Slot<T>]>,
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+}
+
+//槽位只有抢占到对应位置的线程才会访问
+unsafe impl<T: Send> Send for MpmcRingBuffer<T> {}
+
+unsafe impl<T: Send> Sync for MpmcRingBuffer<T> {}
+
+impl<T> MpmcRingBuffer<T> {
+    /// 容量向上取整为2的幂
+    pub fn new(capacity: usize) -> Self {
+        MpmcRingBuffer {
+            buffer: (0..capacity_of(capacity))
+                .map(|i| Slot {
+                    sequence: AtomicUsize::new(i),
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect(),
+            head: CachePadded::new(AtomicUsize::new(0)),
+            tail: CachePadded::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// 并发修改时只是一个近似值
+    pub fn len(&self) -> usize {
+        //先读head，读到的tail不会比head小
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head).min(self.capacity())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn slot(&self, position: usize) -> &Slot<T> {
+        &self.buffer[position & (self.capacity() - 1)]
+    }
+
+    //从cursor开始抢占最多max个状态为expect(位置)的连续槽位，返回抢占到的起始位置和数量
+    fn claim(
+        &self,
+        cursor: &AtomicUsize,
+        max: usize,
+        expect: impl Fn(usize) -> usize,
+    ) -> (usize, usize) {
+        let max = max.min(self.capacity());
+        let mut position = cursor.load(Ordering::Relaxed);
+        loop {
+            let mut count = 0;
+            while count < max {
+                let current = position.wrapping_add(count);
+                if self.slot(current).sequence.load(Ordering::Acquire) != expect(current) {
+                    break;
+                }
+                count += 1;
+            }
+            if count == 0 {
+                let sequence = self.slot(position).sequence.load(Ordering::Acquire);
+                //序号落后于位置，说明满了(写)或者空了(读)
+                if (sequence.wrapping_sub(expect(position)) as isize) < 0 || max == 0 {
+                    return (position, 0);
+                }
+                //位置已经被其他线程抢走
+                position = cursor.load(Ordering::Relaxed);
+                continue;
+            }
+            match cursor.compare_exchange_weak(
+                position,
+                position.wrapping_add(count),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return (position, count),
+                Err(current) => position = current,
+            }
+        }
+    }
+
+    /// 写入value，满了返回Err
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let (position, count) = self.claim(&self.tail, 1, |position| position);
+        if count == 0 {
+            return Err(value);
+        }
+        let slot = self.slot(position);
+        unsafe { (*slot.value.get()).write(value) };
+        slot.sequence
+            .store(position.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// 批量写入values，返回写入的数量，写入的元素从values头部移除
+    pub fn push_batch(&self, values: &mut Vec<T>) -> usize {
+        let (position, count) = self.claim(&self.tail, values.len(), |position| position);
+        for (i, value) in values.drain(..count).enumerate() {
+            let position = position.wrapping_add(i);
+            let slot = self.slot(position);
+            unsafe { (*slot.value.get()).write(value) };
+            slot.sequence
+                .store(position.wrapping_add(1), Ordering::Release);
+        }
+        count
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let (position, count) = self.claim(&self.head, 1, |position| position.wrapping_add(1));
+        if count == 0 {
+            return None;
+        }
+        Some(self.take(position))
+    }
+
+    /// 批量取出最多max个元素追加到values，返回取出的数量
+    pub fn pop_batch(&self, values: &mut Vec<T>, max: usize) -> usize {
+        let (position, count) = self.claim(&self.head, max, |position| position.wrapping_add(1));
+        values.reserve(count);
+        for i in 0..count {
+            values.push(self.take(position.wrapping_add(i)));
+        }
+        count
+    }
+
+    fn take(&self, position: usize) -> T {
+        let slot = self.slot(position);
+        let value = unsafe { (*slot.value.get()).assume_init_read() };
+        slot.sequence
+            .store(position.wrapping_add(self.capacity()), Ordering::Release);
+        value
+    }
+}
+
+impl<T> Drop for MpmcRingBuffer<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+impl<T> Debug for MpmcRingBuffer<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MpmcRingBuffer")
+            .field("capacity", &self.capacity())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mpmc::MpmcRingBuffer;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_mpmc() {
+        let buffer = MpmcRingBuffer::new(4);
+        assert!(buffer.is_empty());
+        assert_eq!(None, buffer.pop());
+        let mut values: Vec<usize> = (0..6).collect();
+        assert_eq!(4, buffer.push_batch(&mut values));
+        assert_eq!(Err(4), buffer.push(4));
+        assert_eq!(Some(0), buffer.pop());
+        assert_eq!(1, buffer.push_batch(&mut values));
+        let mut popped = Vec::new();
+        assert_eq!(4, buffer.pop_batch(&mut popped, 10));
+        assert_eq!(vec![1, 2, 3, 4], popped);
+        assert_eq!(0, buffer.pop_batch(&mut popped, 10));
+        //绕过一圈之后仍然正确
+        assert_eq!(1, buffer.push_batch(&mut values));
+        assert_eq!(Some(5), buffer.pop());
+    }
+
+    #[test]
+    fn test_threads() {
+        const THREADS: usize = 4;
+        const COUNT: usize = 20_000;
+        let buffer = Arc::new(MpmcRingBuffer::new(128));
+        let received = Arc::new(AtomicUsize::new(0));
+        let sum = Arc::new(AtomicUsize::new(0));
+        let mut threads = Vec::new();
+        for t in 0..THREADS {
+            let producer = buffer.clone();
+            threads.push(std::thread::spawn(move || {
+                let mut values: Vec<usize> = (t * COUNT..(t + 1) * COUNT).collect();
+                while !values.is_empty() {
+                    if producer.push_batch(&mut values) == 0 {
+                        std::thread::yield_now();
+                    }
+                }
+            }));
+            let (consumer, received, sum) = (buffer.clone(), received.clone(), sum.clone());
+            threads.push(std::thread::spawn(move || {
+                let mut values = Vec::new();
+                while received.load(Ordering::Relaxed) < THREADS * COUNT {
+                    if consumer.pop_batch(&mut values, 8) == 0 {
+                        std::thread::yield_now();
+                    }
+                    received.fetch_add(values.len(), Ordering::Relaxed);
+                    sum.fetch_add(values.drain(..).sum(), Ordering::Relaxed);
+                }
+            }));
+        }
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let total = THREADS * COUNT;
+        assert_eq!(total, received.load(Ordering::Relaxed));
+        assert_eq!(total * (total - 1) / 2, sum.load(Ordering::Relaxed));
+        assert!(buffer.is_empty());
+    }
+}
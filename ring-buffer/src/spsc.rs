@@ -0,0 +1,248 @@
+use crate::{capacity_of, CachePadded};
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/*
+单生产者单消费者，生产者只写tail，消费者只写head，
+双方各自缓存对方的位置，只有缓存的位置显示满/空时才重新读取
+ */
+
+/// 创建容量为capacity(向上取整为2的幂)的环形缓冲区，返回生产者和消费者
+pub fn ring_buffer<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    let inner = Arc::new(Inner {
+        buffer: (0..capacity_of(capacity))
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect(),
+        head: CachePadded::new(AtomicUsize::new(0)),
+        tail: CachePadded::new(AtomicUsize::new(0)),
+    });
+    (
+        Producer {
+            inner: inner.clone(),
+            head: 0,
+            tail: 0,
+        },
+        Consumer {
+            inner,
+            head: 0,
+            tail: 0,
+        },
+    )
+}
+
+#[derive(Debug)]
+struct Inner<T> {
+    buffer: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    //消费者的位置
+    head: CachePadded<AtomicUsize>,
+    //生产者的位置
+    tail: CachePadded<AtomicUsize>,
+}
+
+//同一个槽位不会同时被生产者和消费者访问
+unsafe impl<T: Send> Send for Inner<T> {}
+
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> Inner<T> {
+    fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn slot(&self, position: usize) -> *mut MaybeUninit<T> {
+        self.buffer[position & (self.capacity() - 1)].get()
+    }
+
+    fn len(&self) -> usize {
+        //先读head，读到的tail不会比head小
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        let (head, tail) = (*self.head.get_mut(), *self.tail.get_mut());
+        let mut position = head;
+        while position != tail {
+            unsafe { (*self.slot(position)).assume_init_drop() };
+            position = position.wrapping_add(1);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Producer<T> {
+    inner: Arc<Inner<T>>,
+    //缓存的消费者位置
+    head: usize,
+    tail: usize,
+}
+
+impl<T> Producer<T> {
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    //可以写入的数量，不够need时重新读取消费者位置
+    fn available(&mut self, need: usize) -> usize {
+        let mut available = self.capacity() - self.tail.wrapping_sub(self.head);
+        if available < need {
+            self.head = self.inner.head.load(Ordering::Acquire);
+            available = self.capacity() - self.tail.wrapping_sub(self.head);
+        }
+        available
+    }
+
+    /// 写入value，满了返回Err
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.available(1) == 0 {
+            return Err(value);
+        }
+        unsafe { (*self.inner.slot(self.tail)).write(value) };
+        self.tail = self.tail.wrapping_add(1);
+        self.inner.tail.store(self.tail, Ordering::Release);
+        Ok(())
+    }
+
+    /// 批量写入values，全部写完后才对消费者可见，返回写入的数量，
+    /// 写入的元素从values头部移除
+    pub fn push_batch(&mut self, values: &mut Vec<T>) -> usize {
+        let count = self.available(values.len()).min(values.len());
+        for value in values.drain(..count) {
+            unsafe { (*self.inner.slot(self.tail)).write(value) };
+            self.tail = self.tail.wrapping_add(1);
+        }
+        self.inner.tail.store(self.tail, Ordering::Release);
+        count
+    }
+}
+
+#[derive(Debug)]
+pub struct Consumer<T> {
+    inner: Arc<Inner<T>>,
+    head: usize,
+    //缓存的生产者位置
+    tail: usize,
+}
+
+impl<T> Consumer<T> {
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    //可以读取的数量，不够need时重新读取生产者位置
+    fn available(&mut self, need: usize) -> usize {
+        let mut available = self.tail.wrapping_sub(self.head);
+        if available < need {
+            self.tail = self.inner.tail.load(Ordering::Acquire);
+            available = self.tail.wrapping_sub(self.head);
+        }
+        available
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.available(1) == 0 {
+            return None;
+        }
+        let value = unsafe { (*self.inner.slot(self.head)).assume_init_read() };
+        self.head = self.head.wrapping_add(1);
+        self.inner.head.store(self.head, Ordering::Release);
+        Some(value)
+    }
+
+    /// 批量取出最多max个元素追加到values，全部取完后才释放槽位，返回取出的数量
+    pub fn pop_batch(&mut self, values: &mut Vec<T>, max: usize) -> usize {
+        let count = self.available(max).min(max);
+        values.reserve(count);
+        for _ in 0..count {
+            values.push(unsafe { (*self.inner.slot(self.head)).assume_init_read() });
+            self.head = self.head.wrapping_add(1);
+        }
+        self.inner.head.store(self.head, Ordering::Release);
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spsc::ring_buffer;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_spsc() {
+        let (mut producer, mut consumer) = ring_buffer(6);
+        assert_eq!(8, producer.capacity());
+        let mut values: Vec<usize> = (0..10).collect();
+        assert_eq!(8, producer.push_batch(&mut values));
+        assert_eq!(Err(8), producer.push(8));
+        assert_eq!(Some(0), consumer.pop());
+        let mut popped = Vec::new();
+        assert_eq!(3, consumer.pop_batch(&mut popped, 3));
+        assert_eq!(vec![1, 2, 3], popped);
+        assert_eq!(4, consumer.len());
+        assert_eq!(2, producer.push_batch(&mut values));
+        assert_eq!(6, producer.len());
+    }
+
+    #[test]
+    fn test_threads() {
+        const COUNT: usize = 100_000;
+        let (mut producer, mut consumer) = ring_buffer(64);
+        let writer = std::thread::spawn(move || {
+            let mut next = 0;
+            while next < COUNT {
+                let mut values: Vec<usize> = (next..COUNT.min(next + 16)).collect();
+                match producer.push_batch(&mut values) {
+                    0 => std::thread::yield_now(),
+                    count => next += count,
+                }
+            }
+        });
+        let mut expected = 0;
+        let mut values = Vec::new();
+        while expected < COUNT {
+            if consumer.pop_batch(&mut values, 32) == 0 {
+                std::thread::yield_now();
+            }
+            for value in values.drain(..) {
+                assert_eq!(expected, value);
+                expected += 1;
+            }
+        }
+        writer.join().unwrap();
+        assert!(consumer.pop().is_none());
+    }
+
+    #[test]
+    fn test_drop() {
+        let value = Arc::new(());
+        let (mut producer, consumer) = ring_buffer(4);
+        for _ in 0..3 {
+            producer.push(value.clone()).unwrap();
+        }
+        drop(producer);
+        assert_eq!(4, Arc::strong_count(&value));
+        drop(consumer);
+        assert_eq!(1, Arc::strong_count(&value));
+    }
+}
@@ -1,6 +1,5 @@
 use object_list::ObjectList;
 use std::collections::VecDeque;
-use std::os::raw::c_void;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 const NANOS_PER_SEC: u64 = 1_000_000_000;
@@ -23,16 +22,16 @@ pub fn dur_to_ns(dur: Duration) -> u64 {
 }
 
 pub fn get_timeout_time(dur: Duration) -> u64 {
-    now() + dur_to_ns(dur)
+    now().saturating_add(dur_to_ns(dur))
 }
 
 #[derive(Debug, PartialEq, Eq)]
-pub struct TimerEntry {
+pub struct TimerEntry<T> {
     time: u64,
-    dequeue: ObjectList,
+    dequeue: ObjectList<T>,
 }
 
-impl TimerEntry {
+impl<T> TimerEntry<T> {
     pub fn new(time: u64) -> Self {
         TimerEntry {
             time,
@@ -52,26 +51,22 @@ impl TimerEntry {
         self.time
     }
 
-    pub fn pop_front<T>(&mut self) -> Option<T> {
+    pub fn pop_front(&mut self) -> Option<T> {
         self.dequeue.pop_front()
     }
 
-    pub fn pop_front_raw(&mut self) -> Option<*mut c_void> {
-        self.dequeue.pop_front_raw()
-    }
-
-    pub fn push_back<T>(&mut self, t: T) {
+    pub fn push_back(&mut self, t: T) {
         self.dequeue.push_back(t)
     }
 }
 
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq)]
-pub struct TimerList {
-    dequeue: VecDeque<TimerEntry>,
+pub struct TimerList<T> {
+    dequeue: VecDeque<TimerEntry<T>>,
 }
 
-impl TimerList {
+impl<T> TimerList<T> {
     pub fn new() -> Self {
         TimerList {
             dequeue: VecDeque::new(),
@@ -82,16 +77,11 @@ impl TimerList {
         self.dequeue.len()
     }
 
-    pub fn insert<T>(&mut self, time: u64, t: T) {
-        let index = self
-            .dequeue
-            .binary_search_by(|x| x.time.cmp(&time))
-            .unwrap_or_else(|x| x);
-        match self.dequeue.get_mut(index) {
-            Some(entry) => {
-                entry.push_back(t);
-            }
-            None => {
+    pub fn insert(&mut self, time: u64, t: T) {
+        match self.dequeue.binary_search_by(|x| x.time.cmp(&time)) {
+            Ok(index) => self.dequeue[index].push_back(t),
+            //没有相同时间的entry，在index处新建，不能放到后面时间更晚的entry里
+            Err(index) => {
                 let mut entry = TimerEntry::new(time);
                 entry.push_back(t);
                 self.dequeue.insert(index, entry);
@@ -99,11 +89,11 @@ impl TimerList {
         }
     }
 
-    pub fn front(&self) -> Option<&TimerEntry> {
+    pub fn front(&self) -> Option<&TimerEntry<T>> {
         self.dequeue.front()
     }
 
-    pub fn pop_front(&mut self) -> Option<TimerEntry> {
+    pub fn pop_front(&mut self) -> Option<TimerEntry<T>> {
         self.dequeue.pop_front()
     }
 
@@ -112,7 +102,7 @@ impl TimerList {
     }
 }
 
-impl Default for TimerList {
+impl<T> Default for TimerList<T> {
     fn default() -> Self {
         Self::new()
     }
@@ -120,13 +110,19 @@ impl Default for TimerList {
 
 #[cfg(test)]
 mod tests {
-    use crate::{now, TimerList};
+    use crate::{get_timeout_time, now, TimerList};
+    use std::time::Duration;
 
     #[test]
     fn test() {
         println!("{}", now());
     }
 
+    #[test]
+    fn timeout_time_saturates() {
+        assert_eq!(u64::MAX, get_timeout_time(Duration::MAX));
+    }
+
     #[test]
     fn timer_list() {
         let mut list = TimerList::new();
@@ -136,6 +132,25 @@ mod tests {
 
         let mut entry = list.pop_front().unwrap();
         assert_eq!(entry.len(), 1);
-        assert!(entry.pop_front::<i32>().is_some());
+        assert_eq!(
+            Some(String::from("data can be everything")),
+            entry.pop_front()
+        );
+        assert!(entry.is_empty());
+    }
+
+    #[test]
+    fn timer_list_order() {
+        let mut list = TimerList::new();
+        list.insert(3, 3);
+        list.insert(1, 1);
+        list.insert(3, 4);
+        list.insert(2, 2);
+        assert_eq!(list.len(), 3);
+        for (time, len) in [(1, 1), (2, 1), (3, 2)] {
+            let entry = list.pop_front().unwrap();
+            assert_eq!(entry.get_time(), time);
+            assert_eq!(entry.len(), len);
+        }
     }
 }